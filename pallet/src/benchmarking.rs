@@ -1,7 +1,149 @@
 use super::*;
 
 use frame_benchmarking::v2::*;
+use frame_system::RawOrigin;
 use sp_std::vec;
+use xnft_primitives::traits::DerivativeWithdrawal;
+
+/// The helper to set up the NFT engine state for the benchmarks.
+///
+/// The benchmarks are generic over the NFT engine,
+/// so the integrators implement this trait for their engine
+/// to create classes and instances the benchmarks operate on.
+/// The created classes and instances must be usable by the [`NftTransactor`] of the engine,
+/// e.g., the xnft pallet account must be able to mint derivatives within a created class.
+pub trait BenchmarkHelper<ClassId, InstanceId, AccountId> {
+    /// Creates a new class owned by the `owner`.
+    fn create_class(owner: &AccountId) -> ClassId;
+
+    /// Mints a new class instance within the `class_id` class to the `owner` account.
+    fn mint_instance(class_id: &ClassId, owner: &AccountId) -> InstanceId;
+
+    /// Returns the dispatch error the most expensive to convert into the XCM error,
+    /// e.g., an error of the last pallet checked by the `DispatchErrorsConvert`.
+    fn worst_case_dispatch_error() -> DispatchError;
+
+    /// Makes the NFT engine withdraw the derivative instance as the `withdrawal` describes,
+    /// i.e., burn or stash it.
+    ///
+    /// An engine always withdrawing the derivatives the same way can leave it as is.
+    fn prepare_derivative_withdrawal(
+        _class_id: &ClassId,
+        _instance_id: &InstanceId,
+        _withdrawal: DerivativeWithdrawal,
+    ) {
+    }
+}
+
+fn deeply_nested_asset_id() -> AssetId {
+    AssetId::Concrete(MultiLocation {
+        parents: 1,
+        interior: X8(
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+            GeneralKey {
+                length: 32,
+                data: [0xff; 32],
+            },
+        ),
+    })
+}
+
+/// Registers a foreign asset backed by a new derivative class
+/// bypassing the registration call.
+fn register_benchmark_foreign_asset<T: Config>() -> (AssetId, ClassIdOf<T, ()>) {
+    let asset_id = deeply_nested_asset_id();
+    let class_id = T::BenchmarkHelper::create_class(&T::PalletAccountId::get());
+    let stored_class_id = <Pallet<T>>::stored_class_id(class_id.clone());
+
+    <ForeignAssetToLocalClass<T>>::insert(asset_id, &stored_class_id);
+    <LocalClassToForeignAsset<T>>::insert(&stored_class_id, asset_id);
+
+    (asset_id, class_id)
+}
+
+/// Resolves the asset instance of the benchmark foreign asset.
+fn benchmark_class_instance<T: Config>(
+    asset_id: &AssetId,
+    asset_instance: &AssetInstance,
+) -> Result<CategorizedClassInstanceOf<T, ()>, BenchmarkError> {
+    <Pallet<T>>::class_instance(asset_id, asset_instance)
+        .map_err(|_| BenchmarkError::Stop("failed to resolve the benchmark class instance"))
+}
+
+/// Registers the benchmark foreign asset and deposits its instance to the `owner` account.
+fn deposited_derivative<T: Config>(
+    owner: &NftEngineAccountIdOf<T, ()>,
+) -> Result<(AssetId, AssetInstance), BenchmarkError> {
+    let (asset_id, _) = register_benchmark_foreign_asset::<T>();
+    let asset_instance = AssetInstance::Index(0);
+
+    <Pallet<T>>::deposit_class_instance(
+        benchmark_class_instance::<T>(&asset_id, &asset_instance)?,
+        owner,
+        None,
+    )
+    .map_err(|_| BenchmarkError::Stop("failed to deposit the benchmark derivative"))?;
+
+    Ok((asset_id, asset_instance))
+}
+
+/// Prepares the engine to withdraw the active benchmark derivative as the `withdrawal` describes.
+fn prepare_derivative_withdrawal<T: Config>(
+    asset_id: &AssetId,
+    asset_instance: &AssetInstance,
+    withdrawal: DerivativeWithdrawal,
+) -> Result<(), BenchmarkError> {
+    let CategorizedClassInstance::Derivative {
+        derivative: derivative_status,
+        ..
+    } = benchmark_class_instance::<T>(asset_id, asset_instance)?
+    else {
+        return Err(BenchmarkError::Stop(
+            "the benchmark asset isn't a derivative",
+        ));
+    };
+
+    let DerivativeStatus::Active(instance_id) = derivative_status.instance_id else {
+        return Err(BenchmarkError::Stop(
+            "the benchmark derivative isn't active",
+        ));
+    };
+
+    T::BenchmarkHelper::prepare_derivative_withdrawal(
+        &derivative_status.class_id,
+        &instance_id,
+        withdrawal,
+    );
+
+    Ok(())
+}
 
 #[benchmarks]
 pub mod benchmarks {
@@ -9,46 +151,10 @@ pub mod benchmarks {
 
     #[benchmark]
     pub fn foreign_asset_registration_checks() -> Result<(), BenchmarkError> {
-        let asset_id = AssetId::Concrete(MultiLocation {
-            parents: 1,
-            interior: X8(
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-                GeneralKey {
-                    length: 32,
-                    data: [0xff; 32],
-                },
-            ),
-        });
+        let asset_id = deeply_nested_asset_id();
         let versioned_asset_id = VersionedAssetId::V3(asset_id);
 
-        let origin = T::RegisterOrigin::try_successful_origin(&asset_id).unwrap();
+        let origin = T::ForeignAssetRegisterOrigin::try_successful_origin(&asset_id).unwrap();
 
         #[block]
         {
@@ -57,4 +163,302 @@ pub mod benchmarks {
 
         Ok(())
     }
+
+    #[benchmark]
+    pub fn class_instance_lookup() -> Result<(), BenchmarkError> {
+        let asset_id = deeply_nested_asset_id();
+        let asset_instance = AssetInstance::Array32([0xff; 32]);
+
+        #[block]
+        {
+            let _ = <Pallet<T>>::class_instance(&asset_id, &asset_instance);
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn dispatch_error_conversion() -> Result<(), BenchmarkError> {
+        let error = T::BenchmarkHelper::worst_case_dispatch_error();
+
+        #[block]
+        {
+            let _ = T::DispatchErrorsConvert::convert(error);
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn deposit_derivative_new() -> Result<(), BenchmarkError> {
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let (asset_id, _) = register_benchmark_foreign_asset::<T>();
+        let class_instance = benchmark_class_instance::<T>(&asset_id, &AssetInstance::Index(0))?;
+
+        #[block]
+        {
+            <Pallet<T>>::deposit_class_instance(class_instance, &beneficiary, None)
+                .map_err(|_| BenchmarkError::Stop("failed to deposit the derivative"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn deposit_derivative_stashed() -> Result<(), BenchmarkError> {
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let (asset_id, class_id) = register_benchmark_foreign_asset::<T>();
+        let asset_instance = AssetInstance::Index(0);
+
+        let instance_id = T::BenchmarkHelper::mint_instance(
+            &class_id,
+            &<Pallet<T>>::class_custody_account(&class_id),
+        );
+
+        <ForeignInstanceToDerivativeStatus<T>>::insert(
+            &class_id,
+            asset_instance,
+            DerivativeStatus::Stashed(instance_id.clone()),
+        );
+        <DerivativeToForeignInstance<T>>::insert(&class_id, &instance_id, asset_instance);
+        <DerivativeCountPerClass<T>>::insert(&class_id, 1);
+        <StashedCountPerClass<T>>::insert(&class_id, 1);
+
+        let class_instance = benchmark_class_instance::<T>(&asset_id, &asset_instance)?;
+
+        #[block]
+        {
+            <Pallet<T>>::deposit_class_instance(class_instance, &beneficiary, None)
+                .map_err(|_| BenchmarkError::Stop("failed to deposit the derivative"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn withdraw_derivative_burned() -> Result<(), BenchmarkError> {
+        let owner: NftEngineAccountIdOf<T, ()> = account("owner", 0, 0);
+        let (asset_id, asset_instance) = deposited_derivative::<T>(&owner)?;
+
+        prepare_derivative_withdrawal::<T>(
+            &asset_id,
+            &asset_instance,
+            DerivativeWithdrawal::Burned,
+        )?;
+
+        let class_instance = benchmark_class_instance::<T>(&asset_id, &asset_instance)?;
+
+        #[block]
+        {
+            <Pallet<T>>::withdraw_class_instance(class_instance, &owner)
+                .map_err(|_| BenchmarkError::Stop("failed to withdraw the derivative"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn withdraw_derivative_stashed() -> Result<(), BenchmarkError> {
+        let owner: NftEngineAccountIdOf<T, ()> = account("owner", 0, 0);
+        let (asset_id, asset_instance) = deposited_derivative::<T>(&owner)?;
+
+        prepare_derivative_withdrawal::<T>(
+            &asset_id,
+            &asset_instance,
+            DerivativeWithdrawal::Stash,
+        )?;
+
+        let class_instance = benchmark_class_instance::<T>(&asset_id, &asset_instance)?;
+
+        #[block]
+        {
+            <Pallet<T>>::withdraw_class_instance(class_instance, &owner)
+                .map_err(|_| BenchmarkError::Stop("failed to withdraw the derivative"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn transfer_derivative() -> Result<(), BenchmarkError> {
+        let owner: NftEngineAccountIdOf<T, ()> = account("owner", 0, 0);
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let (asset_id, asset_instance) = deposited_derivative::<T>(&owner)?;
+        let class_instance = benchmark_class_instance::<T>(&asset_id, &asset_instance)?;
+
+        #[block]
+        {
+            <Pallet<T>>::transfer_class_instance(class_instance, &owner, &beneficiary)
+                .map_err(|_| BenchmarkError::Stop("failed to transfer the derivative"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn deposit_local() -> Result<(), BenchmarkError> {
+        let owner: NftEngineAccountIdOf<T, ()> = account("owner", 0, 0);
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let class_id = T::BenchmarkHelper::create_class(&owner);
+        let instance_id = T::BenchmarkHelper::mint_instance(
+            &class_id,
+            &<Pallet<T>>::class_custody_account(&class_id),
+        );
+
+        #[block]
+        {
+            <Pallet<T>>::deposit_class_instance(
+                CategorizedClassInstance::local(class_id, instance_id),
+                &beneficiary,
+                None,
+            )
+            .map_err(|_| BenchmarkError::Stop("failed to deposit the local class instance"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn withdraw_local() -> Result<(), BenchmarkError> {
+        let owner: NftEngineAccountIdOf<T, ()> = account("owner", 0, 0);
+        let class_id = T::BenchmarkHelper::create_class(&owner);
+        let instance_id = T::BenchmarkHelper::mint_instance(&class_id, &owner);
+
+        #[block]
+        {
+            <Pallet<T>>::withdraw_class_instance(
+                CategorizedClassInstance::local(class_id, instance_id),
+                &owner,
+            )
+            .map_err(|_| BenchmarkError::Stop("failed to withdraw the local class instance"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn transfer_local() -> Result<(), BenchmarkError> {
+        let owner: NftEngineAccountIdOf<T, ()> = account("owner", 0, 0);
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let class_id = T::BenchmarkHelper::create_class(&owner);
+        let instance_id = T::BenchmarkHelper::mint_instance(&class_id, &owner);
+
+        #[block]
+        {
+            <Pallet<T>>::transfer_class_instance(
+                CategorizedClassInstance::local(class_id, instance_id),
+                &owner,
+                &beneficiary,
+            )
+            .map_err(|_| BenchmarkError::Stop("failed to transfer the local class instance"))?;
+        }
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn bulk_withdraw_to_custody(
+        n: Linear<1, { T::MaxBulkWithdraw::get() }>,
+    ) -> Result<(), BenchmarkError> {
+        let origin = T::BulkWithdrawOrigin::try_successful_origin()
+            .map_err(|_| BenchmarkError::Weightless)?;
+        let owner = T::BulkWithdrawOrigin::try_origin(origin.clone())
+            .map_err(|_| BenchmarkError::Stop("the successful origin is rejected"))?;
+
+        let (asset_id, _) = register_benchmark_foreign_asset::<T>();
+        let mut class_instances = BoundedVec::new();
+
+        // The stashed withdrawal is the costliest one: the derivative is moved to the custody.
+        for index in 0..n {
+            let asset_instance = AssetInstance::Index(index.into());
+
+            <Pallet<T>>::deposit_class_instance(
+                benchmark_class_instance::<T>(&asset_id, &asset_instance)?,
+                &owner,
+                None,
+            )
+            .map_err(|_| BenchmarkError::Stop("failed to deposit the benchmark derivative"))?;
+
+            prepare_derivative_withdrawal::<T>(
+                &asset_id,
+                &asset_instance,
+                DerivativeWithdrawal::Stash,
+            )?;
+
+            let CategorizedClassInstance::Derivative {
+                derivative: derivative_status,
+                ..
+            } = benchmark_class_instance::<T>(&asset_id, &asset_instance)?
+            else {
+                return Err(BenchmarkError::Stop(
+                    "the benchmark asset isn't a derivative",
+                ));
+            };
+
+            let DerivativeStatus::Active(instance_id) = derivative_status.instance_id else {
+                return Err(BenchmarkError::Stop(
+                    "the benchmark derivative isn't active",
+                ));
+            };
+
+            class_instances
+                .try_push((derivative_status.class_id, instance_id))
+                .map_err(|_| BenchmarkError::Stop("too many benchmark class instances"))?;
+        }
+
+        #[extrinsic_call]
+        _(origin as T::RuntimeOrigin, class_instances);
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn force_withdraw_stashed() -> Result<(), BenchmarkError> {
+        let origin = T::ForeignAssetAdminOrigin::try_successful_origin()
+            .map_err(|_| BenchmarkError::Weightless)?;
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let (_, class_id) = register_benchmark_foreign_asset::<T>();
+        let asset_instance = AssetInstance::Index(0);
+
+        let instance_id = T::BenchmarkHelper::mint_instance(
+            &class_id,
+            &<Pallet<T>>::class_custody_account(&class_id),
+        );
+
+        <ForeignInstanceToDerivativeStatus<T>>::insert(
+            &class_id,
+            asset_instance,
+            DerivativeStatus::Stashed(instance_id.clone()),
+        );
+        <DerivativeToForeignInstance<T>>::insert(&class_id, &instance_id, asset_instance);
+        <DerivativeCountPerClass<T>>::insert(&class_id, 1);
+        <StashedCountPerClass<T>>::insert(&class_id, 1);
+
+        #[extrinsic_call]
+        _(
+            origin as T::RuntimeOrigin,
+            class_id,
+            asset_instance,
+            beneficiary,
+        );
+
+        Ok(())
+    }
+
+    #[benchmark]
+    pub fn retry_deposit() -> Result<(), BenchmarkError> {
+        let caller: T::AccountId = whitelisted_caller();
+        let beneficiary: NftEngineAccountIdOf<T, ()> = account("beneficiary", 0, 0);
+        let (_, class_id) = register_benchmark_foreign_asset::<T>();
+        let asset_instance = AssetInstance::Index(0);
+
+        <PendingDeposits<T>>::insert(&class_id, asset_instance, &beneficiary);
+
+        #[extrinsic_call]
+        _(RawOrigin::Signed(caller), class_id, asset_instance);
+
+        Ok(())
+    }
+
+    impl_benchmark_test_suite!(Pallet, crate::mock::new_test_ext(), crate::mock::Test);
 }
@@ -0,0 +1,155 @@
+//! The XCM execution fees handling.
+//!
+//! The xnft pallet never treats an NFT as a fungible asset,
+//! so an NFT can't be used to pay for the XCM execution.
+//! The executor buys the execution weight via its `WeightTrader`, not via the asset transactor,
+//! so a trader accepting any asset could consume an NFT as a fee payment.
+//! The [`RejectNftFees`] wrapper prevents that.
+//!
+//! The executor charges the message sender only for the weight its `Weigher` computes,
+//! so the work of the pallet on the NFTs must be a part of that weight.
+//! The [`XnftAssetWeight`] computes it for the runtime's `XcmWeightInfo`.
+
+use frame_support::{traits::Get, weights::Weight};
+use sp_std::{marker::PhantomData, mem};
+use xcm::v3::{prelude::*, Error as XcmError, XcmContext};
+use xcm_executor::{traits::WeightTrader, Assets};
+
+use crate::{weights::WeightInfo, Config, Pallet, XnftOp};
+
+/// The [`WeightTrader`] wrapper that never uses the non-fungible assets as a fee payment.
+///
+/// The non-fungible assets are excluded from the payment passed to the inner `Trader`
+/// and are returned as unused.
+/// If the payment consists of non-fungible assets only,
+/// the weight purchase fails with the [`TooExpensive`](XcmError::TooExpensive) error.
+pub struct RejectNftFees<Trader>(Trader);
+
+impl<Trader: WeightTrader> WeightTrader for RejectNftFees<Trader> {
+    fn new() -> Self {
+        Self(Trader::new())
+    }
+
+    fn buy_weight(
+        &mut self,
+        weight: Weight,
+        mut payment: Assets,
+        context: &XcmContext,
+    ) -> Result<Assets, XcmError> {
+        let non_fungible = mem::take(&mut payment.non_fungible);
+
+        if payment.fungible.is_empty() && !non_fungible.is_empty() {
+            log::debug!(
+                target: "xcm::xnft::fees",
+                "buy_weight: an NFT can't be used as a fee payment: {non_fungible:?}",
+            );
+
+            return Err(XcmError::TooExpensive);
+        }
+
+        let mut unused = self.0.buy_weight(weight, payment, context)?;
+        unused.non_fungible.extend(non_fungible);
+
+        Ok(unused)
+    }
+
+    fn refund_weight(&mut self, weight: Weight, context: &XcmContext) -> Option<MultiAsset> {
+        self.0.refund_weight(weight, context)
+    }
+}
+
+/// The weight of the pallet's work on the NFTs the XCM executor doesn't account for.
+///
+/// The runtime's `XcmWeightInfo` adds it to the weight of the instructions
+/// transacting the assets (e.g., `WithdrawAsset`, `DepositAsset`, and `TransferAsset`),
+/// so the message sender pays for it before the execution.
+/// Only the non-fungible assets are weighed, the other assets are left to their transactors.
+/// Each NFT is weighed as the costliest path of the operation,
+/// since the path depends on the state at the execution time.
+/// A wildcard not limiting the number of the assets is weighed as `MaxAssets` NFTs.
+pub struct XnftAssetWeight<T, MaxAssets, I = ()>(PhantomData<(T, MaxAssets, I)>);
+
+impl<T: Config<I>, MaxAssets: Get<u32>, I: 'static> XnftAssetWeight<T, MaxAssets, I> {
+    /// Returns the weight of depositing the NFTs matching the `assets` filter.
+    pub fn deposit_asset(assets: &MultiAssetFilter) -> Weight {
+        let deposit = Pallet::<T, I>::op_weight(XnftOp::Deposit {
+            reactivation: false,
+        })
+        .max(Pallet::<T, I>::op_weight(XnftOp::Deposit {
+            reactivation: true,
+        }));
+
+        Self::per_nft(deposit).saturating_mul(Self::nft_count(assets).into())
+    }
+
+    /// Returns the weight of withdrawing the NFTs among the `assets`.
+    ///
+    /// An `Undefined` instance withdraws the whole derivative class
+    /// if the `ClassLevelTransfer` is enabled,
+    /// so it is weighed as the `MaxClassLevelWithdrawal` derivative withdrawals.
+    pub fn withdraw_asset(assets: &MultiAssets) -> Weight {
+        let withdrawal = Pallet::<T, I>::op_weight(XnftOp::Withdraw { stash: false })
+            .max(Pallet::<T, I>::op_weight(XnftOp::Withdraw { stash: true }));
+
+        let class_withdrawal = if T::ClassLevelTransfer::get() {
+            withdrawal.saturating_mul(T::MaxClassLevelWithdrawal::get().into())
+        } else {
+            withdrawal
+        };
+
+        assets
+            .inner()
+            .iter()
+            .map(|asset| match asset.fun {
+                NonFungible(AssetInstance::Undefined) => Self::per_nft(class_withdrawal),
+                NonFungible(_) => Self::per_nft(withdrawal),
+                Fungible(_) => Weight::zero(),
+            })
+            .fold(Weight::zero(), Weight::saturating_add)
+    }
+
+    /// Returns the weight of transferring the NFTs among the `assets`.
+    pub fn transfer_asset(assets: &MultiAssets) -> Weight {
+        let transfer = Pallet::<T, I>::op_weight(XnftOp::Transfer);
+
+        Self::per_nft(transfer).saturating_mul(Self::definite_nft_count(assets).into())
+    }
+
+    /// Returns the weight of the `operation` on a single NFT.
+    ///
+    /// Each NFT is looked up first,
+    /// and the operation may fail with an NFT engine error converted into the XCM error.
+    fn per_nft(operation: Weight) -> Weight {
+        T::WeightInfo::class_instance_lookup()
+            .saturating_add(T::WeightInfo::dispatch_error_conversion())
+            .saturating_add(operation)
+    }
+
+    /// Returns the maximum number of the NFTs matching the `assets` filter.
+    fn nft_count(assets: &MultiAssetFilter) -> u32 {
+        match assets {
+            Definite(assets) => Self::definite_nft_count(assets),
+            Wild(AllOf {
+                fun: WildFungibility::Fungible,
+                ..
+            })
+            | Wild(AllOfCounted {
+                fun: WildFungibility::Fungible,
+                ..
+            }) => 0,
+            Wild(AllCounted(count)) | Wild(AllOfCounted { count, .. }) => {
+                (*count).min(MaxAssets::get())
+            }
+            Wild(All) | Wild(AllOf { .. }) => MaxAssets::get(),
+        }
+    }
+
+    /// Returns the number of the NFTs among the `assets`.
+    fn definite_nft_count(assets: &MultiAssets) -> u32 {
+        assets
+            .inner()
+            .iter()
+            .filter(|asset| matches!(asset.fun, NonFungible(_)))
+            .count() as u32
+    }
+}
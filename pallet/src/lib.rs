@@ -4,28 +4,52 @@
 //! The xnft pallet is a generalized NFT XCM Asset Transactor.
 //! It can be integrated into any Substrate chain implementing the [`NftEngine`] trait.
 
-use frame_support::{ensure, pallet_prelude::*, traits::EnsureOriginWithArg};
+use frame_support::{
+    ensure,
+    pallet_prelude::*,
+    traits::{Contains, EnsureOriginWithArg, OriginTrait},
+    PalletId,
+};
 use frame_system::pallet_prelude::*;
-use sp_runtime::{traits::MaybeEquivalence, DispatchResult};
-use sp_std::boxed::Box;
-use xcm::{
-    v3::prelude::{AssetId as XcmAssetId, AssetInstance as XcmAssetInstance, *},
-    VersionedAssetId,
+use sp_runtime::{
+    traits::{
+        AccountIdConversion, Convert, ConvertBack, Hash, MaybeEquivalence, TrailingZeroInput,
+    },
+    DispatchResult, Saturating,
 };
-use xcm_executor::traits::{ConvertLocation, Error as XcmExecutorError};
-use xnft_primitives::traits::{DispatchErrorsConvert, NftEngine, NftTransactor};
+use sp_std::{boxed::Box, vec::Vec};
+use xcm::{v3::prelude::*, VersionedAssetId};
+use xcm_executor::traits::{ConvertLocation, Error as XcmExecutorError, TransactAsset};
+use xnft_primitives::traits::{DispatchErrorsConvert, NftEngine, NftTransactor, Royalty};
+
+use self::{StoredAssetId as XcmAssetId, StoredAssetInstance as XcmAssetInstance};
 
 pub use pallet::*;
+pub use xnft_primitives::traits::ForeignAssetInstance;
 
 #[allow(missing_docs)]
 pub mod weights;
 
 mod transact_asset;
 
+pub mod fees;
+
+pub mod reserve;
+
+pub mod migrations;
+
+pub mod runtime_api;
+
 #[cfg(feature = "runtime-benchmarks")]
 #[allow(missing_docs)]
 pub mod benchmarking;
 
+#[cfg(test)]
+mod mock;
+
+#[cfg(test)]
+mod tests;
+
 type NftEngineOf<T, I> = <T as Config<I>>::NftEngine;
 type NftTransactorOf<T, I> = <NftEngineOf<T, I> as NftEngine>::Transactor;
 type NftEngineAccountIdOf<T, I> = <NftTransactorOf<T, I> as NftTransactor>::AccountId;
@@ -56,12 +80,39 @@ pub mod pallet {
         /// The xnft pallet account ID.
         type PalletAccountId: Get<NftEngineAccountIdOf<Self, I>>;
 
+        /// Whether each class has its own custody account for the stashed derivatives
+        /// and the withdrawn local class instances instead of the `PalletAccountId`.
+        ///
+        /// See [`Pallet::class_custody_account`].
+        #[pallet::constant]
+        type PerClassCustody: Get<bool>;
+
+        /// The pallet ID from which the per-class custody accounts are derived.
+        ///
+        /// It is used only when the `PerClassCustody` is enabled.
+        #[pallet::constant]
+        type PalletId: Get<PalletId>;
+
         /// Defines the reserve location for a local class.
         /// A local class is a class originally created on this chain
         /// (i.e., it doesn't correspond to a foreign asset).
         type LocalAssetIdConvert: MaybeEquivalence<InteriorMultiLocation, ClassIdOf<Self, I>>;
 
         /// Converts the XCM asset instance into the NFT engine's instance ID.
+        ///
+        /// Every instance ID must be representable as an XCM asset instance:
+        ///
+        /// | Instance ID width  | `AssetInstance` variant |
+        /// |--------------------|-------------------------|
+        /// | up to 128 bits     | `Index`                 |
+        /// | 4 bytes            | `Array4`                |
+        /// | 8 bytes            | `Array8`                |
+        /// | 16 bytes           | `Array16`               |
+        /// | 32 bytes           | `Array32`               |
+        ///
+        /// Wider instance IDs can't be represented. The `integrity_test` checks
+        /// that the instance ID encoding fits an `AssetInstance`
+        /// and that a sample instance ID, if representable, round-trips through the converter.
         type AssetInstanceConvert: MaybeEquivalence<XcmAssetInstance, InstanceIdOf<Self, I>>;
 
         /// The chain's Universal Location.
@@ -70,9 +121,56 @@ pub mod pallet {
         /// A converter from a multilocation to the chain's account ID.
         type LocationToAccountId: ConvertLocation<NftEngineAccountIdOf<Self, I>>;
 
+        /// Whether to accept the accounts specified with an explicit network
+        /// matching this chain's network when the `LocationToAccountId` fails to convert them.
+        ///
+        /// The network is taken from the `UniversalLocation`.
+        /// The accounts of other networks are never accepted
+        /// to avoid confusing them with the accounts of this chain.
+        #[pallet::constant]
+        type AcceptForeignNetworkAccounts: Get<bool>;
+
+        /// The account receiving the deposits whose beneficiary location can't be converted
+        /// into an account.
+        ///
+        /// `None` makes such deposits fail.
+        type UnconvertibleDestinationEscrow: Get<Option<NftEngineAccountIdOf<Self, I>>>;
+
+        /// The `(XCM origin, asset ID)` pairs whose deposits and withdrawals are teleports.
+        ///
+        /// The same collection can be teleported from a trusted chain
+        /// and reserve-transferred from others, so the choice is made per message.
+        /// A teleport additionally passes the check-in (on deposit) or check-out (on withdrawal)
+        /// accounting of the pallet's custody, see the `can_check_in` and `can_check_out`.
+        /// Use `Nothing` to treat all the messages as reserve transfers.
+        type DynamicTrustModel: Contains<(MultiLocation, XcmAssetId)>;
+
+        /// The filter of the XCM context consulted before any asset is deposited,
+        /// withdrawn, or transferred.
+        ///
+        /// It is the central point to reject the NFT operations from untrusted origins.
+        /// Use `()` to allow all the operations.
+        type XcmContextFilter: XcmContextFilter;
+
+        /// The junctions allowed in the interior location of a registered foreign asset.
+        ///
+        /// Rejecting the oddly-shaped locations prevents aliasing and converter ambiguity.
+        /// See [`SupportedInteriorJunctions`](xnft_primitives::conversion::SupportedInteriorJunctions)
+        /// for the junctions the provided converters support.
+        type AllowedInteriorJunctions: Contains<Junction>;
+
         /// An origin allowed to register foreign NFT assets.
         type ForeignAssetRegisterOrigin: EnsureOriginWithArg<Self::RuntimeOrigin, XcmAssetId>;
 
+        /// An origin allowed to manage the registered foreign assets (e.g., the governance).
+        type ForeignAssetAdminOrigin: EnsureOrigin<Self::RuntimeOrigin>;
+
+        /// The conversion of a signer account into the NFT engine's account.
+        ///
+        /// It is used to check the class ownership of a signed origin,
+        /// see [`Pallet::register_foreign_asset_for_existing_class`].
+        type SignerToNftEngineAccount: Convert<Self::AccountId, NftEngineAccountIdOf<Self, I>>;
+
         /// Pallet dispatch errors that are convertible to XCM errors.
         ///
         /// This type allows the xnft pallet to decode certain pallet errors into proper XCM errors.
@@ -80,6 +178,244 @@ pub mod pallet {
         /// The [`FailedToTransactAsset`](XcmError::FailedToTransactAsset) is a fallback
         /// when the dispatch error can't be decoded into any of the specified dispatch error types.
         type DispatchErrorsConvert: DispatchErrorsConvert<Self>;
+
+        /// The minimum number of parents a foreign asset location must have to be registered.
+        ///
+        /// The location is checked after it is simplified relative to the `UniversalLocation`,
+        /// so an asset expressed via a path going through this chain is checked in its shortest form.
+        /// The usual value is `1`. A bridge hub might use `2` to register only the assets
+        /// located beyond the relay chain.
+        #[pallet::constant]
+        type MinForeignParents: Get<u8>;
+
+        /// An origin allowed to withdraw its own class instances into the xnft custody.
+        ///
+        /// The origin's success value is the account from which the instances are withdrawn.
+        type BulkWithdrawOrigin: EnsureOrigin<
+            Self::RuntimeOrigin,
+            Success = NftEngineAccountIdOf<Self, I>,
+        >;
+
+        /// The maximum number of class instances withdrawn by a single `bulk_withdraw_to_custody` call.
+        #[pallet::constant]
+        type MaxBulkWithdraw: Get<u32>;
+
+        /// The maximum number of chains a derivative class can be additionally moved to.
+        #[pallet::constant]
+        type MaxWithdrawalDestinations: Get<u32>;
+
+        /// Transforms the derivative class ID returned by the NFT engine
+        /// into the form stored in the foreign asset mappings.
+        ///
+        /// The transformation MUST be a bijection:
+        /// the stored class ID is converted back every time the pallet reads it,
+        /// so the NFT engine always receives the class ID it has created.
+        ///
+        /// Use [`Identity`](sp_runtime::traits::Identity) to store the class IDs as is.
+        type DerivativeClassIdTransform: ConvertBack<ClassIdOf<Self, I>, ClassIdOf<Self, I>>;
+
+        /// Whether to treat a repeated deposit of the same asset instance
+        /// by the same XCM message as a successful no-op.
+        ///
+        /// A message is identified by its topic (i.e., the `SetTopic` ID),
+        /// so the deposits made by the messages without the topic aren't deduplicated.
+        /// When enabled, every successful deposit made by a message with the topic is recorded
+        /// for the `ProcessedDepositTtl` number of blocks.
+        /// The record is written within the deposit transaction,
+        /// so a failed deposit (including the one delegated to the `FallbackTransactor`)
+        /// isn't recorded.
+        /// The records are removed in `on_idle` once they expire,
+        /// so the storage grows proportionally to the number of deposits made within the TTL.
+        #[pallet::constant]
+        type DeduplicateByMessageId: Get<bool>;
+
+        /// The number of blocks during which a processed deposit is remembered.
+        #[pallet::constant]
+        type ProcessedDepositTtl: Get<BlockNumberFor<Self>>;
+
+        /// Whether the pallet only observes the XCM NFT flows.
+        ///
+        /// In the observer mode, the pallet categorizes every deposit, withdrawal, and transfer
+        /// and emits the [`Event::ObservedFlow`], but it doesn't call the NFT engine
+        /// and doesn't modify its own mappings. The operations are reported as successful.
+        #[pallet::constant]
+        type ObserverMode: Get<bool>;
+
+        /// The number of blocks during which a deregistered foreign asset can't be registered again.
+        ///
+        /// This protects the in-flight XCM messages referencing the old mapping.
+        #[pallet::constant]
+        type ReregistrationCooldown: Get<BlockNumberFor<Self>>;
+
+        /// The maximum number of foreign asset registrations a single origin can make
+        /// within the `RegistrationWindow`.
+        ///
+        /// `None` disables the rate limit. The root origin is never rate-limited.
+        #[pallet::constant]
+        type MaxRegistrationsPerWindow: Get<Option<u32>>;
+
+        /// The maximum number of derivatives within a single derivative class.
+        ///
+        /// A deposit requiring a new derivative mint in a full class fails.
+        /// `None` disables the cap.
+        #[pallet::constant]
+        type MaxDerivativesPerClass: Get<Option<u32>>;
+
+        /// Whether a failed derivative mint is recorded in the `PendingDeposits`
+        /// instead of failing the deposit.
+        ///
+        /// The recorded deposit succeeds, so the foreign asset instance isn't trapped
+        /// on the source chain, and the mint can be retried via the `retry_deposit` call.
+        #[pallet::constant]
+        type RecordFailedMints: Get<bool>;
+
+        /// The number of blocks of the sliding window within which the registrations are counted.
+        #[pallet::constant]
+        type RegistrationWindow: Get<BlockNumberFor<Self>>;
+
+        /// Whether a foreign asset can be split into several derivative classes (shards)
+        /// by the foreign asset instance.
+        #[pallet::constant]
+        type InstanceSharding: Get<bool>;
+
+        /// Maps a foreign asset instance to the shard of its derivative class.
+        ///
+        /// Used only when the `InstanceSharding` is enabled.
+        /// See [`IndexRangeRouter`](xnft_primitives::conversion::IndexRangeRouter)
+        /// for the range-based routing.
+        type InstanceRangeRouter: Convert<XcmAssetInstance, Option<u32>>;
+
+        /// Whether the `Undefined` asset instance of a foreign asset
+        /// denotes its whole derivative class.
+        ///
+        /// When enabled, withdrawing the `Undefined` instance withdraws
+        /// all the active derivatives of the class and marks the class as dormant.
+        /// When disabled, the `Undefined` instance is rejected.
+        #[pallet::constant]
+        type ClassLevelTransfer: Get<bool>;
+
+        /// The maximum number of active derivatives withdrawn by a class-level withdrawal.
+        #[pallet::constant]
+        type MaxClassLevelWithdrawal: Get<u32>;
+
+        /// The maximum number of stashed derivatives purged by a foreign asset deregistration.
+        #[pallet::constant]
+        type MaxDeregistrationDerivatives: Get<u32>;
+
+        /// The maximum length of the derivative class metadata given at the registration
+        /// and of the derivative metadata carried by a deposit (see the `MetadataSource`).
+        #[pallet::constant]
+        type MetadataLimit: Get<u32>;
+
+        /// What happens when a local class instance that doesn't exist is deposited.
+        ///
+        /// See [`LocalDepositMode`].
+        #[pallet::constant]
+        type LocalDepositMode: Get<LocalDepositMode>;
+
+        /// The source of the royalty of the incoming foreign asset instances.
+        ///
+        /// The royalty is set on a freshly minted derivative
+        /// if the bridge carried it within the asset or the XCM context.
+        /// Use `()` to mint the derivatives without a royalty.
+        type RoyaltySource: RoyaltySource<NftEngineAccountIdOf<Self, I>>;
+
+        /// The source of the metadata of the incoming foreign asset instances.
+        ///
+        /// The metadata is passed to the [`NftTransactor::mint_derivative_full`]
+        /// when a fresh derivative is minted.
+        /// A metadata longer than the `MetadataLimit` is ignored.
+        /// Use `()` to mint the derivatives without metadata.
+        type MetadataSource: MetadataSource;
+
+        /// The hook invoked right after a derivative is minted.
+        ///
+        /// It can, e.g., reserve a storage deposit from the pallet account or record analytics.
+        /// A failure of the hook rolls back the mint.
+        /// Use `()` to disable the hook.
+        type OnDerivativeMinted: OnDerivativeMinted<
+            ClassIdOf<Self, I>,
+            InstanceIdOf<Self, I>,
+            NftEngineAccountIdOf<Self, I>,
+        >;
+
+        /// The asset transactor to which the recognized NFTs are delegated
+        /// when the pallet fails to process them because their class isn't operational
+        /// (e.g., it is frozen), see [`NftEngine::is_class_operational`].
+        ///
+        /// Any other failure is returned as is.
+        ///
+        /// The pallet's own error is returned if the fallback transactor fails too.
+        /// Use `()` to disable the fallback.
+        type FallbackTransactor: TransactAsset;
+
+        /// Whether the derivative-to-foreign-instance mapping is computed on demand
+        /// instead of being stored in the `DerivativeToForeignInstance`.
+        ///
+        /// If enabled, the NFT engine MUST mint each derivative with the instance ID
+        /// the `DerivativeInstanceConvert` computes from the foreign asset instance.
+        /// The computed instance ID is passed to the engine as the instance ID hint,
+        /// see [`NftTransactor::mint_derivative_full`].
+        /// A mint producing any other instance ID fails.
+        #[pallet::constant]
+        type DeriveReverseMapping: Get<bool>;
+
+        /// The bijection between the foreign asset instances and the derivative instance IDs.
+        ///
+        /// Used only if the `DeriveReverseMapping` is enabled.
+        type DerivativeInstanceConvert: MaybeEquivalence<XcmAssetInstance, InstanceIdOf<Self, I>>;
+
+        /// Whether the instance ID of a burned derivative is retained
+        /// and passed to the engine as the instance ID hint when the foreign instance returns.
+        ///
+        /// It lets an engine burning the derivatives on withdrawal re-mint the same instance ID,
+        /// so the off-chain references to the derivative stay valid.
+        /// See the `RetainedDerivativeIds`.
+        #[pallet::constant]
+        type PreserveIdAcrossBurn: Get<bool>;
+
+        /// Whether an operator approved by the owner can withdraw the owner's class instance.
+        ///
+        /// If enabled, the withdrawal by an approved operator takes the class instance
+        /// from its owner, see [`NftTransactor::is_approved_or_owner`].
+        /// Otherwise, only the owner can withdraw the class instance.
+        #[pallet::constant]
+        type AllowApprovedOperators: Get<bool>;
+
+        /// Whether the foreign asset registrations are paused together with the XCM operations.
+        #[pallet::constant]
+        type PauseRegistrations: Get<bool>;
+
+        /// Whether only the `Index` foreign asset instances can get derivatives.
+        ///
+        /// The foreign asset instance is a part of the `ForeignInstanceToDerivativeStatus` key.
+        /// An `Index` instance takes up to 17 bytes of the key (a compact `u128`),
+        /// while an `Array32` one takes 33 bytes.
+        /// Enable it on chains that only ever receive `Index` instances
+        /// to keep the storage compact: no derivative is minted for an array-form instance.
+        #[pallet::constant]
+        type IndexOnlyForeignInstances: Get<bool>;
+
+        /// Whether the per-instance events (`Deposited`, `Withdrawn`, `Transferred`,
+        /// `DerivativeBurned`, and `DerivativeStashed`) are emitted by default.
+        ///
+        /// A derivative class can override it at the registration,
+        /// see the `ClassEventEmission`.
+        #[pallet::constant]
+        type EmitInstanceEvents: Get<bool>;
+
+        /// Whether the `BlockFlowSummary` event summarizing the block's xnft activity
+        /// is emitted at the end of each block with any activity.
+        #[pallet::constant]
+        type EmitBlockSummary: Get<bool>;
+
+        /// The helper to set up the NFT engine state for the benchmarks.
+        #[cfg(feature = "runtime-benchmarks")]
+        type BenchmarkHelper: benchmarking::BenchmarkHelper<
+            ClassIdOf<Self, I>,
+            InstanceIdOf<Self, I>,
+            NftEngineAccountIdOf<Self, I>,
+        >;
     }
 
     /// XNFT errors.
@@ -91,8 +427,68 @@ pub mod pallet {
         /// Is it impossible to register a local asset as a foreign one.
         AttemptToRegisterLocalAsset,
 
-        /// The given asset ID could not be converted into the current XCM version.
-        BadAssetId,
+        /// The foreign asset location contains a junction not allowed by the `AllowedInteriorJunctions`.
+        DisallowedJunction,
+
+        /// The foreign asset isn't registered as a whole.
+        ForeignAssetNotRegistered,
+
+        /// The derivative class still has active derivatives or pending deposits.
+        DerivativesStillExist,
+
+        /// The derivative class has more stashed derivatives than can be purged at once.
+        TooManyDerivatives,
+
+        /// The foreign asset location has fewer parents than the configured minimum.
+        ReserveTooClose,
+
+        /// The pallet is paused.
+        Paused,
+
+        /// The foreign asset was deregistered recently and can't be registered again yet.
+        ReregistrationTooSoon,
+
+        /// The origin has made too many registrations within the registration window.
+        RegistrationRateLimited,
+
+        /// The instance sharding is disabled.
+        ShardingDisabled,
+
+        /// The given derivative class instance doesn't correspond to any foreign asset instance.
+        UnknownDerivative,
+
+        /// The class instance can't be withdrawn into the custody
+        /// (e.g., the derivative isn't active).
+        WithdrawalFailed,
+
+        /// The foreign asset instance has no stashed derivative.
+        DerivativeNotStashed,
+
+        /// The foreign asset instance has no pending deposit.
+        NoPendingDeposit,
+
+        /// The pending deposit can't be completed yet.
+        DepositRetryFailed,
+
+        /// The class is already bound to a foreign asset.
+        ClassAlreadyBound,
+
+        /// The class doesn't exist.
+        ClassNotFound,
+
+        /// The class instance isn't owned by the account.
+        NotInstanceOwner,
+
+        /// The class already has instances (e.g., the exported local NFTs kept in the custody).
+        ClassNotEmpty,
+
+        /// The given asset ID's XCM version can't be converted into the stored one
+        /// (see [`StoredAssetId`](crate::StoredAssetId)).
+        UnsupportedAssetVersion,
+
+        /// The given asset ID doesn't identify a foreign asset location:
+        /// it is an abstract one, or its location points above the universal root.
+        MalformedAssetId,
     }
 
     #[pallet::event]
@@ -105,6 +501,208 @@ pub mod pallet {
 
             /// The derivative class ID of the registered foreign asset.
             derivative_class_id: ClassIdOf<T, I>,
+
+            /// The interior location of the foreign asset if the asset ID is concrete.
+            interior_location: Option<InteriorMultiLocation>,
+
+            /// The ID of the reserve parachain if the foreign asset location starts with it.
+            reserve_para_id: Option<u32>,
+        },
+
+        /// The runtime upgrade changed the event schema version.
+        ///
+        /// See [`EVENT_SCHEMA_VERSION`](crate::EVENT_SCHEMA_VERSION).
+        SchemaVersionChanged {
+            /// The new event schema version.
+            version: u16,
+        },
+
+        /// The given foreign asset is deregistered.
+        ForeignAssetDeregistered {
+            /// The XCM asset ID of the deregistered foreign asset.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The derivative class ID of the deregistered foreign asset.
+            derivative_class_id: ClassIdOf<T, I>,
+        },
+
+        /// The deregistration of the given foreign asset is unfinished
+        /// and has to be continued by another `deregister_foreign_asset` call.
+        ForeignAssetDeregistrationContinues {
+            /// The XCM asset ID of the foreign asset being deregistered.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The derivative class ID of the foreign asset being deregistered.
+            derivative_class_id: ClassIdOf<T, I>,
+        },
+
+        /// The instance IDs are reserved for the derivatives of the foreign asset.
+        DerivativeRangeReserved {
+            /// The XCM asset ID of the foreign asset.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The derivative class ID.
+            derivative_class_id: ClassIdOf<T, I>,
+
+            /// The number of the reserved instance IDs.
+            count: u32,
+        },
+
+        /// The foreign asset is deprecated.
+        ForeignAssetDeprecated {
+            /// The XCM asset ID of the deprecated foreign asset.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The derivative class ID of the deprecated foreign asset.
+            derivative_class_id: ClassIdOf<T, I>,
+        },
+
+        /// All the XCM NFT operations are paused.
+        PalletPaused,
+
+        /// The XCM NFT operations are resumed.
+        PalletUnpaused,
+
+        /// The derivative counters of the class are recomputed.
+        CountersRecomputed {
+            /// The derivative class ID.
+            class_id: ClassIdOf<T, I>,
+        },
+
+        /// The summary of the xnft activity within the block.
+        BlockFlowSummary {
+            /// The number of the deposited class instances.
+            deposits: u32,
+
+            /// The number of the withdrawn class instances.
+            withdrawals: u32,
+
+            /// The number of the transferred class instances.
+            transfers: u32,
+
+            /// The number of the minted class instances.
+            mints: u32,
+
+            /// The number of the burned derivatives.
+            burns: u32,
+        },
+
+        /// The chains the derivative class can be moved to are changed.
+        AllowedWithdrawalDestinationsSet {
+            /// The derivative class ID.
+            derivative_class_id: ClassIdOf<T, I>,
+
+            /// The chains allowed in addition to the reserve one.
+            destinations: BoundedVec<MultiLocation, T::MaxWithdrawalDestinations>,
+        },
+
+        /// The owner of the derivative class is changed.
+        DerivativeClassOwnerChanged {
+            /// The XCM asset ID of the foreign asset.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The derivative class ID.
+            derivative_class_id: ClassIdOf<T, I>,
+
+            /// The new owner of the derivative class.
+            new_owner: NftEngineAccountIdOf<T, I>,
+        },
+
+        /// A stashed derivative is forcibly withdrawn from the pallet account.
+        StashedDerivativeForceWithdrawn {
+            /// The foreign asset instance the derivative corresponded to.
+            foreign_asset_instance: Box<ForeignAssetInstance>,
+
+            /// The withdrawn derivative.
+            derivative: InstanceOf<T, I>,
+
+            /// The account receiving the derivative.
+            beneficiary: NftEngineAccountIdOf<T, I>,
+        },
+
+        /// A shard of the given foreign asset is registered.
+        ForeignAssetShardRegistered {
+            /// The XCM asset ID of the registered foreign asset.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The shard index.
+            shard: u32,
+
+            /// The derivative class ID of the registered shard.
+            derivative_class_id: ClassIdOf<T, I>,
+        },
+
+        /// A whole derivative class is withdrawn.
+        ClassWithdrawn {
+            /// The XCM asset ID of the foreign asset.
+            foreign_asset_id: Box<XcmAssetId>,
+
+            /// The withdrawn derivative class ID.
+            derivative_class_id: ClassIdOf<T, I>,
+
+            /// The account from whom the class instances are withdrawn.
+            from: NftEngineAccountIdOf<T, I>,
+
+            /// The number of withdrawn class instances.
+            withdrawn_count: u32,
+        },
+
+        /// The derivative mint failed, the deposit is recorded in the `PendingDeposits`.
+        DepositPending {
+            /// The deposited foreign asset instance.
+            foreign_asset_instance: Box<ForeignAssetInstance>,
+
+            /// The derivative class ID.
+            derivative_class_id: ClassIdOf<T, I>,
+
+            /// The beneficiary of the deposit.
+            beneficiary: NftEngineAccountIdOf<T, I>,
+        },
+
+        /// The pending deposit is dropped without minting the derivative.
+        PendingDepositDropped {
+            /// The derivative class ID.
+            derivative_class_id: ClassIdOf<T, I>,
+
+            /// The foreign asset instance whose deposit was pending.
+            asset_instance: XcmAssetInstance,
+
+            /// The beneficiary of the dropped deposit.
+            beneficiary: NftEngineAccountIdOf<T, I>,
+        },
+
+        /// A stashed derivative no longer exists, so a new derivative is minted instead.
+        StashLostReminted {
+            /// The foreign asset instance to which the derivatives correspond.
+            foreign_asset_instance: Box<ForeignAssetInstance>,
+
+            /// The lost stashed derivative.
+            lost_derivative: InstanceOf<T, I>,
+
+            /// The newly minted derivative.
+            derivative: InstanceOf<T, I>,
+        },
+
+        /// A class instance is deposited to the `UnconvertibleDestinationEscrow`
+        /// since its beneficiary location can't be converted into an account.
+        DepositedToEscrow {
+            /// The beneficiary location of the deposit.
+            original_location: MultiLocation,
+        },
+
+        /// A class instance flow is observed but not performed (see `ObserverMode`).
+        ObservedFlow {
+            /// The operation that would be performed.
+            op: XnftOp,
+
+            /// The categorized class instance in question.
+            class_instance: CategorizedClassInstanceOf<T, I>,
+
+            /// The account from whom the instance would be withdrawn.
+            from: Option<NftEngineAccountIdOf<T, I>>,
+
+            /// The account to whom the instance would be deposited.
+            to: Option<NftEngineAccountIdOf<T, I>>,
         },
 
         /// A class instance is deposited.
@@ -114,15 +712,52 @@ pub mod pallet {
 
             /// The account to whom the instance is deposited.
             to: NftEngineAccountIdOf<T, I>,
+
+            /// The XCM asset ID of the class instance.
+            ///
+            /// `None` if the `LocalAssetIdConvert` can't represent the local class.
+            xcm_asset_id: Option<Box<XcmAssetId>>,
         },
 
         /// A class instance is withdrawn.
+        ///
+        /// A withdrawn derivative is additionally reported
+        /// via the `DerivativeBurned` or `DerivativeStashed` event preceding this one.
         Withdrawn {
             /// The class instance in question.
             class_instance: CategorizedClassInstance<InstanceOf<T, I>, InstanceOf<T, I>>,
 
             /// The account from whom the instance is withdrawn.
             from: NftEngineAccountIdOf<T, I>,
+
+            /// The XCM asset ID of the class instance.
+            ///
+            /// `None` if the `LocalAssetIdConvert` can't represent the local class.
+            xcm_asset_id: Option<Box<XcmAssetId>>,
+        },
+
+        /// A derivative is withdrawn and burned.
+        DerivativeBurned {
+            /// The foreign asset instance the derivative corresponded to.
+            foreign_asset_instance: Box<ForeignAssetInstance>,
+
+            /// The burned derivative.
+            derivative: InstanceOf<T, I>,
+
+            /// The account from whom the derivative is withdrawn.
+            from: NftEngineAccountIdOf<T, I>,
+        },
+
+        /// A derivative is withdrawn and stashed in the pallet account.
+        DerivativeStashed {
+            /// The foreign asset instance the derivative corresponds to.
+            foreign_asset_instance: Box<ForeignAssetInstance>,
+
+            /// The stashed derivative.
+            derivative: InstanceOf<T, I>,
+
+            /// The account from whom the derivative is withdrawn.
+            from: NftEngineAccountIdOf<T, I>,
         },
 
         /// A class instance is transferred.
@@ -135,19 +770,30 @@ pub mod pallet {
 
             /// The account to whom the instance is deposited.
             to: NftEngineAccountIdOf<T, I>,
+
+            /// The XCM asset ID of the class instance.
+            ///
+            /// `None` if the `LocalAssetIdConvert` can't represent the local class.
+            xcm_asset_id: Option<Box<XcmAssetId>>,
         },
     }
 
     #[pallet::storage]
     #[pallet::getter(fn foreign_asset_to_local_class)]
     pub type ForeignAssetToLocalClass<T: Config<I>, I: 'static = ()> =
-        StorageMap<_, Blake2_128Concat, xcm::v3::AssetId, ClassIdOf<T, I>, OptionQuery>;
+        StorageMap<_, Blake2_128Concat, StoredAssetId, ClassIdOf<T, I>, OptionQuery>;
 
     #[pallet::storage]
     #[pallet::getter(fn local_class_to_foreign_asset)]
     pub type LocalClassToForeignAsset<T: Config<I>, I: 'static = ()> =
-        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, xcm::v3::AssetId, OptionQuery>;
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, StoredAssetId, OptionQuery>;
 
+    /// The derivative status of each foreign asset instance within a derivative class.
+    ///
+    /// The asset instances of different forms never alias here:
+    /// the SCALE encoding of an `AssetInstance` starts with the variant index,
+    /// and `Blake2_128Concat` keeps the whole encoded key,
+    /// so, e.g., `Array16` and `Array32` instances always have distinct keys.
     #[pallet::storage]
     #[pallet::getter(fn foreign_instance_to_derivative_status)]
     pub type ForeignInstanceToDerivativeStatus<T: Config<I>, I: 'static = ()> = StorageDoubleMap<
@@ -155,11 +801,15 @@ pub mod pallet {
         Blake2_128Concat,
         ClassIdOf<T, I>,
         Blake2_128Concat,
-        xcm::v3::AssetInstance,
+        StoredAssetInstance,
         DerivativeStatus<InstanceIdOf<T, I>>,
         ValueQuery,
     >;
 
+    /// The foreign asset instance backing each derivative.
+    ///
+    /// Not populated if the `DeriveReverseMapping` is enabled.
+    /// Use [`Pallet::derivative_foreign_instance`] to query the mapping in either mode.
     #[pallet::storage]
     #[pallet::getter(fn derivative_to_foreign_instance)]
     pub type DerivativeToForeignInstance<T: Config<I>, I: 'static = ()> = StorageDoubleMap<
@@ -168,105 +818,1802 @@ pub mod pallet {
         ClassIdOf<T, I>,
         Blake2_128Concat,
         InstanceIdOf<T, I>,
-        xcm::v3::AssetInstance,
+        StoredAssetInstance,
         OptionQuery,
     >;
 
-    #[pallet::pallet]
-    pub struct Pallet<T, I = ()>(_);
+    /// The instance ID of the last burned derivative of each foreign asset instance.
+    ///
+    /// Populated only if the `PreserveIdAcrossBurn` is enabled.
+    /// The entry is consumed by the next mint of the foreign asset instance's derivative.
+    #[pallet::storage]
+    #[pallet::getter(fn retained_derivative_id)]
+    pub type RetainedDerivativeIds<T: Config<I>, I: 'static = ()> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        ClassIdOf<T, I>,
+        Blake2_128Concat,
+        StoredAssetInstance,
+        InstanceIdOf<T, I>,
+        OptionQuery,
+    >;
 
-    #[pallet::call]
-    impl<T: Config<I>, I: 'static> Pallet<T, I> {
-        /// Registers a foreign non-fungible asset.
-        ///
-        /// Creates a derivative class on this chain
-        /// backed by the foreign asset identified by the `versioned_foreign_asset`.
-        #[pallet::call_index(0)]
-        #[pallet::weight(T::WeightInfo::foreign_asset_registration_checks()
-            .saturating_add(T::NftEngine::create_class_weight(derivative_class_data))
-			.saturating_add(T::DbWeight::get().writes(3)))]
-        pub fn register_foreign_asset(
-            origin: OriginFor<T>,
-            versioned_foreign_asset: Box<VersionedAssetId>,
-            derivative_class_data: ClassDataOf<T, I>,
-        ) -> DispatchResult {
-            let foreign_asset_id =
-                Self::foreign_asset_registration_checks(origin, versioned_foreign_asset)?;
+    /// The cursors of the unfinished removals of the retained derivative IDs
+    /// by the `deregister_foreign_asset` call.
+    #[pallet::storage]
+    #[pallet::unbounded]
+    pub type RetainedDerivativeIdsCleanups<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, Vec<u8>, OptionQuery>;
 
-            let derivative_class_owner = T::PalletAccountId::get();
-            let derivative_class_id =
-                T::NftEngine::create_class(&derivative_class_owner, derivative_class_data)?;
+    #[pallet::storage]
+    #[pallet::getter(fn processed_deposit_expiry)]
+    pub type ProcessedDeposits<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ProcessedDepositKey, BlockNumberFor<T>, OptionQuery>;
 
-            <ForeignAssetToLocalClass<T, I>>::insert(foreign_asset_id, &derivative_class_id);
-            <LocalClassToForeignAsset<T, I>>::insert(&derivative_class_id, foreign_asset_id);
+    #[pallet::storage]
+    pub type ProcessedDepositExpiries<T: Config<I>, I: 'static = ()> = StorageDoubleMap<
+        _,
+        Twox64Concat,
+        BlockNumberFor<T>,
+        Blake2_128Concat,
+        ProcessedDepositKey,
+        (),
+        OptionQuery,
+    >;
 
-            Self::deposit_event(Event::ForeignAssetRegistered {
-                foreign_asset_id: Box::new(foreign_asset_id),
-                derivative_class_id,
-            });
+    #[pallet::storage]
+    pub type ProcessedDepositCleanupCursor<T: Config<I>, I: 'static = ()> =
+        StorageValue<_, BlockNumberFor<T>, OptionQuery>;
 
-            Ok(())
-        }
-    }
-}
+    #[pallet::storage]
+    #[pallet::getter(fn foreign_asset_shard_to_local_class)]
+    pub type ForeignAssetShardToLocalClass<T: Config<I>, I: 'static = ()> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        StoredAssetId,
+        Twox64Concat,
+        u32,
+        ClassIdOf<T, I>,
+        OptionQuery,
+    >;
 
-impl<T: Config<I>, I: 'static> Pallet<T, I> {
-    /// This function simplifies the `asset_id` reserve location
-    /// relative to the `UniversalLocation` of this chain.
-    ///
-    /// See `fn simplify` in [MultiLocation].
-    fn simplify_asset_id(mut asset_id: XcmAssetId) -> XcmAssetId {
-        if let XcmAssetId::Concrete(location) = &mut asset_id {
-            let context = T::UniversalLocation::get();
-            location.simplify(&context);
-        }
+    #[pallet::storage]
+    #[pallet::getter(fn dormant_derivative_class)]
+    pub type DormantDerivativeClasses<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, (), OptionQuery>;
 
-        asset_id
-    }
+    #[pallet::storage]
+    #[pallet::getter(fn foreign_asset_deregistration_block)]
+    pub type DeregisteredForeignAssets<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, StoredAssetId, BlockNumberFor<T>, OptionQuery>;
 
-    /// This function simplifies the `asset` reserve location
-    /// relative to the `UniversalLocation` of this chain.
+    /// The raw key of the last deregistration tombstone scanned by the `on_idle` cleanup.
     ///
-    /// See `fn simplify` in [MultiLocation].
-    fn simplify_asset(xcm_asset: MultiAsset) -> MultiAsset {
-        MultiAsset {
+    /// The next cleanup resumes after it.
+    #[pallet::storage]
+    #[pallet::unbounded]
+    pub type DeregistrationTombstonesCleanupCursor<T: Config<I>, I: 'static = ()> =
+        StorageValue<_, Vec<u8>, OptionQuery>;
+
+    /// The chains other than the reserve one to which each derivative class can be moved.
+    ///
+    /// An empty list means the derivatives can be moved to their reserve chain only.
+    #[pallet::storage]
+    #[pallet::getter(fn allowed_withdrawal_destinations)]
+    pub type AllowedWithdrawalDestinations<T: Config<I>, I: 'static = ()> = StorageMap<
+        _,
+        Blake2_128Concat,
+        ClassIdOf<T, I>,
+        BoundedVec<xcm::v3::MultiLocation, T::MaxWithdrawalDestinations>,
+        ValueQuery,
+    >;
+
+    /// The derivative classes that no longer accept deposits, but still allow withdrawals.
+    #[pallet::storage]
+    #[pallet::getter(fn deprecated_derivative_class)]
+    pub type DeprecatedDerivativeClasses<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, (), OptionQuery>;
+
+    /// The number of existing derivatives (both active and stashed) within each derivative class.
+    #[pallet::storage]
+    #[pallet::getter(fn derivative_count)]
+    pub type DerivativeCountPerClass<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, u32, ValueQuery>;
+
+    /// The number of stashed derivatives within each derivative class.
+    #[pallet::storage]
+    #[pallet::getter(fn stashed_count)]
+    pub type StashedCountPerClass<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, u32, ValueQuery>;
+
+    /// The beneficiaries of the foreign asset instances whose derivative mint failed
+    /// (see the `RecordFailedMints`).
+    ///
+    /// The entries are recorded within the XCM executor's `TransactionalProcessor` storage layer
+    /// of the deposit instruction: if a later instruction of the message fails and the layer
+    /// is rolled back, the entry disappears together with the rest of the deposit.
+    /// The `retry_deposit` call runs outside of the XCM execution,
+    /// so it never reenters the executor.
+    #[pallet::storage]
+    #[pallet::getter(fn pending_deposit)]
+    pub type PendingDeposits<T: Config<I>, I: 'static = ()> = StorageDoubleMap<
+        _,
+        Blake2_128Concat,
+        ClassIdOf<T, I>,
+        Blake2_128Concat,
+        StoredAssetInstance,
+        NftEngineAccountIdOf<T, I>,
+        OptionQuery,
+    >;
+
+    /// The progress of the unfinished derivative counter recomputations:
+    /// the raw key to continue the iteration from
+    /// and the numbers of all and stashed derivatives counted so far.
+    #[pallet::storage]
+    #[pallet::unbounded]
+    #[pallet::getter(fn counter_recomputation)]
+    pub type CounterRecomputations<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, (Vec<u8>, u32, u32), OptionQuery>;
+
+    /// The per-class overrides of the `EmitInstanceEvents` setting.
+    #[pallet::storage]
+    #[pallet::getter(fn class_event_emission)]
+    pub type ClassEventEmission<T: Config<I>, I: 'static = ()> =
+        StorageMap<_, Blake2_128Concat, ClassIdOf<T, I>, bool, OptionQuery>;
+
+    /// The xnft activity within the current block.
+    ///
+    /// Used only if the `EmitBlockSummary` is enabled.
+    /// It is emitted as the `BlockFlowSummary` event and reset at the end of the block.
+    #[pallet::storage]
+    pub type BlockFlow<T: Config<I>, I: 'static = ()> = StorageValue<_, FlowSummary, ValueQuery>;
+
+    /// Whether all the XCM NFT operations are paused.
+    #[pallet::storage]
+    #[pallet::getter(fn is_paused)]
+    pub type Paused<T: Config<I>, I: 'static = ()> = StorageValue<_, bool, ValueQuery>;
+
+    /// The event schema version of the last runtime upgrade.
+    #[pallet::storage]
+    pub type EventSchemaVersion<T: Config<I>, I: 'static = ()> = StorageValue<_, u16, ValueQuery>;
+
+    /// The number of registrations made by each origin (identified by its hash) in each block.
+    #[pallet::storage]
+    pub type RegistrationBuckets<T: Config<I>, I: 'static = ()> =
+        StorageDoubleMap<_, Identity, T::Hash, Twox64Concat, BlockNumberFor<T>, u32, ValueQuery>;
+
+    /// The raw key of the last registration bucket scanned by the `on_idle` cleanup.
+    ///
+    /// The next cleanup resumes after it.
+    #[pallet::storage]
+    #[pallet::unbounded]
+    pub type RegistrationBucketsCleanupCursor<T: Config<I>, I: 'static = ()> =
+        StorageValue<_, Vec<u8>, OptionQuery>;
+
+    /// The foreign assets to register at genesis.
+    #[pallet::genesis_config]
+    #[derive(frame_support::DefaultNoBound)]
+    pub struct GenesisConfig<T: Config<I>, I: 'static = ()> {
+        /// The foreign assets and the data to create their derivative classes with.
+        pub foreign_assets: Vec<(VersionedAssetId, ClassDataOf<T, I>)>,
+
+        /// The marker binding the config to the runtime and the pallet instance.
+        ///
+        /// It isn't a part of the chain spec, use `Default::default()` to fill it in.
+        #[serde(skip)]
+        pub _phantom: PhantomData<(T, I)>,
+    }
+
+    #[pallet::genesis_build]
+    impl<T: Config<I>, I: 'static> BuildGenesisConfig for GenesisConfig<T, I> {
+        fn build(&self) {
+            for (versioned_foreign_asset, derivative_class_data) in &self.foreign_assets {
+                Pallet::<T, I>::register_genesis_foreign_asset(
+                    versioned_foreign_asset,
+                    derivative_class_data.clone(),
+                )
+                .expect("the genesis foreign asset must be registrable");
+            }
+        }
+    }
+
+    /// The in-code storage version.
+    ///
+    /// See the [`migrations`](crate::migrations) for the migrations between the versions.
+    pub const STORAGE_VERSION: StorageVersion = StorageVersion::new(1);
+
+    #[pallet::pallet]
+    #[pallet::storage_version(STORAGE_VERSION)]
+    pub struct Pallet<T, I = ()>(_);
+
+    #[pallet::hooks]
+    impl<T: Config<I>, I: 'static> Hooks<BlockNumberFor<T>> for Pallet<T, I> {
+        fn integrity_test() {
+            Self::do_integrity_test()
+        }
+
+        fn on_initialize(_n: BlockNumberFor<T>) -> Weight {
+            if T::EmitBlockSummary::get() {
+                T::DbWeight::get().reads_writes(1, 2)
+            } else {
+                Weight::zero()
+            }
+        }
+
+        fn on_finalize(_n: BlockNumberFor<T>) {
+            if T::EmitBlockSummary::get() {
+                let flow = <BlockFlow<T, I>>::take();
+
+                if flow != FlowSummary::default() {
+                    Self::deposit_event(Event::BlockFlowSummary {
+                        deposits: flow.deposits,
+                        withdrawals: flow.withdrawals,
+                        transfers: flow.transfers,
+                        mints: flow.mints,
+                        burns: flow.burns,
+                    });
+                }
+            }
+        }
+
+        fn on_runtime_upgrade() -> Weight {
+            let db_weight = T::DbWeight::get();
+
+            if <EventSchemaVersion<T, I>>::get() == EVENT_SCHEMA_VERSION {
+                return db_weight.reads(1);
+            }
+
+            <EventSchemaVersion<T, I>>::put(EVENT_SCHEMA_VERSION);
+
+            Self::deposit_event(Event::SchemaVersionChanged {
+                version: EVENT_SCHEMA_VERSION,
+            });
+
+            db_weight.reads_writes(1, 2)
+        }
+
+        fn on_idle(now: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+            let consumed = Self::cleanup_processed_deposits(now, remaining_weight);
+            let remaining_weight = remaining_weight.saturating_sub(consumed);
+
+            let consumed = consumed.saturating_add(Self::cleanup_deregistration_tombstones(
+                now,
+                remaining_weight,
+            ));
+            let remaining_weight = remaining_weight.saturating_sub(consumed);
+
+            consumed.saturating_add(Self::cleanup_registration_buckets(now, remaining_weight))
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn try_state(_n: BlockNumberFor<T>) -> Result<(), sp_runtime::TryRuntimeError> {
+            Self::do_try_state()
+        }
+    }
+
+    #[pallet::call]
+    impl<T: Config<I>, I: 'static> Pallet<T, I> {
+        /// Registers a foreign non-fungible asset.
+        ///
+        /// Creates a derivative class on this chain
+        /// backed by the foreign asset identified by the `versioned_foreign_asset`.
+        ///
+        /// The `derivative_class_metadata`, if given, is forwarded to the NFT engine
+        /// (see [`NftEngine::set_class_metadata`]); the pallet doesn't store it.
+        ///
+        /// The `emit_events`, if given, overrides the `EmitInstanceEvents` for the derivative class.
+        #[pallet::call_index(0)]
+        #[pallet::weight(Pallet::<T, I>::op_weight(XnftOp::Register)
+            .saturating_add(T::NftEngine::create_class_weight(derivative_class_data))
+            .saturating_add(T::DbWeight::get().writes(4))
+            .saturating_add(Pallet::<T, I>::registration_rate_limit_weight())
+            .saturating_add(derivative_class_metadata.as_ref().map_or(Weight::zero(), |metadata| {
+                T::NftEngine::set_class_metadata_weight(metadata.len() as u32)
+            })))]
+        pub fn register_foreign_asset(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+            derivative_class_data: ClassDataOf<T, I>,
+            derivative_class_metadata: Option<BoundedVec<u8, T::MetadataLimit>>,
+            emit_events: Option<bool>,
+        ) -> DispatchResult {
+            let foreign_asset_id =
+                Self::foreign_asset_registration_checks(origin, versioned_foreign_asset)?;
+
+            ensure!(
+                !<ForeignAssetShardToLocalClass<T, I>>::contains_prefix(foreign_asset_id),
+                <Error<T, I>>::AssetAlreadyRegistered,
+            );
+
+            let derivative_class_owner = T::PalletAccountId::get();
+            let derivative_class_id =
+                T::NftEngine::create_class(&derivative_class_owner, derivative_class_data)
+                    .map_err(Self::op_failed(XnftOp::Register))?;
+
+            if let Some(metadata) = derivative_class_metadata {
+                T::NftEngine::set_class_metadata(&derivative_class_id, &metadata)
+                    .map_err(Self::op_failed(XnftOp::Register))?;
+            }
+
+            let stored_class_id = Self::stored_class_id(derivative_class_id.clone());
+
+            <ForeignAssetToLocalClass<T, I>>::insert(foreign_asset_id, &stored_class_id);
+            <LocalClassToForeignAsset<T, I>>::insert(&stored_class_id, foreign_asset_id);
+
+            if let Some(emit_events) = emit_events {
+                <ClassEventEmission<T, I>>::insert(&derivative_class_id, emit_events);
+            }
+
+            let (interior_location, reserve_para_id) =
+                Self::foreign_asset_location_details(&foreign_asset_id);
+
+            Self::deposit_event(Event::ForeignAssetRegistered {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                derivative_class_id,
+                interior_location,
+                reserve_para_id,
+            });
+
+            Ok(())
+        }
+
+        /// Registers a shard of a foreign non-fungible asset.
+        ///
+        /// Creates a derivative class on this chain backed by the foreign asset instances
+        /// routed to the `shard` by the `InstanceRangeRouter`.
+        ///
+        /// A foreign asset registered as a whole can't be sharded and vice versa.
+        ///
+        /// The `emit_events`, if given, overrides the `EmitInstanceEvents` for the derivative class.
+        #[pallet::call_index(2)]
+        #[pallet::weight(Pallet::<T, I>::op_weight(XnftOp::Register)
+            .saturating_add(T::NftEngine::create_class_weight(derivative_class_data))
+            .saturating_add(T::DbWeight::get().reads_writes(1, 3))
+            .saturating_add(Pallet::<T, I>::registration_rate_limit_weight()))]
+        pub fn register_foreign_asset_shard(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+            shard: u32,
+            derivative_class_data: ClassDataOf<T, I>,
+            emit_events: Option<bool>,
+        ) -> DispatchResult {
+            ensure!(T::InstanceSharding::get(), <Error<T, I>>::ShardingDisabled);
+
+            let foreign_asset_id =
+                Self::foreign_asset_registration_checks(origin, versioned_foreign_asset)?;
+
+            ensure!(
+                !<ForeignAssetShardToLocalClass<T, I>>::contains_key(foreign_asset_id, shard),
+                <Error<T, I>>::AssetAlreadyRegistered,
+            );
+
+            let derivative_class_owner = T::PalletAccountId::get();
+            let derivative_class_id =
+                T::NftEngine::create_class(&derivative_class_owner, derivative_class_data)
+                    .map_err(Self::op_failed(XnftOp::Register))?;
+
+            let stored_class_id = Self::stored_class_id(derivative_class_id.clone());
+
+            <ForeignAssetShardToLocalClass<T, I>>::insert(
+                foreign_asset_id,
+                shard,
+                &stored_class_id,
+            );
+            <LocalClassToForeignAsset<T, I>>::insert(&stored_class_id, foreign_asset_id);
+
+            if let Some(emit_events) = emit_events {
+                <ClassEventEmission<T, I>>::insert(&derivative_class_id, emit_events);
+            }
+
+            Self::deposit_event(Event::ForeignAssetShardRegistered {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                shard,
+                derivative_class_id,
+            });
+
+            Ok(())
+        }
+
+        /// Withdraws the given class instances owned by the origin into the xnft custody.
+        ///
+        /// The instances are withdrawn in the same way as if they were withdrawn by XCM,
+        /// so a subsequent outbound XCM can send them all at once.
+        /// Each instance must be owned by the origin itself,
+        /// even if the `AllowApprovedOperators` is enabled.
+        /// If any of the instances can't be withdrawn, the whole batch is rejected
+        /// with the error of the failed withdrawal.
+        #[pallet::call_index(8)]
+        #[pallet::weight(T::WeightInfo::bulk_withdraw_to_custody(class_instances.len() as u32))]
+        pub fn bulk_withdraw_to_custody(
+            origin: OriginFor<T>,
+            class_instances: BoundedVec<(ClassIdOf<T, I>, InstanceIdOf<T, I>), T::MaxBulkWithdraw>,
+        ) -> DispatchResult {
+            let from = T::BulkWithdrawOrigin::ensure_origin(origin)?;
+
+            for (class_id, instance_id) in class_instances {
+                let owner = <NftTransactorOf<T, I>>::class_instance_owner(&class_id, &instance_id);
+                ensure!(owner == Some(from.clone()), <Error<T, I>>::NotInstanceOwner);
+
+                match Self::categorize_local_instance(class_id, instance_id)? {
+                    CategorizedClassInstance::Local(class_instance) => {
+                        Self::withdraw_local_class_instance(class_instance, &from)?
+                    }
+                    CategorizedClassInstance::Derivative {
+                        foreign_asset_instance,
+                        derivative,
+                    } => {
+                        let DerivativeStatus::Active(instance_id) = derivative.instance_id else {
+                            return Err(<Error<T, I>>::WithdrawalFailed.into());
+                        };
+
+                        Self::withdraw_foreign_asset_instance(
+                            foreign_asset_instance,
+                            (derivative.class_id, instance_id).into(),
+                            &from,
+                        )?
+                    }
+                }
+            }
+
+            Ok(())
+        }
+
+        /// Changes the owner of the derivative class backed by the `versioned_foreign_asset`.
+        ///
+        /// The NFT engine MUST retain the pallet's rights to mint, transfer, and burn
+        /// the derivatives, otherwise the derivatives can no longer be transacted via XCM.
+        #[pallet::call_index(3)]
+        #[pallet::weight(T::NftEngine::set_class_owner_weight()
+            .saturating_add(T::DbWeight::get().reads(1)))]
+        pub fn set_derivative_class_owner(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+            new_owner: NftEngineAccountIdOf<T, I>,
+        ) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            let (foreign_asset_id, derivative_class_id) =
+                Self::registered_derivative_class(&versioned_foreign_asset)?;
+
+            T::NftEngine::set_class_owner(&derivative_class_id, &new_owner)?;
+
+            Self::deposit_event(Event::DerivativeClassOwnerChanged {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                derivative_class_id,
+                new_owner,
+            });
+
+            Ok(())
+        }
+
+        /// Sets the chains other than the reserve one
+        /// to which the derivatives of the `derivative_class_id` class can be moved.
+        ///
+        /// An empty list restricts the derivatives to their reserve chain.
+        #[pallet::call_index(4)]
+        #[pallet::weight(T::DbWeight::get().reads_writes(1, 1))]
+        pub fn set_allowed_withdrawal_destinations(
+            origin: OriginFor<T>,
+            derivative_class_id: ClassIdOf<T, I>,
+            destinations: BoundedVec<MultiLocation, T::MaxWithdrawalDestinations>,
+        ) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            ensure!(
+                Self::is_derivative_class(&derivative_class_id),
+                <Error<T, I>>::ForeignAssetNotRegistered,
+            );
+
+            <AllowedWithdrawalDestinations<T, I>>::insert(&derivative_class_id, &destinations);
+
+            Self::deposit_event(Event::AllowedWithdrawalDestinationsSet {
+                derivative_class_id,
+                destinations,
+            });
+
+            Ok(())
+        }
+
+        /// Pauses all the XCM NFT operations.
+        ///
+        /// The deposits, withdrawals, and transfers of the NFTs fail with the `NoPermission` error
+        /// until the pallet is unpaused.
+        #[pallet::call_index(5)]
+        #[pallet::weight(T::DbWeight::get().writes(1))]
+        pub fn pause(origin: OriginFor<T>) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            <Paused<T, I>>::put(true);
+
+            Self::deposit_event(Event::PalletPaused);
+
+            Ok(())
+        }
+
+        /// Resumes the XCM NFT operations.
+        #[pallet::call_index(6)]
+        #[pallet::weight(T::DbWeight::get().writes(1))]
+        pub fn unpause(origin: OriginFor<T>) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            <Paused<T, I>>::kill();
+
+            Self::deposit_event(Event::PalletUnpaused);
+
+            Ok(())
+        }
+
+        /// Deprecates the foreign asset registered as a whole.
+        ///
+        /// The deposits of the deprecated foreign asset are rejected,
+        /// so neither new derivatives are minted nor the stashed ones are reactivated.
+        /// The existing derivatives can still be withdrawn.
+        #[pallet::call_index(7)]
+        #[pallet::weight(T::DbWeight::get().reads_writes(1, 1))]
+        pub fn deprecate_foreign_asset(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+        ) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            let (foreign_asset_id, derivative_class_id) =
+                Self::registered_derivative_class(&versioned_foreign_asset)?;
+
+            <DeprecatedDerivativeClasses<T, I>>::insert(&derivative_class_id, ());
+
+            Self::deposit_event(Event::ForeignAssetDeprecated {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                derivative_class_id,
+            });
+
+            Ok(())
+        }
+
+        /// Deregisters the foreign asset registered as a whole.
+        ///
+        /// Removes the mappings between the foreign asset and its derivative class.
+        /// The stashed derivatives are purged from the pallet's storage,
+        /// while the active ones (held by the users on this chain) prevent the deregistration.
+        /// All the retained derivative IDs of the class (see the `PreserveIdAcrossBurn`)
+        /// are removed too, at most `MaxDeregistrationDerivatives` keys per call.
+        /// If more of them remain, the progress is saved in the `RetainedDerivativeIdsCleanups`,
+        /// the `ForeignAssetDeregistrationContinues` event is emitted,
+        /// and the call has to be repeated to finish the deregistration.
+        ///
+        /// The foreign asset can't be registered again during the `ReregistrationCooldown`.
+        #[pallet::call_index(1)]
+        #[pallet::weight(Pallet::<T, I>::op_weight(XnftOp::Deregister)
+            .saturating_add(T::DbWeight::get().reads_writes(3, 8))
+            .saturating_add(T::DbWeight::get()
+                .reads_writes(1, 3)
+                .saturating_mul(T::MaxDeregistrationDerivatives::get().into())))]
+        pub fn deregister_foreign_asset(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+        ) -> DispatchResult {
+            let foreign_asset_id = Self::simplified_foreign_asset_id(&versioned_foreign_asset)?;
+
+            T::ForeignAssetRegisterOrigin::ensure_origin(origin, &foreign_asset_id)?;
+
+            let stored_class_id = Self::foreign_asset_to_local_class(foreign_asset_id)
+                .ok_or(<Error<T, I>>::ForeignAssetNotRegistered)?;
+
+            let derivative_class_id = Self::engine_class_id(stored_class_id.clone());
+            let max_derivatives = T::MaxDeregistrationDerivatives::get() as usize;
+
+            ensure!(
+                !<PendingDeposits<T, I>>::contains_prefix(&derivative_class_id),
+                <Error<T, I>>::DerivativesStillExist,
+            );
+
+            let mut stashed_derivatives = Vec::new();
+            for (asset_instance, derivative_status) in
+                <ForeignInstanceToDerivativeStatus<T, I>>::iter_prefix(&derivative_class_id)
+            {
+                let instance_id = match derivative_status {
+                    DerivativeStatus::Active(_) => {
+                        return Err(<Error<T, I>>::DerivativesStillExist.into())
+                    }
+                    DerivativeStatus::Stashed(instance_id) => instance_id,
+                    DerivativeStatus::NotExists => continue,
+                };
+
+                ensure!(
+                    stashed_derivatives.len() < max_derivatives,
+                    <Error<T, I>>::TooManyDerivatives,
+                );
+
+                stashed_derivatives.push((asset_instance, instance_id));
+            }
+
+            let retained_ids_removal = <RetainedDerivativeIds<T, I>>::clear_prefix(
+                &derivative_class_id,
+                T::MaxDeregistrationDerivatives::get(),
+                <RetainedDerivativeIdsCleanups<T, I>>::take(&derivative_class_id).as_deref(),
+            );
+
+            if let Some(cursor) = retained_ids_removal.maybe_cursor {
+                <RetainedDerivativeIdsCleanups<T, I>>::insert(&derivative_class_id, cursor);
+
+                Self::deposit_event(Event::ForeignAssetDeregistrationContinues {
+                    foreign_asset_id: Box::new(foreign_asset_id),
+                    derivative_class_id,
+                });
+
+                return Ok(());
+            }
+
+            for (asset_instance, instance_id) in stashed_derivatives {
+                <ForeignInstanceToDerivativeStatus<T, I>>::remove(
+                    &derivative_class_id,
+                    asset_instance,
+                );
+                Self::remove_reverse_mapping(&derivative_class_id, &instance_id);
+            }
+
+            <ForeignAssetToLocalClass<T, I>>::remove(foreign_asset_id);
+            <LocalClassToForeignAsset<T, I>>::remove(&stored_class_id);
+            <DormantDerivativeClasses<T, I>>::remove(&derivative_class_id);
+            <DeprecatedDerivativeClasses<T, I>>::remove(&derivative_class_id);
+            <ClassEventEmission<T, I>>::remove(&derivative_class_id);
+            <DerivativeCountPerClass<T, I>>::remove(&derivative_class_id);
+            <StashedCountPerClass<T, I>>::remove(&derivative_class_id);
+            <CounterRecomputations<T, I>>::remove(&derivative_class_id);
+            <AllowedWithdrawalDestinations<T, I>>::remove(&derivative_class_id);
+
+            <DeregisteredForeignAssets<T, I>>::insert(
+                foreign_asset_id,
+                <frame_system::Pallet<T>>::block_number(),
+            );
+
+            Self::deposit_event(Event::ForeignAssetDeregistered {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                derivative_class_id,
+            });
+
+            Ok(())
+        }
+
+        /// Reserves the `count` instance IDs for the future derivatives
+        /// of the foreign asset registered as a whole.
+        ///
+        /// The reservation is delegated to the NFT engine,
+        /// which may ignore it if it doesn't support the contiguous allocation.
+        #[pallet::call_index(9)]
+        #[pallet::weight(T::NftEngine::reserve_instance_range_weight(*count)
+            .saturating_add(T::DbWeight::get().reads(1)))]
+        pub fn reserve_derivative_range(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+            count: u32,
+        ) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            let (foreign_asset_id, derivative_class_id) =
+                Self::registered_derivative_class(&versioned_foreign_asset)?;
+
+            T::NftEngine::reserve_instance_range(&derivative_class_id, count)?;
+
+            Self::deposit_event(Event::DerivativeRangeReserved {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                derivative_class_id,
+                count,
+            });
+
+            Ok(())
+        }
+
+        /// Transfers the stashed derivative of the `asset_instance`
+        /// from the pallet account to the `beneficiary`.
+        ///
+        /// The derivative stops corresponding to the foreign asset instance,
+        /// so a subsequent deposit of the foreign asset instance mints a new derivative.
+        /// It is an escape hatch for the derivatives whose foreign instances are never sent back.
+        #[pallet::call_index(10)]
+        #[pallet::weight(T::WeightInfo::force_withdraw_stashed())]
+        pub fn force_withdraw_stashed(
+            origin: OriginFor<T>,
+            derivative_class_id: ClassIdOf<T, I>,
+            asset_instance: XcmAssetInstance,
+            beneficiary: NftEngineAccountIdOf<T, I>,
+        ) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            let asset_id = Self::local_class_to_foreign_asset(Self::stored_class_id(
+                derivative_class_id.clone(),
+            ))
+            .ok_or(<Error<T, I>>::ForeignAssetNotRegistered)?;
+
+            let DerivativeStatus::Stashed(instance_id) =
+                Self::foreign_instance_to_derivative_status(&derivative_class_id, asset_instance)
+            else {
+                return Err(<Error<T, I>>::DerivativeNotStashed.into());
+            };
+
+            <NftTransactorOf<T, I>>::transfer_class_instance(
+                &derivative_class_id,
+                &instance_id,
+                &Self::class_custody_account(&derivative_class_id),
+                &beneficiary,
+            )?;
+
+            Self::remove_reverse_mapping(&derivative_class_id, &instance_id);
+            <ForeignInstanceToDerivativeStatus<T, I>>::remove(&derivative_class_id, asset_instance);
+            <DerivativeCountPerClass<T, I>>::mutate(&derivative_class_id, |count| {
+                *count = count.saturating_sub(1)
+            });
+            <StashedCountPerClass<T, I>>::mutate(&derivative_class_id, |count| {
+                *count = count.saturating_sub(1)
+            });
+
+            Self::deposit_event(Event::StashedDerivativeForceWithdrawn {
+                foreign_asset_instance: Box::new((asset_id, asset_instance).into()),
+                derivative: (derivative_class_id, instance_id).into(),
+                beneficiary,
+            });
+
+            Ok(())
+        }
+
+        /// Retries the derivative mint of a pending deposit.
+        ///
+        /// On success, the derivative is minted to the recorded beneficiary.
+        /// Any signed origin can retry since the beneficiary is fixed.
+        /// The retry is a deposit, so it is rejected while the pallet is paused
+        /// or if the `XcmContextFilter` rejects a deposit without the XCM context.
+        #[pallet::call_index(11)]
+        #[pallet::weight(T::WeightInfo::retry_deposit()
+            .saturating_add(T::OnDerivativeMinted::on_derivative_minted_weight()))]
+        pub fn retry_deposit(
+            origin: OriginFor<T>,
+            derivative_class_id: ClassIdOf<T, I>,
+            asset_instance: XcmAssetInstance,
+        ) -> DispatchResult {
+            ensure_signed(origin)?;
+
+            ensure!(!Self::is_paused(), <Error<T, I>>::Paused);
+
+            T::XcmContextFilter::filter(
+                None,
+                XnftOp::Deposit {
+                    reactivation: false,
+                },
+            )
+            .map_err(|_| <Error<T, I>>::DepositRetryFailed)?;
+
+            let beneficiary = <PendingDeposits<T, I>>::take(&derivative_class_id, asset_instance)
+                .ok_or(<Error<T, I>>::NoPendingDeposit)?;
+
+            Self::retry_pending_deposit(derivative_class_id, asset_instance, beneficiary)
+        }
+
+        /// Drops the pending deposit of the `asset_instance` without minting its derivative.
+        ///
+        /// It is an escape hatch for the deposits that can never be completed,
+        /// e.g., because the NFT engine keeps rejecting the mint.
+        /// A pending deposit prevents the deregistration of the foreign asset.
+        #[pallet::call_index(14)]
+        #[pallet::weight(T::DbWeight::get().writes(1))]
+        pub fn drop_pending_deposit(
+            origin: OriginFor<T>,
+            derivative_class_id: ClassIdOf<T, I>,
+            asset_instance: XcmAssetInstance,
+        ) -> DispatchResult {
+            T::ForeignAssetAdminOrigin::ensure_origin(origin)?;
+
+            let beneficiary = <PendingDeposits<T, I>>::take(&derivative_class_id, asset_instance)
+                .ok_or(<Error<T, I>>::NoPendingDeposit)?;
+
+            Self::deposit_event(Event::PendingDepositDropped {
+                derivative_class_id,
+                asset_instance,
+                beneficiary,
+            });
+
+            Ok(())
+        }
+
+        /// Recomputes the derivative counters of the class
+        /// from the `ForeignInstanceToDerivativeStatus`.
+        ///
+        /// At most `max_items` entries are visited per call.
+        /// If more entries remain, the progress is saved in the `CounterRecomputations`,
+        /// and the call has to be repeated.
+        /// The counters are updated and the `CountersRecomputed` event is emitted
+        /// once all the entries are visited.
+        ///
+        /// NOTE: the derivatives minted or burned between the calls can be miscounted,
+        /// consider pausing the pallet during a multi-call recomputation.
+        #[pallet::call_index(12)]
+        #[pallet::weight(T::DbWeight::get().reads_writes(u64::from(*max_items) + 1, 3))]
+        pub fn recompute_class_counters(
+            origin: OriginFor<T>,
+            class_id: ClassIdOf<T, I>,
+            max_items: u32,
+        ) -> DispatchResult {
+            ensure_root(origin)?;
+
+            let (mut iter, mut count, mut stashed_count) =
+                match <CounterRecomputations<T, I>>::take(&class_id) {
+                    Some((start_key, count, stashed_count)) => (
+                        <ForeignInstanceToDerivativeStatus<T, I>>::iter_prefix_from(
+                            &class_id, start_key,
+                        ),
+                        count,
+                        stashed_count,
+                    ),
+                    None => (
+                        <ForeignInstanceToDerivativeStatus<T, I>>::iter_prefix(&class_id),
+                        0,
+                        0,
+                    ),
+                };
+
+            for _ in 0..max_items {
+                let Some((_, derivative_status)) = iter.next() else {
+                    <DerivativeCountPerClass<T, I>>::insert(&class_id, count);
+                    <StashedCountPerClass<T, I>>::insert(&class_id, stashed_count);
+
+                    Self::deposit_event(Event::CountersRecomputed { class_id });
+
+                    return Ok(());
+                };
+
+                match derivative_status {
+                    DerivativeStatus::Active(_) => count.saturating_inc(),
+                    DerivativeStatus::Stashed(_) => {
+                        count.saturating_inc();
+                        stashed_count.saturating_inc();
+                    }
+                    DerivativeStatus::NotExists => {}
+                }
+            }
+
+            <CounterRecomputations<T, I>>::insert(
+                &class_id,
+                (iter.last_raw_key().to_vec(), count, stashed_count),
+            );
+
+            Ok(())
+        }
+
+        /// Registers a foreign non-fungible asset backed by an existing class.
+        ///
+        /// Binds the existing `class_id` class as the derivative class of the foreign asset
+        /// instead of creating a new one, so the chain can pre-create the class
+        /// (e.g., to set custom admins or metadata).
+        /// The NFT engine must let the pallet account mint the derivatives within the class.
+        ///
+        /// The origin must pass the `ForeignAssetRegisterOrigin` check.
+        /// Additionally, the class must be owned either by the signer
+        /// (converted via the `SignerToNftEngineAccount`) or by the pallet account,
+        /// so an unsigned origin (e.g., root) can bind only a class handed over to the pallet.
+        /// The class must have no instances,
+        /// so a local class whose NFTs are in use can't be turned into a derivative one.
+        #[pallet::call_index(13)]
+        #[pallet::weight(Pallet::<T, I>::op_weight(XnftOp::Register)
+            .saturating_add(T::DbWeight::get().reads_writes(4, 3))
+            .saturating_add(Pallet::<T, I>::registration_rate_limit_weight()))]
+        pub fn register_foreign_asset_for_existing_class(
+            origin: OriginFor<T>,
+            versioned_foreign_asset: Box<VersionedAssetId>,
+            class_id: ClassIdOf<T, I>,
+        ) -> DispatchResult {
+            let signer = frame_system::ensure_signed(origin.clone())
+                .ok()
+                .map(T::SignerToNftEngineAccount::convert);
+
+            let foreign_asset_id =
+                Self::foreign_asset_registration_checks(origin, versioned_foreign_asset)?;
+
+            ensure!(
+                !<ForeignAssetShardToLocalClass<T, I>>::contains_prefix(foreign_asset_id),
+                <Error<T, I>>::AssetAlreadyRegistered,
+            );
+
+            let class_owner =
+                T::NftEngine::class_owner(&class_id).ok_or(<Error<T, I>>::ClassNotFound)?;
+
+            ensure!(
+                class_owner == T::PalletAccountId::get() || Some(class_owner) == signer,
+                DispatchError::BadOrigin,
+            );
+
+            ensure!(
+                T::NftEngine::is_class_empty(&class_id),
+                <Error<T, I>>::ClassNotEmpty,
+            );
+
+            let stored_class_id = Self::stored_class_id(class_id.clone());
+
+            ensure!(
+                !<LocalClassToForeignAsset<T, I>>::contains_key(&stored_class_id),
+                <Error<T, I>>::ClassAlreadyBound,
+            );
+
+            <ForeignAssetToLocalClass<T, I>>::insert(foreign_asset_id, &stored_class_id);
+            <LocalClassToForeignAsset<T, I>>::insert(&stored_class_id, foreign_asset_id);
+
+            let (interior_location, reserve_para_id) =
+                Self::foreign_asset_location_details(&foreign_asset_id);
+
+            Self::deposit_event(Event::ForeignAssetRegistered {
+                foreign_asset_id: Box::new(foreign_asset_id),
+                derivative_class_id: class_id,
+                interior_location,
+                reserve_para_id,
+            });
+
+            Ok(())
+        }
+    }
+}
+
+impl<T: Config<I>, I: 'static> Pallet<T, I> {
+    /// Converts the NFT engine's derivative class ID into the form stored in the foreign asset mappings.
+    fn stored_class_id(class_id: ClassIdOf<T, I>) -> ClassIdOf<T, I> {
+        T::DerivativeClassIdTransform::convert(class_id)
+    }
+
+    /// Converts the derivative class ID stored in the foreign asset mappings
+    /// back into the NFT engine's class ID.
+    fn engine_class_id(stored_class_id: ClassIdOf<T, I>) -> ClassIdOf<T, I> {
+        T::DerivativeClassIdTransform::convert_back(stored_class_id)
+    }
+
+    /// Checks the consistency of the pallet configuration.
+    ///
+    /// The following invariants are checked:
+    /// * The `UniversalLocation` starts with the `GlobalConsensus` junction,
+    ///   so the chain's network is known and the location is a universal one.
+    /// * A sample local class ID round-trips through the `LocalAssetIdConvert`
+    ///   if the converter can convert it into an interior location.
+    /// * The sample local class location doesn't point to another chain
+    ///   and fits an XCM location along with the `UniversalLocation`,
+    ///   so other consensus systems can refer to the local class.
+    /// * The instance IDs fit an XCM asset instance,
+    ///   and a sample one round-trips through the `AssetInstanceConvert` if representable.
+    /// * The class and instance IDs fit the [`MAX_ID_ENCODED_LEN`].
+    fn do_integrity_test() {
+        let universal_location = T::UniversalLocation::get();
+
+        assert!(
+            matches!(universal_location.first(), Some(GlobalConsensus(_))),
+            "the `UniversalLocation` must start with the `GlobalConsensus` junction",
+        );
+
+        if let Ok(sample_class_id) = ClassIdOf::<T, I>::decode(&mut TrailingZeroInput::zeroes()) {
+            if let Some(interior) = T::LocalAssetIdConvert::convert_back(&sample_class_id) {
+                assert_eq!(
+                    T::LocalAssetIdConvert::convert(&interior),
+                    Some(sample_class_id),
+                    "the local class ID must round-trip through the `LocalAssetIdConvert`",
+                );
+
+                assert!(
+                    !matches!(interior.first(), Some(Parachain(_) | GlobalConsensus(_))),
+                    "the local class location must not point to another chain, check the `LocalAssetIdConvert`",
+                );
+
+                let mut absolute_interior = universal_location;
+                assert!(
+                    interior
+                        .iter()
+                        .all(|junction| absolute_interior.push(*junction).is_ok()),
+                    "the local class location doesn't fit an XCM location along with the `UniversalLocation`",
+                );
+            }
+        }
+
+        assert!(
+            InstanceIdOf::<T, I>::max_encoded_len() <= XcmAssetInstance::max_encoded_len(),
+            "the instance ID is wider than any XCM asset instance, check the `AssetInstanceConvert`",
+        );
+
+        // The converter may reject the sample (e.g., zero),
+        // but an ID it represents must convert back to itself.
+        if let Ok(sample_instance_id) =
+            InstanceIdOf::<T, I>::decode(&mut TrailingZeroInput::zeroes())
+        {
+            if let Some(asset_instance) = T::AssetInstanceConvert::convert_back(&sample_instance_id)
+            {
+                assert_eq!(
+                    T::AssetInstanceConvert::convert(&asset_instance),
+                    Some(sample_instance_id),
+                    "the instance ID must round-trip through the `AssetInstanceConvert`",
+                );
+            }
+        }
+
+        assert!(
+            ClassIdOf::<T, I>::max_encoded_len() <= MAX_ID_ENCODED_LEN,
+            "the class ID is too large to be a part of the storage keys",
+        );
+
+        assert!(
+            InstanceIdOf::<T, I>::max_encoded_len() <= MAX_ID_ENCODED_LEN,
+            "the instance ID is too large to be a part of the storage keys",
+        );
+    }
+
+    /// Checks the consistency of the foreign asset mappings.
+    #[cfg(any(feature = "try-runtime", feature = "std"))]
+    fn do_try_state() -> Result<(), sp_runtime::TryRuntimeError> {
+        for (foreign_asset_id, stored_class_id) in <ForeignAssetToLocalClass<T, I>>::iter() {
+            ensure!(
+                Self::stored_class_id(Self::engine_class_id(stored_class_id.clone()))
+                    == stored_class_id,
+                "the derivative class ID transform is not a bijection",
+            );
+
+            ensure!(
+                Self::local_class_to_foreign_asset(&stored_class_id) == Some(foreign_asset_id),
+                "the foreign asset mappings are inconsistent",
+            );
+        }
+
+        for (class_id, asset_instance, derivative_status) in
+            <ForeignInstanceToDerivativeStatus<T, I>>::iter()
+        {
+            let (DerivativeStatus::Active(instance_id) | DerivativeStatus::Stashed(instance_id)) =
+                derivative_status
+            else {
+                continue;
+            };
+
+            ensure!(
+                Self::derivative_foreign_instance(&class_id, &instance_id) == Some(asset_instance),
+                "the derivative doesn't map back to its foreign asset instance",
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Asserts the consistency of the foreign asset mappings.
+    ///
+    /// Performs the same checks as the `try_state` hook,
+    /// so the integrators can call it at the end of their test scenarios.
+    ///
+    /// # Panics
+    ///
+    /// Panics with the description of the first inconsistency found.
+    #[cfg(feature = "std")]
+    pub fn assert_storage_consistent() {
+        if let Err(error) = Self::do_try_state() {
+            panic!("the xnft storage is inconsistent: {error:?}");
+        }
+    }
+
+    /// Checks if the deposit identified by the `key` has already been processed.
+    fn is_deposit_processed(key: &ProcessedDepositKey) -> bool {
+        <ProcessedDeposits<T, I>>::contains_key(key)
+    }
+
+    /// Remembers the processed deposit identified by the `key` for the `ProcessedDepositTtl` blocks.
+    fn record_processed_deposit(key: ProcessedDepositKey) {
+        let now = <frame_system::Pallet<T>>::block_number();
+        let expiry = now.saturating_add(T::ProcessedDepositTtl::get());
+
+        if <ProcessedDepositCleanupCursor<T, I>>::get().is_none() {
+            <ProcessedDepositCleanupCursor<T, I>>::put(now);
+        }
+
+        <ProcessedDeposits<T, I>>::insert(&key, expiry);
+        <ProcessedDepositExpiries<T, I>>::insert(expiry, key, ());
+    }
+
+    /// Removes the expired processed deposit records while the `remaining_weight` allows.
+    fn cleanup_processed_deposits(now: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+        let db_weight = T::DbWeight::get();
+        let block_weight = db_weight.reads(1);
+        let record_weight = db_weight.reads_writes(1, 2);
+
+        let mut consumed = db_weight.reads_writes(1, 1);
+        if remaining_weight.any_lt(consumed) {
+            return Weight::zero();
+        }
+
+        let Some(mut cursor) = <ProcessedDepositCleanupCursor<T, I>>::get() else {
+            return db_weight.reads(1);
+        };
+
+        'blocks: while cursor < now {
+            if remaining_weight.any_lt(consumed.saturating_add(block_weight)) {
+                break;
+            }
+            consumed.saturating_accrue(block_weight);
+
+            for key in <ProcessedDepositExpiries<T, I>>::iter_key_prefix(cursor) {
+                if remaining_weight.any_lt(consumed.saturating_add(record_weight)) {
+                    break 'blocks;
+                }
+                consumed.saturating_accrue(record_weight);
+
+                <ProcessedDeposits<T, I>>::remove(&key);
+                <ProcessedDepositExpiries<T, I>>::remove(cursor, key);
+            }
+
+            cursor.saturating_inc();
+        }
+
+        <ProcessedDepositCleanupCursor<T, I>>::put(cursor);
+
+        consumed
+    }
+
+    /// Removes the deregistration tombstones whose cooldown has passed
+    /// while the `remaining_weight` allows.
+    ///
+    /// The scan resumes from the `DeregistrationTombstonesCleanupCursor`,
+    /// so the tombstones not yet expired don't block the ones after them.
+    fn cleanup_deregistration_tombstones(
+        now: BlockNumberFor<T>,
+        remaining_weight: Weight,
+    ) -> Weight {
+        let db_weight = T::DbWeight::get();
+        let tombstone_weight = db_weight.reads_writes(1, 1);
+        let cooldown = T::ReregistrationCooldown::get();
+
+        let mut consumed = db_weight.reads_writes(1, 1);
+        if remaining_weight.any_lt(consumed) {
+            return Weight::zero();
+        }
+
+        let mut tombstones = match <DeregistrationTombstonesCleanupCursor<T, I>>::take() {
+            Some(cursor) => <DeregisteredForeignAssets<T, I>>::iter_from(cursor),
+            None => <DeregisteredForeignAssets<T, I>>::iter(),
+        };
+
+        loop {
+            if remaining_weight.any_lt(consumed.saturating_add(tombstone_weight)) {
+                <DeregistrationTombstonesCleanupCursor<T, I>>::put(
+                    tombstones.last_raw_key().to_vec(),
+                );
+                break;
+            }
+
+            let Some((asset_id, deregistration_block)) = tombstones.next() else {
+                break;
+            };
+            consumed.saturating_accrue(tombstone_weight);
+
+            if now >= deregistration_block.saturating_add(cooldown) {
+                <DeregisteredForeignAssets<T, I>>::remove(asset_id);
+            }
+        }
+
+        consumed
+    }
+
+    /// Ensures the `origin` hasn't reached the `MaxRegistrationsPerWindow`
+    /// and counts its new registration.
+    fn ensure_registration_rate(origin: &OriginFor<T>) -> DispatchResult {
+        let Some(max_registrations) = T::MaxRegistrationsPerWindow::get() else {
+            return Ok(());
+        };
+
+        if ensure_root(origin.clone()).is_ok() {
+            return Ok(());
+        }
+
+        let origin_hash = T::Hashing::hash_of(origin.caller());
+        let now = <frame_system::Pallet<T>>::block_number();
+        let window = T::RegistrationWindow::get();
+
+        let registrations = <RegistrationBuckets<T, I>>::iter_prefix(origin_hash)
+            .filter(|(block, _)| block.saturating_add(window) > now)
+            .fold(0u32, |registrations, (_, count)| {
+                registrations.saturating_add(count)
+            });
+
+        ensure!(
+            registrations < max_registrations,
+            <Error<T, I>>::RegistrationRateLimited,
+        );
+
+        <RegistrationBuckets<T, I>>::mutate(origin_hash, now, |count| count.saturating_inc());
+
+        Ok(())
+    }
+
+    /// The weight of the registration rate limit check.
+    ///
+    /// An origin has at most `MaxRegistrationsPerWindow` buckets within the window,
+    /// plus the outdated ones not yet removed in `on_idle`.
+    pub fn registration_rate_limit_weight() -> Weight {
+        T::MaxRegistrationsPerWindow::get()
+            .map(|max_registrations| {
+                T::DbWeight::get().reads_writes(u64::from(max_registrations).saturating_add(1), 1)
+            })
+            .unwrap_or_default()
+    }
+
+    /// The weight of the pallet's part of the `op`.
+    ///
+    /// An operation on a class instance is weighed as the costliest of its paths
+    /// and doesn't include the class instance lookup.
+    /// A registration or a deregistration is weighed as its checks only,
+    /// the NFT engine's and the storage work depend on the call.
+    pub fn op_weight(op: XnftOp) -> Weight {
+        match op {
+            XnftOp::Register | XnftOp::Deregister => {
+                T::WeightInfo::foreign_asset_registration_checks()
+            }
+            XnftOp::Deposit {
+                reactivation: false,
+            } => T::WeightInfo::deposit_local().max(T::WeightInfo::deposit_derivative_new()),
+            XnftOp::Deposit { reactivation: true } => T::WeightInfo::deposit_derivative_stashed(),
+            XnftOp::Withdraw { stash: false } => {
+                T::WeightInfo::withdraw_local().max(T::WeightInfo::withdraw_derivative_burned())
+            }
+            XnftOp::Withdraw { stash: true } => T::WeightInfo::withdraw_derivative_stashed(),
+            XnftOp::Transfer => {
+                T::WeightInfo::transfer_local().max(T::WeightInfo::transfer_derivative())
+            }
+            XnftOp::Mint => T::WeightInfo::deposit_derivative_new(),
+        }
+    }
+
+    /// Removes the registration buckets outside the registration window
+    /// while the `remaining_weight` allows.
+    ///
+    /// The scan resumes from the `RegistrationBucketsCleanupCursor`,
+    /// so the buckets still within the window don't block the ones after them.
+    fn cleanup_registration_buckets(now: BlockNumberFor<T>, remaining_weight: Weight) -> Weight {
+        let db_weight = T::DbWeight::get();
+        let bucket_weight = db_weight.reads_writes(1, 1);
+        let window = T::RegistrationWindow::get();
+
+        let mut consumed = db_weight.reads_writes(1, 1);
+        if remaining_weight.any_lt(consumed) {
+            return Weight::zero();
+        }
+
+        let mut buckets = match <RegistrationBucketsCleanupCursor<T, I>>::take() {
+            Some(cursor) => <RegistrationBuckets<T, I>>::iter_from(cursor),
+            None => <RegistrationBuckets<T, I>>::iter(),
+        };
+
+        loop {
+            if remaining_weight.any_lt(consumed.saturating_add(bucket_weight)) {
+                <RegistrationBucketsCleanupCursor<T, I>>::put(buckets.last_raw_key().to_vec());
+                break;
+            }
+
+            let Some((origin_hash, block, _)) = buckets.next() else {
+                break;
+            };
+            consumed.saturating_accrue(bucket_weight);
+
+            if block.saturating_add(window) <= now {
+                <RegistrationBuckets<T, I>>::remove(origin_hash, block);
+            }
+        }
+
+        consumed
+    }
+
+    /// This function simplifies the `asset_id` reserve location
+    /// relative to the `UniversalLocation` of this chain.
+    ///
+    /// See `fn simplify` in [MultiLocation].
+    fn simplify_asset_id(asset_id: XcmAssetId) -> XcmAssetId {
+        Self::simplify_asset_id_within(asset_id, &T::UniversalLocation::get())
+    }
+
+    /// This function simplifies the `asset_id` reserve location
+    /// relative to the given `context`.
+    ///
+    /// The simplification is idempotent: a simplified location starts with a junction
+    /// that differs from the `context` one at the same depth, so it can't be reduced further.
+    /// It only strips the leading junctions shared with the `context`,
+    /// so a location drops to zero parents only if it points inside the chain itself.
+    /// Thus, a foreign asset always retains `parents > 0` after the simplification,
+    /// including the assets of other parachains within our consensus
+    /// and the ones expressed via nested `GlobalConsensus` junctions.
+    /// A location with more parents than the `context` junctions is left intact.
+    ///
+    /// See `fn simplify` in [MultiLocation].
+    fn simplify_asset_id_within(
+        mut asset_id: XcmAssetId,
+        context: &InteriorMultiLocation,
+    ) -> XcmAssetId {
+        if let XcmAssetId::Concrete(location) = &mut asset_id {
+            location.simplify(context);
+        }
+
+        asset_id
+    }
+
+    /// Converts the versioned `asset_id` into the [`StoredAssetId`]
+    /// and simplifies it relative to the given `context`.
+    ///
+    /// Returns `None` if the `asset_id` isn't representable in the stored XCM version.
+    pub(crate) fn simplify_versioned_asset_id_within(
+        versioned_asset_id: VersionedAssetId,
+        context: &InteriorMultiLocation,
+    ) -> Option<XcmAssetId> {
+        let asset_id = versioned_asset_id.try_into().ok()?;
+
+        Some(Self::simplify_asset_id_within(asset_id, context))
+    }
+
+    /// Categorizes each of the given asset instances.
+    ///
+    /// Returns `None` for an asset instance that isn't managed by the pallet.
+    ///
+    /// NOTE: the results reflect the current storage state, not the historical one.
+    pub fn categorize_batch(
+        asset_instances: Vec<(VersionedAssetId, XcmAssetInstance)>,
+    ) -> Vec<Option<CategorizedClassInstanceOf<T, I>>> {
+        let context = T::UniversalLocation::get();
+
+        asset_instances
+            .into_iter()
+            .map(|(versioned_asset_id, asset_instance)| {
+                let asset_id =
+                    Self::simplify_versioned_asset_id_within(versioned_asset_id, &context)?;
+
+                Self::class_instance(&asset_id, &asset_instance).ok()
+            })
+            .collect()
+    }
+
+    /// Returns a page of at most `limit` stashed derivatives across all derivative classes
+    /// along with the raw storage key to continue from, if the page is full.
+    ///
+    /// The iteration starts after the `start_key` or from the beginning if it is `None`.
+    ///
+    /// NOTE: the stashed derivatives are filtered during the iteration over every derivative,
+    /// so a single page may read the whole `ForeignInstanceToDerivativeStatus` map.
+    /// This is fine for off-chain incident inspection,
+    /// but it is unsuitable for alerting, which should rely on dedicated counters instead.
+    pub fn stashed_derivatives(
+        start_key: Option<Vec<u8>>,
+        limit: u32,
+    ) -> (
+        Vec<(ClassIdOf<T, I>, XcmAssetInstance, InstanceIdOf<T, I>)>,
+        Option<Vec<u8>>,
+    ) {
+        let mut iter = match start_key {
+            Some(start_key) => <ForeignInstanceToDerivativeStatus<T, I>>::iter_from(start_key),
+            None => <ForeignInstanceToDerivativeStatus<T, I>>::iter(),
+        };
+
+        let mut page = Vec::new();
+
+        while page.len() < limit as usize {
+            let Some((class_id, asset_instance, derivative_status)) = iter.next() else {
+                return (page, None);
+            };
+
+            if let DerivativeStatus::Stashed(instance_id) = derivative_status {
+                page.push((class_id, asset_instance, instance_id));
+            }
+        }
+
+        (page, Some(iter.last_raw_key().to_vec()))
+    }
+
+    /// Returns a page of at most `limit` derivative classes
+    /// as `(class ID, foreign asset ID, active count, stashed count)` tuples,
+    /// along with the key to request the next page with, if the page is full.
+    ///
+    /// NOTE: each class costs three storage reads (the mapping and both counters),
+    /// so the `limit` should be kept moderate.
+    pub fn derivative_class_summary(
+        start_key: Option<Vec<u8>>,
+        limit: u32,
+    ) -> (
+        Vec<(ClassIdOf<T, I>, VersionedAssetId, u32, u32)>,
+        Option<Vec<u8>>,
+    ) {
+        let mut iter = match start_key {
+            Some(start_key) => <LocalClassToForeignAsset<T, I>>::iter_from(start_key),
+            None => <LocalClassToForeignAsset<T, I>>::iter(),
+        };
+
+        let mut page = Vec::new();
+
+        while page.len() < limit as usize {
+            let Some((stored_class_id, foreign_asset_id)) = iter.next() else {
+                return (page, None);
+            };
+
+            let class_id = Self::engine_class_id(stored_class_id);
+            let count = Self::derivative_count(&class_id);
+            let stashed_count = Self::stashed_count(&class_id);
+
+            page.push((
+                class_id,
+                VersionedAssetId::from(foreign_asset_id),
+                count.saturating_sub(stashed_count),
+                stashed_count,
+            ));
+        }
+
+        (page, Some(iter.last_raw_key().to_vec()))
+    }
+
+    /// Iterates over the stashed derivatives of the class
+    /// as `(foreign asset instance, derivative instance ID)` pairs.
+    pub fn stashed_instances(
+        class_id: &ClassIdOf<T, I>,
+    ) -> impl Iterator<Item = (XcmAssetInstance, InstanceIdOf<T, I>)> {
+        <ForeignInstanceToDerivativeStatus<T, I>>::iter_prefix(class_id).filter_map(
+            |(asset_instance, derivative_status)| match derivative_status {
+                DerivativeStatus::Stashed(instance_id) => Some((asset_instance, instance_id)),
+                _ => None,
+            },
+        )
+    }
+
+    /// Iterates over the active derivatives of the class
+    /// as `(foreign asset instance, derivative instance ID)` pairs.
+    pub fn active_instances(
+        class_id: &ClassIdOf<T, I>,
+    ) -> impl Iterator<Item = (XcmAssetInstance, InstanceIdOf<T, I>)> {
+        <ForeignInstanceToDerivativeStatus<T, I>>::iter_prefix(class_id).filter_map(
+            |(asset_instance, derivative_status)| match derivative_status {
+                DerivativeStatus::Active(instance_id) => Some((asset_instance, instance_id)),
+                _ => None,
+            },
+        )
+    }
+
+    /// Returns the foreign asset instance the derivative corresponds to.
+    ///
+    /// Returns `None` if the class instance isn't a derivative.
+    pub fn derivative_of_foreign(
+        class_id: ClassIdOf<T, I>,
+        instance_id: InstanceIdOf<T, I>,
+    ) -> Option<ForeignAssetInstance> {
+        let asset_id = Self::local_class_to_foreign_asset(Self::stored_class_id(class_id.clone()))?;
+        let asset_instance = Self::derivative_foreign_instance(&class_id, &instance_id)?;
+
+        Some((asset_id, asset_instance).into())
+    }
+
+    /// Returns the status of the derivative corresponding to the foreign asset instance.
+    ///
+    /// Returns `None` if the asset isn't a registered foreign asset
+    /// or if the derivative doesn't exist.
+    pub fn foreign_of_derivative(
+        versioned_asset_id: VersionedAssetId,
+        asset_instance: XcmAssetInstance,
+    ) -> Option<DerivativeIdStatusOf<T, I>> {
+        let asset_id = Self::simplify_versioned_asset_id_within(
+            versioned_asset_id,
+            &T::UniversalLocation::get(),
+        )?;
+
+        let (class_id, true) = Self::lookup_class(&asset_id, &asset_instance)? else {
+            return None;
+        };
+
+        match Self::foreign_instance_to_derivative_status(&class_id, asset_instance) {
+            DerivativeStatus::NotExists => None,
+            derivative_status => Some(derivative_status),
+        }
+    }
+
+    /// Returns the derivative class of the foreign asset registered as a whole.
+    pub fn local_class_of_foreign_asset(
+        versioned_asset_id: VersionedAssetId,
+    ) -> Option<ClassIdOf<T, I>> {
+        let asset_id = Self::simplify_versioned_asset_id_within(
+            versioned_asset_id,
+            &T::UniversalLocation::get(),
+        )?;
+
+        Self::foreign_asset_to_local_class(asset_id).map(Self::engine_class_id)
+    }
+
+    /// Checks if the `asset` is an NFT managed by the pallet,
+    /// i.e., a non-fungible asset of a local class or a registered foreign asset.
+    ///
+    /// It has no side effects, so it can be used in the XCM barriers.
+    pub fn is_managed_nft(asset: &MultiAsset) -> bool {
+        let asset = Self::simplify_asset(asset.clone());
+
+        let Fungibility::NonFungible(asset_instance) = asset.fun else {
+            return false;
+        };
+
+        Self::lookup_class(&asset.id, &asset_instance).is_some()
+    }
+
+    /// This function simplifies the `asset` reserve location
+    /// relative to the `UniversalLocation` of this chain.
+    ///
+    /// See `fn simplify` in [MultiLocation].
+    fn simplify_asset(xcm_asset: MultiAsset) -> MultiAsset {
+        MultiAsset {
             id: Self::simplify_asset_id(xcm_asset.id),
             ..xcm_asset
         }
     }
 
+    /// Converts the versioned foreign asset ID into the current XCM version and simplifies it.
+    ///
+    /// NOTE: the `VersionedAssetId` has no XCM v2 variant,
+    /// and the XCM executor upgrades the incoming v2 assets to v3 before transacting them,
+    /// so neither the extrinsics nor the transactor ever observe a v2 asset ID.
+    fn simplified_foreign_asset_id(
+        versioned_foreign_asset: &VersionedAssetId,
+    ) -> Result<XcmAssetId, DispatchError> {
+        let foreign_asset_id: XcmAssetId = versioned_foreign_asset
+            .clone()
+            .try_into()
+            .map_err(|()| <Error<T, I>>::UnsupportedAssetVersion)?;
+
+        match foreign_asset_id {
+            XcmAssetId::Concrete(location) => ensure!(
+                usize::from(location.parents) <= T::UniversalLocation::get().len(),
+                <Error<T, I>>::MalformedAssetId,
+            ),
+            // The reserve of an abstract asset can't be determined.
+            XcmAssetId::Abstract(_) => return Err(<Error<T, I>>::MalformedAssetId.into()),
+        }
+
+        Ok(Self::simplify_asset_id(foreign_asset_id))
+    }
+
+    /// Returns the simplified foreign asset ID and the derivative class ID
+    /// of the foreign asset registered as a whole.
+    fn registered_derivative_class(
+        versioned_foreign_asset: &VersionedAssetId,
+    ) -> Result<(XcmAssetId, ClassIdOf<T, I>), DispatchError> {
+        let foreign_asset_id = Self::simplified_foreign_asset_id(versioned_foreign_asset)?;
+
+        let stored_class_id = Self::foreign_asset_to_local_class(foreign_asset_id)
+            .ok_or(<Error<T, I>>::ForeignAssetNotRegistered)?;
+
+        Ok((foreign_asset_id, Self::engine_class_id(stored_class_id)))
+    }
+
     /// Check if the foreign asset can be registered.
     fn foreign_asset_registration_checks(
         origin: OriginFor<T>,
         versioned_foreign_asset: Box<VersionedAssetId>,
     ) -> Result<XcmAssetId, DispatchError> {
-        let foreign_asset_id: XcmAssetId = versioned_foreign_asset
-            .as_ref()
-            .clone()
-            .try_into()
-            .map_err(|()| Error::<T, I>::BadAssetId)?;
+        let simplified_asset_id = Self::foreign_asset_location_checks(&versioned_foreign_asset)?;
+
+        ensure!(
+            !(T::PauseRegistrations::get() && Self::is_paused()),
+            <Error<T, I>>::Paused,
+        );
+
+        Self::ensure_registration_rate(&origin)?;
+
+        T::ForeignAssetRegisterOrigin::ensure_origin(origin, &simplified_asset_id)?;
+
+        Self::ensure_not_registered(simplified_asset_id)?;
+
+        Ok(simplified_asset_id)
+    }
 
-        let simplified_asset_id = Self::simplify_asset_id(foreign_asset_id);
+    /// Converts the versioned foreign asset ID into the current XCM version, simplifies it,
+    /// and checks that its location can be registered as a foreign asset.
+    fn foreign_asset_location_checks(
+        versioned_foreign_asset: &VersionedAssetId,
+    ) -> Result<XcmAssetId, DispatchError> {
+        let simplified_asset_id = Self::simplified_foreign_asset_id(versioned_foreign_asset)?;
 
         if let XcmAssetId::Concrete(location) = simplified_asset_id {
             ensure!(
                 location.parents > 0,
                 <Error<T, I>>::AttemptToRegisterLocalAsset
             );
+
+            ensure!(
+                location.parents >= T::MinForeignParents::get(),
+                <Error<T, I>>::ReserveTooClose
+            );
+
+            ensure!(
+                location
+                    .interior
+                    .iter()
+                    .all(T::AllowedInteriorJunctions::contains),
+                <Error<T, I>>::DisallowedJunction,
+            );
         }
 
-        T::ForeignAssetRegisterOrigin::ensure_origin(origin, &simplified_asset_id)?;
+        Ok(simplified_asset_id)
+    }
 
+    /// Ensures the foreign asset isn't registered
+    /// and its re-registration cooldown, if any, has passed.
+    fn ensure_not_registered(simplified_asset_id: XcmAssetId) -> DispatchResult {
         ensure!(
             !<ForeignAssetToLocalClass<T, I>>::contains_key(simplified_asset_id),
             <Error<T, I>>::AssetAlreadyRegistered,
         );
 
-        Ok(simplified_asset_id)
+        if let Some(deregistration_block) =
+            Self::foreign_asset_deregistration_block(simplified_asset_id)
+        {
+            let now = <frame_system::Pallet<T>>::block_number();
+
+            ensure!(
+                now >= deregistration_block.saturating_add(T::ReregistrationCooldown::get()),
+                <Error<T, I>>::ReregistrationTooSoon,
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Checks if the foreign asset can be registered and returns its simplified asset ID.
+    ///
+    /// The same checks as for the `register_foreign_asset` call apply,
+    /// except for the origin-dependent ones (the `ForeignAssetRegisterOrigin`
+    /// and the `MaxRegistrationsPerWindow`). The state isn't changed,
+    /// so the front-ends can use it to pre-validate a registration.
+    pub fn can_register(
+        versioned_foreign_asset: &VersionedAssetId,
+    ) -> Result<XcmAssetId, DispatchError> {
+        let foreign_asset_id = Self::foreign_asset_location_checks(versioned_foreign_asset)?;
+
+        ensure!(
+            !(T::PauseRegistrations::get() && Self::is_paused()),
+            <Error<T, I>>::Paused,
+        );
+
+        Self::ensure_not_registered(foreign_asset_id)?;
+
+        ensure!(
+            !<ForeignAssetShardToLocalClass<T, I>>::contains_prefix(foreign_asset_id),
+            <Error<T, I>>::AssetAlreadyRegistered,
+        );
+
+        Ok(foreign_asset_id)
+    }
+
+    /// Registers the foreign asset at genesis.
+    ///
+    /// The same checks as for the `register_foreign_asset` call apply, except for the origin ones.
+    /// No events are emitted.
+    fn register_genesis_foreign_asset(
+        versioned_foreign_asset: &VersionedAssetId,
+        derivative_class_data: ClassDataOf<T, I>,
+    ) -> DispatchResult {
+        let foreign_asset_id = Self::foreign_asset_location_checks(versioned_foreign_asset)?;
+
+        Self::ensure_not_registered(foreign_asset_id)?;
+
+        ensure!(
+            !<ForeignAssetShardToLocalClass<T, I>>::contains_prefix(foreign_asset_id),
+            <Error<T, I>>::AssetAlreadyRegistered,
+        );
+
+        let derivative_class_id =
+            T::NftEngine::create_class(&T::PalletAccountId::get(), derivative_class_data)
+                .map_err(Self::op_failed(XnftOp::Register))?;
+
+        let stored_class_id = Self::stored_class_id(derivative_class_id);
+
+        <ForeignAssetToLocalClass<T, I>>::insert(foreign_asset_id, &stored_class_id);
+        <LocalClassToForeignAsset<T, I>>::insert(&stored_class_id, foreign_asset_id);
+
+        Ok(())
+    }
+
+    /// Returns the interior location of the foreign asset
+    /// and the ID of its reserve parachain for the indexers.
+    fn foreign_asset_location_details(
+        foreign_asset_id: &XcmAssetId,
+    ) -> (Option<InteriorMultiLocation>, Option<u32>) {
+        let Concrete(location) = foreign_asset_id else {
+            return (None, None);
+        };
+
+        let reserve_para_id = match location.interior.first() {
+            Some(Parachain(para_id)) => Some(*para_id),
+            _ => None,
+        };
+
+        (Some(location.interior), reserve_para_id)
+    }
+
+    /// Returns the account custodying the stashed derivatives
+    /// and the withdrawn local class instances of the class.
+    ///
+    /// It is the `PalletAccountId` unless the `PerClassCustody` is enabled.
+    /// Otherwise, it is the sub-account of the `PalletId` keyed by the class ID.
+    /// The sub-account is truncated to the account ID width, so the account IDs
+    /// narrower than the pallet ID prefix plus the encoded class ID can't tell the classes apart.
+    ///
+    /// NOTE: switching the `PerClassCustody` strands the instances in the previous custody accounts,
+    /// so it must be accompanied by a migration moving them.
+    pub fn class_custody_account(class_id: &ClassIdOf<T, I>) -> NftEngineAccountIdOf<T, I> {
+        if !T::PerClassCustody::get() {
+            return T::PalletAccountId::get();
+        }
+
+        T::PalletId::get().into_sub_account_truncating(class_id)
+    }
+
+    /// Records the xnft activity in the `BlockFlow` if the `EmitBlockSummary` is enabled.
+    fn note_flow(update: impl FnOnce(&mut FlowSummary)) {
+        if T::EmitBlockSummary::get() {
+            <BlockFlow<T, I>>::mutate(update);
+        }
+    }
+
+    /// Checks if the per-instance events of the class are emitted.
+    pub fn class_events_enabled(class_id: &ClassIdOf<T, I>) -> bool {
+        Self::class_event_emission(class_id).unwrap_or_else(T::EmitInstanceEvents::get)
+    }
+
+    /// Checks if the class is a derivative one.
+    ///
+    /// Only the storage key existence is checked, the stored foreign asset ID isn't decoded.
+    pub fn is_derivative_class(class_id: &ClassIdOf<T, I>) -> bool {
+        <LocalClassToForeignAsset<T, I>>::contains_key(Self::stored_class_id(class_id.clone()))
+    }
+
+    /// Returns the versioned asset ID of the foreign asset backing the derivative class.
+    ///
+    /// Returns `None` if the class isn't a derivative one.
+    pub fn derivative_reserve_asset(class_id: ClassIdOf<T, I>) -> Option<VersionedAssetId> {
+        Self::local_class_to_foreign_asset(Self::stored_class_id(class_id))
+            .map(VersionedAssetId::from)
+    }
+
+    /// Returns the foreign asset instance backing the derivative.
+    ///
+    /// If the `DeriveReverseMapping` is enabled, the foreign asset instance is computed
+    /// and then checked against the derivative status instead of being read from the storage.
+    pub fn derivative_foreign_instance(
+        class_id: &ClassIdOf<T, I>,
+        instance_id: &InstanceIdOf<T, I>,
+    ) -> Option<XcmAssetInstance> {
+        if !T::DeriveReverseMapping::get() {
+            return Self::derivative_to_foreign_instance(class_id, instance_id);
+        }
+
+        let asset_instance = T::DerivativeInstanceConvert::convert_back(instance_id)?;
+
+        match Self::foreign_instance_to_derivative_status(class_id, asset_instance) {
+            DerivativeStatus::Active(derivative_instance_id)
+            | DerivativeStatus::Stashed(derivative_instance_id)
+                if &derivative_instance_id == instance_id =>
+            {
+                Some(asset_instance)
+            }
+            _ => None,
+        }
+    }
+
+    /// Categorizes the class instance identified by the local `class_id` and `instance_id`.
+    fn categorize_local_instance(
+        class_id: ClassIdOf<T, I>,
+        instance_id: InstanceIdOf<T, I>,
+    ) -> Result<CategorizedClassInstanceOf<T, I>, DispatchError> {
+        let stored_class_id = Self::stored_class_id(class_id.clone());
+        let Some(foreign_asset_id) = Self::local_class_to_foreign_asset(&stored_class_id) else {
+            return Ok(CategorizedClassInstance::local(class_id, instance_id));
+        };
+
+        let foreign_asset_instance = Self::derivative_foreign_instance(&class_id, &instance_id)
+            .ok_or(<Error<T, I>>::UnknownDerivative)?;
+
+        let derivative_status =
+            Self::foreign_instance_to_derivative_status(&class_id, foreign_asset_instance);
+
+        Ok(CategorizedClassInstance::derivative(
+            foreign_asset_id,
+            foreign_asset_instance,
+            class_id,
+            derivative_status,
+        ))
     }
 }
 
-#[derive(Default, Debug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
+#[derive(Default, RuntimeDebug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
 /// The status of a derivative asset instance ID.
 pub enum DerivativeStatus<InstanceId> {
     /// The given derivative ID is active,
@@ -297,7 +2644,7 @@ impl<InstanceId> DerivativeStatus<InstanceId> {
 }
 
 /// An NFT complete identification.
-#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
+#[derive(RuntimeDebug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
 pub struct ClassInstance<ClassId, InstanceId> {
     /// The class ID of the instance.
     pub class_id: ClassId,
@@ -317,28 +2664,59 @@ impl<ClassId, InstanceId> From<(ClassId, InstanceId)> for ClassInstance<ClassId,
 
 type InstanceOf<T, I> = ClassInstance<ClassIdOf<T, I>, InstanceIdOf<T, I>>;
 
-/// A foreign NFT complete identification.
-#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
-pub struct ForeignAssetInstance {
-    /// The asset ID of the foreign instance.
-    pub asset_id: XcmAssetId,
-
-    /// The foreign asset instance.
-    pub asset_instance: XcmAssetInstance,
-}
-
-impl From<(XcmAssetId, XcmAssetInstance)> for ForeignAssetInstance {
-    fn from((asset_id, asset_instance): (XcmAssetId, XcmAssetInstance)) -> Self {
-        Self {
-            asset_id,
-            asset_instance,
-        }
-    }
-}
+/// The maximum encoded length of the class and instance IDs.
+///
+/// The IDs are parts of the storage keys and values, so their size directly affects the PoV.
+/// E.g., the largest `ForeignInstanceToDerivativeStatus` entry takes
+/// 32 bytes of the key prefix, 16 + `ClassId` bytes of the class hasher and key,
+/// 16 + 33 bytes of the `AssetInstance` hasher and key (an `Array32` instance),
+/// and 1 + `InstanceId` bytes of the `DerivativeStatus` value.
+///
+/// The bound equals the encoded length of the widest XCM asset instance (`Array32`).
+/// An instance ID must fit an asset instance anyway,
+/// and the same bound admits the 32-byte class IDs (e.g., hashes),
+/// so the largest entry above takes 130 bytes of the key and 34 bytes of the value.
+/// The bound is checked in the integrity test, so an ID type change blowing up the keys
+/// (e.g., to a `BoundedVec`) doesn't go unnoticed.
+pub const MAX_ID_ENCODED_LEN: usize = 33;
+
+/// The version of the pallet's event schema.
+///
+/// It is incremented whenever the events change in a way affecting their decoding,
+/// so the indexers can choose the parsing rules per runtime.
+pub const EVENT_SCHEMA_VERSION: u16 = 5;
+
+/// The identification of a deposit made by an XCM message:
+/// the message topic, the deposited asset ID, and the asset instance.
+pub type ProcessedDepositKey = (XcmHash, StoredAssetId, StoredAssetInstance);
+
+/// The XCM asset ID form the pallet stores and matches the foreign assets by.
+///
+/// It is the latest XCM version supported by the `xcm` crate the pallet is built against.
+/// The asset IDs of other XCM versions are converted into it at the pallet boundary
+/// (see the `VersionedAssetId` arguments of the calls and the runtime API).
+/// All the storage items keyed or valued by an asset ID use this alias.
+///
+/// The alias isn't a `Config` item: the `TransactAsset` implementation receives the assets
+/// in the executor's XCM version, which is the same fixed version.
+///
+/// NOTE: switching the alias to a newer XCM version changes the encoding of the storage keys,
+/// so it must be accompanied by a migration re-keying the existing storage.
+pub type StoredAssetId = xcm::v3::AssetId;
+
+/// The XCM asset instance form the pallet stores and matches the foreign instances by.
+///
+/// See [`StoredAssetId`].
+pub type StoredAssetInstance = xcm::v3::AssetInstance;
+
+type CategorizedClassInstanceOf<T, I> =
+    CategorizedClassInstance<InstanceOf<T, I>, DerivativeStatusOf<T, I>>;
+type DerivativeIdStatusOf<T, I> = DerivativeStatus<InstanceIdOf<T, I>>;
+type DerivativeStatusOf<T, I> = ClassInstance<ClassIdOf<T, I>, DerivativeIdStatusOf<T, I>>;
 
 /// A categorized class instance represents either
 /// a local class instance or a derivative class instance corresponding to a foreign one on a remote chain.
-#[derive(Debug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
+#[derive(RuntimeDebug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
 pub enum CategorizedClassInstance<LocalInstance, DerivativeInstance> {
     /// A local class instance.
     Local(LocalInstance),
@@ -352,3 +2730,178 @@ pub enum CategorizedClassInstance<LocalInstance, DerivativeInstance> {
         derivative: DerivativeInstance,
     },
 }
+
+impl<ClassId, LocalInstanceId, DerivativeInstanceId>
+    CategorizedClassInstance<
+        ClassInstance<ClassId, LocalInstanceId>,
+        ClassInstance<ClassId, DerivativeInstanceId>,
+    >
+{
+    /// Creates a local class instance.
+    pub fn local(class_id: ClassId, instance_id: LocalInstanceId) -> Self {
+        Self::Local((class_id, instance_id).into())
+    }
+
+    /// Creates a derivative class instance corresponding to the given foreign asset instance.
+    pub fn derivative(
+        foreign_asset_id: XcmAssetId,
+        foreign_asset_instance: XcmAssetInstance,
+        class_id: ClassId,
+        instance_id: DerivativeInstanceId,
+    ) -> Self {
+        Self::Derivative {
+            foreign_asset_instance: Box::new((foreign_asset_id, foreign_asset_instance).into()),
+            derivative: (class_id, instance_id).into(),
+        }
+    }
+
+    /// Returns the class ID of the class instance.
+    pub fn class_id(&self) -> &ClassId {
+        match self {
+            Self::Local(class_instance) => &class_instance.class_id,
+            Self::Derivative { derivative, .. } => &derivative.class_id,
+        }
+    }
+}
+
+/// The filter of the XCM context of the xnft asset operations.
+pub trait XcmContextFilter {
+    /// Checks if the `op` operation (i.e., a `Deposit`, a `Withdraw`, or a `Transfer`)
+    /// is allowed within the XCM `context`.
+    ///
+    /// The filter is consulted only for the NFTs of the assets managed by the pallet,
+    /// right after their class instance is looked up and before any other check.
+    /// Other assets are left to the next transactor in the tuple without consulting the filter,
+    /// while a rejection of a managed NFT aborts the tuple.
+    fn filter(context: Option<&XcmContext>, op: XnftOp) -> Result<(), XcmError>;
+}
+
+impl XcmContextFilter for () {
+    fn filter(_context: Option<&XcmContext>, _op: XnftOp) -> Result<(), XcmError> {
+        Ok(())
+    }
+}
+
+/// The source of the royalty of the incoming foreign asset instances.
+pub trait RoyaltySource<AccountId> {
+    /// Extracts the royalty of the `asset` deposited within the XCM `context`.
+    ///
+    /// Returns `None` if no royalty was carried.
+    fn royalty(asset: &MultiAsset, context: Option<&XcmContext>) -> Option<Royalty<AccountId>>;
+}
+
+impl<AccountId> RoyaltySource<AccountId> for () {
+    fn royalty(_asset: &MultiAsset, _context: Option<&XcmContext>) -> Option<Royalty<AccountId>> {
+        None
+    }
+}
+
+/// The source of the metadata of the incoming foreign asset instances.
+pub trait MetadataSource {
+    /// Extracts the metadata of the `asset` deposited within the XCM `context`.
+    ///
+    /// Returns `None` if no metadata was carried.
+    fn metadata(asset: &MultiAsset, context: Option<&XcmContext>) -> Option<Vec<u8>>;
+}
+
+impl MetadataSource for () {
+    fn metadata(_asset: &MultiAsset, _context: Option<&XcmContext>) -> Option<Vec<u8>> {
+        None
+    }
+}
+
+/// The hook of the derivative minting.
+pub trait OnDerivativeMinted<ClassId, InstanceId, AccountId> {
+    /// Handles the derivative `instance_id` minted within the `class_id` to the `to` account.
+    ///
+    /// An error rolls back the mint and fails the deposit.
+    fn on_derivative_minted(
+        class_id: &ClassId,
+        instance_id: &InstanceId,
+        to: &AccountId,
+    ) -> DispatchResult;
+
+    /// The weight of the `on_derivative_minted`.
+    fn on_derivative_minted_weight() -> Weight;
+}
+
+impl<ClassId, InstanceId, AccountId> OnDerivativeMinted<ClassId, InstanceId, AccountId> for () {
+    fn on_derivative_minted(
+        _class_id: &ClassId,
+        _instance_id: &InstanceId,
+        _to: &AccountId,
+    ) -> DispatchResult {
+        Ok(())
+    }
+
+    fn on_derivative_minted_weight() -> Weight {
+        Weight::zero()
+    }
+}
+
+/// The handling of a deposit of a local class instance that doesn't exist.
+#[derive(RuntimeDebug, PartialEq, Eq, Clone, Copy, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub enum LocalDepositMode {
+    /// The class instance is transferred from the custody account only,
+    /// so the deposit fails if it doesn't exist.
+    TransferOnly,
+
+    /// The class instance is minted via the [`NftEngine::mint_local`] if it doesn't exist.
+    ///
+    /// It supports re-importing the exported local NFTs after the originals were burned.
+    MintIfMissing,
+}
+
+/// The counters of the xnft activity within a block.
+#[derive(Default, RuntimeDebug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub struct FlowSummary {
+    /// The number of the deposited class instances.
+    pub deposits: u32,
+
+    /// The number of the withdrawn class instances.
+    pub withdrawals: u32,
+
+    /// The number of the transferred class instances.
+    pub transfers: u32,
+
+    /// The number of the minted class instances.
+    pub mints: u32,
+
+    /// The number of the burned derivatives.
+    pub burns: u32,
+}
+
+/// An xnft operation.
+///
+/// It is passed to the [`XcmContextFilter`], reported by the [`Event::ObservedFlow`],
+/// weighed by the [`Pallet::op_weight`],
+/// and used to tag the NFT engine errors with the operation during which they occurred.
+#[derive(RuntimeDebug, PartialEq, Eq, Clone, Copy, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub enum XnftOp {
+    /// A registration of a foreign asset.
+    Register,
+
+    /// A deregistration of a foreign asset.
+    Deregister,
+
+    /// A deposit of a class instance.
+    Deposit {
+        /// Whether a stashed derivative is reactivated rather than a new one minted.
+        reactivation: bool,
+    },
+
+    /// A withdrawal of a class instance.
+    Withdraw {
+        /// Whether the withdrawn derivative is stashed rather than burned.
+        ///
+        /// The NFT engine decides it during the withdrawal,
+        /// so it is always `false` for the [`XcmContextFilter`] and the [`Event::ObservedFlow`].
+        stash: bool,
+    },
+
+    /// A transfer of a class instance.
+    Transfer,
+
+    /// A mint of a new derivative.
+    Mint,
+}
@@ -0,0 +1,202 @@
+//! The storage migrations of the xnft pallet.
+//!
+//! Each migration is an `OnRuntimeUpgrade` the runtime includes into its migrations tuple.
+//! A migration runs only if the on-chain storage version is the one it expects,
+//! so it is a no-op if included once too often.
+
+/// The migration from the storage version 0 to the version 1.
+///
+/// The migration assumes the storage version 0 stored the derivative mappings under these names:
+/// * `ForeignInstanceToDerivativeIdStatus`, now the [`ForeignInstanceToDerivativeStatus`].
+/// * `DerivativeIdToForeignInstance`, now the [`DerivativeToForeignInstance`].
+///
+/// The keys and the values are assumed to be encoded identically,
+/// so the entries are moved to the new storage items as is.
+///
+/// WARNING: the old layout above isn't verified against any released revision of the pallet.
+/// Check it against the chain's actual storage (e.g., by running the migration with `try-runtime`
+/// against a live state) before including the migration into a runtime.
+///
+/// The migration is bounded: it moves at most `MaxEntries` entries of each mapping.
+/// If either old mapping holds more, it leaves the storage untouched
+/// (the `try-runtime` pre-upgrade check fails in that case).
+///
+/// [`ForeignInstanceToDerivativeStatus`]: crate::ForeignInstanceToDerivativeStatus
+/// [`DerivativeToForeignInstance`]: crate::DerivativeToForeignInstance
+pub mod v1 {
+    use frame_support::{
+        pallet_prelude::*,
+        storage_alias,
+        traits::{GetStorageVersion, OnRuntimeUpgrade},
+    };
+    use sp_runtime::Saturating;
+    use sp_std::marker::PhantomData;
+    #[cfg(feature = "try-runtime")]
+    use sp_std::vec::Vec;
+
+    use crate::{
+        ClassIdOf, Config, DerivativeStatus, DerivativeToForeignInstance,
+        ForeignInstanceToDerivativeStatus, InstanceIdOf, Pallet,
+    };
+
+    const LOG_TARGET: &str = "runtime::xnft::migrations";
+
+    #[storage_alias]
+    type ForeignInstanceToDerivativeIdStatus<T: Config<I>, I: 'static> = StorageDoubleMap<
+        Pallet<T, I>,
+        Blake2_128Concat,
+        ClassIdOf<T, I>,
+        Blake2_128Concat,
+        xcm::v3::AssetInstance,
+        DerivativeStatus<InstanceIdOf<T, I>>,
+        OptionQuery,
+    >;
+
+    #[storage_alias]
+    type DerivativeIdToForeignInstance<T: Config<I>, I: 'static> = StorageDoubleMap<
+        Pallet<T, I>,
+        Blake2_128Concat,
+        ClassIdOf<T, I>,
+        Blake2_128Concat,
+        InstanceIdOf<T, I>,
+        xcm::v3::AssetInstance,
+        OptionQuery,
+    >;
+
+    /// Moves the derivative mappings from their old storage names and sets the storage version 1.
+    ///
+    /// The `MaxEntries` bounds the number of the moved entries of each mapping.
+    pub struct MigrateToV1<T, I = (), MaxEntries = ConstU32<10_000>>(
+        PhantomData<(T, I, MaxEntries)>,
+    );
+
+    impl<T: Config<I>, I: 'static, MaxEntries: Get<u32>> MigrateToV1<T, I, MaxEntries> {
+        /// Checks if both old mappings fit into the `MaxEntries` bound.
+        ///
+        /// Returns the number of the entries read.
+        fn old_entries_within_bound() -> (bool, u64) {
+            let max_entries = MaxEntries::get() as usize;
+
+            let status_count = <ForeignInstanceToDerivativeIdStatus<T, I>>::iter_keys()
+                .take(max_entries.saturating_add(1))
+                .count();
+
+            let reverse_count = <DerivativeIdToForeignInstance<T, I>>::iter_keys()
+                .take(max_entries.saturating_add(1))
+                .count();
+
+            (
+                status_count <= max_entries && reverse_count <= max_entries,
+                status_count.saturating_add(reverse_count) as u64,
+            )
+        }
+    }
+
+    impl<T: Config<I>, I: 'static, MaxEntries: Get<u32>> OnRuntimeUpgrade
+        for MigrateToV1<T, I, MaxEntries>
+    {
+        fn on_runtime_upgrade() -> Weight {
+            let db_weight = T::DbWeight::get();
+
+            if <Pallet<T, I>>::on_chain_storage_version() != 0 {
+                log::info!(
+                    target: LOG_TARGET,
+                    "MigrateToV1: the storage version isn't 0, skipping",
+                );
+
+                return db_weight.reads(1);
+            }
+
+            let (within_bound, counted) = Self::old_entries_within_bound();
+
+            if !within_bound {
+                log::error!(
+                    target: LOG_TARGET,
+                    "MigrateToV1: the old mappings hold more than {} entries each, skipping",
+                    MaxEntries::get(),
+                );
+
+                return db_weight.reads(counted.saturating_add(1));
+            }
+
+            let mut moved: u64 = 0;
+
+            for (class_id, asset_instance, status) in
+                <ForeignInstanceToDerivativeIdStatus<T, I>>::drain()
+            {
+                <ForeignInstanceToDerivativeStatus<T, I>>::insert(class_id, asset_instance, status);
+                moved.saturating_inc();
+            }
+
+            for (class_id, instance_id, asset_instance) in
+                <DerivativeIdToForeignInstance<T, I>>::drain()
+            {
+                <DerivativeToForeignInstance<T, I>>::insert(class_id, instance_id, asset_instance);
+                moved.saturating_inc();
+            }
+
+            StorageVersion::new(1).put::<Pallet<T, I>>();
+
+            log::info!(
+                target: LOG_TARGET,
+                "MigrateToV1: moved {moved} derivative mapping entries",
+            );
+
+            // Each moved entry is read, removed, and written under the new name.
+            db_weight.reads_writes(
+                moved.saturating_add(counted).saturating_add(1),
+                moved.saturating_mul(2).saturating_add(1),
+            )
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn pre_upgrade() -> Result<Vec<u8>, sp_runtime::TryRuntimeError> {
+            ensure!(
+                <Pallet<T, I>>::on_chain_storage_version() != 0
+                    || Self::old_entries_within_bound().0,
+                "MigrateToV1: the old mappings exceed the MaxEntries bound",
+            );
+
+            let status_count = <ForeignInstanceToDerivativeIdStatus<T, I>>::iter().count() as u64
+                + <ForeignInstanceToDerivativeStatus<T, I>>::iter().count() as u64;
+
+            let reverse_count = <DerivativeIdToForeignInstance<T, I>>::iter().count() as u64
+                + <DerivativeToForeignInstance<T, I>>::iter().count() as u64;
+
+            Ok((status_count, reverse_count).encode())
+        }
+
+        #[cfg(feature = "try-runtime")]
+        fn post_upgrade(state: Vec<u8>) -> Result<(), sp_runtime::TryRuntimeError> {
+            let (status_count, reverse_count) = <(u64, u64)>::decode(&mut &state[..])
+                .map_err(|_| "MigrateToV1: can't decode the pre-upgrade state")?;
+
+            ensure!(
+                <ForeignInstanceToDerivativeIdStatus<T, I>>::iter()
+                    .next()
+                    .is_none()
+                    && <DerivativeIdToForeignInstance<T, I>>::iter()
+                        .next()
+                        .is_none(),
+                "MigrateToV1: the old storage isn't empty",
+            );
+
+            ensure!(
+                <ForeignInstanceToDerivativeStatus<T, I>>::iter().count() as u64 == status_count,
+                "MigrateToV1: the derivative status count mismatch",
+            );
+
+            ensure!(
+                <DerivativeToForeignInstance<T, I>>::iter().count() as u64 == reverse_count,
+                "MigrateToV1: the reverse mapping count mismatch",
+            );
+
+            ensure!(
+                <Pallet<T, I>>::on_chain_storage_version() >= 1,
+                "MigrateToV1: the storage version isn't updated",
+            );
+
+            Ok(())
+        }
+    }
+}
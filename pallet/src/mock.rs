@@ -0,0 +1,1242 @@
+//! The mock runtime of the xnft pallet tests.
+//!
+//! The [`MockNftEngine`] keeps its state in the runtime storage,
+//! so the storage transactions of the pallet roll back the engine changes too.
+
+use frame_support::{
+    construct_runtime, ensure, parameter_types,
+    storage::unhashed,
+    traits::{
+        AsEnsureOriginWithArg, ConstBool, ConstU128, ConstU32, ConstU64, Contains, EitherOfDiverse,
+        Everything, Nothing,
+    },
+    weights::{RuntimeDbWeight, Weight},
+    PalletId,
+};
+use frame_system::{EnsureRoot, EnsureSigned};
+use parity_scale_codec::{Decode, Encode};
+use sp_core::H256;
+use sp_runtime::{
+    traits::{BlakeTwo256, Convert, ConvertBack, Identity, IdentityLookup, MaybeEquivalence},
+    BuildStorage, DispatchError, DispatchResult,
+};
+use sp_std::collections::{btree_map::BTreeMap, btree_set::BTreeSet};
+use xcm::v3::{prelude::*, Error as XcmError, Result as XcmResult};
+use xcm_executor::traits::{ConvertLocation, JustTry, TransactAsset};
+use xnft_primitives::{
+    conversion::{
+        BytesAssetInstance, BytesInstanceId, IndexAssetInstance, IndexRangeRouter,
+        InteriorGeneralIndex, SupportedInteriorJunctions,
+    },
+    traits::{DerivativeWithdrawal, ForeignAssetInstance, NftEngine, NftTransactor, Royalty},
+};
+
+use crate::{
+    self as pallet_xnft, LocalDepositMode, MetadataSource, RoyaltySource, XcmContextFilter, XnftOp,
+};
+
+type Block = frame_system::mocking::MockBlock<Test>;
+
+construct_runtime!(
+    pub enum Test {
+        System: frame_system,
+        Xnft: pallet_xnft,
+        BytesIdXnft: pallet_xnft::<Instance2>,
+    }
+);
+
+pub const ALICE: u64 = 1;
+pub const BOB: u64 = 2;
+pub const CHARLIE: u64 = 3;
+pub const PALLET_ACCOUNT: u64 = 1000;
+
+/// The base of the sovereign account IDs of the sibling parachains.
+pub const SIBLING_ACCOUNT_BASE: u64 = 10_000;
+
+/// The parachain hosting the foreign NFT collections.
+pub const RESERVE_PARA_ID: u32 = 1000;
+
+parameter_types! {
+    pub const XnftPalletId: PalletId = PalletId(*b"py/xnft_");
+}
+
+parameter_types! {
+    /// The database weight of the mock runtime, free by default.
+    pub static MockDbWeight: RuntimeDbWeight = RuntimeDbWeight { read: 0, write: 0 };
+}
+
+impl frame_system::Config for Test {
+    type BaseCallFilter = Everything;
+    type BlockWeights = ();
+    type BlockLength = ();
+    type DbWeight = MockDbWeight;
+    type RuntimeOrigin = RuntimeOrigin;
+    type RuntimeCall = RuntimeCall;
+    type Nonce = u64;
+    type Hash = H256;
+    type Hashing = BlakeTwo256;
+    type AccountId = u64;
+    type Lookup = IdentityLookup<Self::AccountId>;
+    type Block = Block;
+    type RuntimeEvent = RuntimeEvent;
+    type BlockHashCount = ConstU64<250>;
+    type Version = ();
+    type PalletInfo = PalletInfo;
+    type AccountData = ();
+    type OnNewAccount = ();
+    type OnKilledAccount = ();
+    type SystemWeightInfo = ();
+    type SS58Prefix = ();
+    type OnSetCode = ();
+    type MaxConsumers = ConstU32<16>;
+}
+
+parameter_types! {
+    pub static UniversalLocation: InteriorMultiLocation =
+        X2(GlobalConsensus(NetworkId::Polkadot), Parachain(2000));
+    pub static LocalClassPrefix: InteriorMultiLocation = X1(PalletInstance(42));
+    pub static UnconvertibleDestinationEscrow: Option<u64> = None;
+    pub static MinForeignParents: u8 = 1;
+    pub static DeduplicateByMessageId: bool = false;
+    pub static ObserverMode: bool = false;
+    pub static MaxRegistrationsPerWindow: Option<u32> = None;
+    pub static MaxDerivativesPerClass: Option<u32> = None;
+    pub static RecordFailedMints: bool = false;
+    pub static InstanceSharding: bool = false;
+    pub static ClassLevelTransfer: bool = false;
+    pub static LocalDeposit: LocalDepositMode = LocalDepositMode::TransferOnly;
+    pub static DeriveReverseMapping: bool = false;
+    pub static PreserveIdAcrossBurn: bool = false;
+    pub static AllowApprovedOperators: bool = false;
+    pub static IndexOnlyForeignInstances: bool = false;
+    pub static EmitBlockSummary: bool = false;
+    pub static AcceptForeignNetworkAccounts: bool = false;
+}
+
+impl pallet_xnft::Config for Test {
+    type RuntimeEvent = RuntimeEvent;
+    type WeightInfo = ();
+    type NftEngine = MockNftEngine;
+    type PalletAccountId = ConstU64<PALLET_ACCOUNT>;
+    type PerClassCustody = ConstBool<false>;
+    type PalletId = XnftPalletId;
+    type LocalAssetIdConvert = InteriorGeneralIndex<LocalClassPrefix, u32, JustTry>;
+    type AssetInstanceConvert = IndexAssetInstance<u32, JustTry>;
+    type UniversalLocation = UniversalLocation;
+    type LocationToAccountId = MockLocationToAccountId;
+    type AcceptForeignNetworkAccounts = AcceptForeignNetworkAccounts;
+    type UnconvertibleDestinationEscrow = UnconvertibleDestinationEscrow;
+    type DynamicTrustModel = MockTrustModel;
+    type XcmContextFilter = MockXcmContextFilter;
+    type AllowedInteriorJunctions = SupportedInteriorJunctions;
+    type ForeignAssetRegisterOrigin =
+        AsEnsureOriginWithArg<EitherOfDiverse<EnsureRoot<u64>, EnsureSigned<u64>>>;
+    type ForeignAssetAdminOrigin = EnsureRoot<u64>;
+    type SignerToNftEngineAccount = Identity;
+    type DispatchErrorsConvert = ();
+    type MinForeignParents = MinForeignParents;
+    type BulkWithdrawOrigin = EnsureSigned<u64>;
+    type MaxBulkWithdraw = ConstU32<4>;
+    type MaxWithdrawalDestinations = ConstU32<2>;
+    type DerivativeClassIdTransform = MockClassIdTransform;
+    type DeduplicateByMessageId = DeduplicateByMessageId;
+    type ProcessedDepositTtl = ConstU64<10>;
+    type ObserverMode = ObserverMode;
+    type ReregistrationCooldown = ConstU64<5>;
+    type MaxRegistrationsPerWindow = MaxRegistrationsPerWindow;
+    type MaxDerivativesPerClass = MaxDerivativesPerClass;
+    type RecordFailedMints = RecordFailedMints;
+    type RegistrationWindow = ConstU64<10>;
+    type InstanceSharding = InstanceSharding;
+    type InstanceRangeRouter = IndexRangeRouter<ConstU128<100>>;
+    type ClassLevelTransfer = ClassLevelTransfer;
+    type MaxClassLevelWithdrawal = ConstU32<3>;
+    type MaxDeregistrationDerivatives = ConstU32<3>;
+    type MetadataLimit = ConstU32<32>;
+    type LocalDepositMode = LocalDeposit;
+    type RoyaltySource = MockRoyaltySource;
+    type MetadataSource = MockMetadataSource;
+    type OnDerivativeMinted = ();
+    type FallbackTransactor = MockFallbackTransactor;
+    type DeriveReverseMapping = DeriveReverseMapping;
+    type DerivativeInstanceConvert = IndexAssetInstance<u32, JustTry>;
+    type PreserveIdAcrossBurn = PreserveIdAcrossBurn;
+    type AllowApprovedOperators = AllowApprovedOperators;
+    type PauseRegistrations = ConstBool<true>;
+    type IndexOnlyForeignInstances = IndexOnlyForeignInstances;
+    type EmitInstanceEvents = ConstBool<true>;
+    type EmitBlockSummary = EmitBlockSummary;
+    #[cfg(feature = "runtime-benchmarks")]
+    type BenchmarkHelper = MockNftEngine;
+}
+
+/// The instance ID no XCM asset instance can represent.
+pub type WideInstanceId = [u8; 48];
+
+/// The pallet instance whose NFT engine uses the [`WideInstanceId`].
+///
+/// It isn't a part of the runtime, so only its integrity test can be run.
+pub type WideIdXnft = pallet_xnft::Pallet<Test, pallet_xnft::Instance1>;
+
+// The wide ID instance never deposits events since it is never dispatched.
+impl From<pallet_xnft::Event<Test, pallet_xnft::Instance1>> for RuntimeEvent {
+    fn from(_event: pallet_xnft::Event<Test, pallet_xnft::Instance1>) -> Self {
+        unreachable!("the wide ID xnft instance isn't a part of the runtime")
+    }
+}
+
+impl pallet_xnft::Config<pallet_xnft::Instance1> for Test {
+    type RuntimeEvent = RuntimeEvent;
+    type WeightInfo = ();
+    type NftEngine = WideIdNftEngine;
+    type PalletAccountId = ConstU64<PALLET_ACCOUNT>;
+    type PerClassCustody = ConstBool<false>;
+    type PalletId = XnftPalletId;
+    type LocalAssetIdConvert = InteriorGeneralIndex<LocalClassPrefix, u32, JustTry>;
+    type AssetInstanceConvert = WideInstanceConvert;
+    type UniversalLocation = UniversalLocation;
+    type LocationToAccountId = MockLocationToAccountId;
+    type AcceptForeignNetworkAccounts = ConstBool<false>;
+    type UnconvertibleDestinationEscrow = UnconvertibleDestinationEscrow;
+    type DynamicTrustModel = Nothing;
+    type XcmContextFilter = MockXcmContextFilter;
+    type AllowedInteriorJunctions = SupportedInteriorJunctions;
+    type ForeignAssetRegisterOrigin =
+        AsEnsureOriginWithArg<EitherOfDiverse<EnsureRoot<u64>, EnsureSigned<u64>>>;
+    type ForeignAssetAdminOrigin = EnsureRoot<u64>;
+    type SignerToNftEngineAccount = Identity;
+    type DispatchErrorsConvert = ();
+    type MinForeignParents = MinForeignParents;
+    type BulkWithdrawOrigin = EnsureSigned<u64>;
+    type MaxBulkWithdraw = ConstU32<4>;
+    type MaxWithdrawalDestinations = ConstU32<2>;
+    type DerivativeClassIdTransform = Identity;
+    type DeduplicateByMessageId = DeduplicateByMessageId;
+    type ProcessedDepositTtl = ConstU64<10>;
+    type ObserverMode = ObserverMode;
+    type ReregistrationCooldown = ConstU64<5>;
+    type MaxRegistrationsPerWindow = MaxRegistrationsPerWindow;
+    type MaxDerivativesPerClass = MaxDerivativesPerClass;
+    type RecordFailedMints = RecordFailedMints;
+    type RegistrationWindow = ConstU64<10>;
+    type InstanceSharding = InstanceSharding;
+    type InstanceRangeRouter = IndexRangeRouter<ConstU128<100>>;
+    type ClassLevelTransfer = ClassLevelTransfer;
+    type MaxClassLevelWithdrawal = ConstU32<3>;
+    type MaxDeregistrationDerivatives = ConstU32<3>;
+    type MetadataLimit = ConstU32<32>;
+    type LocalDepositMode = LocalDeposit;
+    type RoyaltySource = MockRoyaltySource;
+    type MetadataSource = MockMetadataSource;
+    type OnDerivativeMinted = ();
+    type FallbackTransactor = MockFallbackTransactor;
+    type DeriveReverseMapping = DeriveReverseMapping;
+    type DerivativeInstanceConvert = WideInstanceConvert;
+    type PreserveIdAcrossBurn = PreserveIdAcrossBurn;
+    type AllowApprovedOperators = AllowApprovedOperators;
+    type PauseRegistrations = ConstBool<true>;
+    type IndexOnlyForeignInstances = IndexOnlyForeignInstances;
+    type EmitInstanceEvents = ConstBool<true>;
+    type EmitBlockSummary = EmitBlockSummary;
+    #[cfg(feature = "runtime-benchmarks")]
+    type BenchmarkHelper = WideIdNftEngine;
+}
+
+/// Never converts the [`WideInstanceId`]s.
+pub struct WideInstanceConvert;
+impl MaybeEquivalence<AssetInstance, WideInstanceId> for WideInstanceConvert {
+    fn convert(_asset_instance: &AssetInstance) -> Option<WideInstanceId> {
+        None
+    }
+
+    fn convert_back(_instance_id: &WideInstanceId) -> Option<AssetInstance> {
+        None
+    }
+}
+
+/// The NFT engine with no classes and instances using the [`WideInstanceId`].
+pub struct WideIdNftEngine;
+
+impl NftTransactor for WideIdNftEngine {
+    type AccountId = u64;
+    type ClassId = u32;
+    type InstanceId = WideInstanceId;
+
+    fn transfer_class_instance(
+        _class_id: &u32,
+        _instance_id: &WideInstanceId,
+        _from: &u64,
+        _to: &u64,
+    ) -> DispatchResult {
+        Err(DispatchError::Other("the instance doesn't exist"))
+    }
+
+    fn class_instance_owner(_class_id: &u32, _instance_id: &WideInstanceId) -> Option<u64> {
+        None
+    }
+
+    fn mint_derivative(
+        _class_id: &u32,
+        _to: &u64,
+        _instance_id_hint: Option<&WideInstanceId>,
+    ) -> Result<WideInstanceId, DispatchError> {
+        Err(DispatchError::Other("the class doesn't exist"))
+    }
+
+    fn withdraw_derivative(
+        _class_id: &u32,
+        _instance_id: &WideInstanceId,
+        _from: &u64,
+        _foreign_asset_instance: &ForeignAssetInstance,
+    ) -> Result<DerivativeWithdrawal, DispatchError> {
+        Err(DispatchError::Other("the instance doesn't exist"))
+    }
+}
+
+impl NftEngine for WideIdNftEngine {
+    type Transactor = Self;
+    type ClassInitData = ();
+
+    fn create_class_weight(_data: &()) -> Weight {
+        Weight::zero()
+    }
+
+    fn create_class(_owner: &u64, _data: ()) -> Result<u32, DispatchError> {
+        Err(DispatchError::Other("the class creation isn't supported"))
+    }
+
+    fn class_owner(_class_id: &u32) -> Option<u64> {
+        None
+    }
+
+    fn set_class_owner_weight() -> Weight {
+        Weight::zero()
+    }
+
+    fn set_class_owner(_class_id: &u32, _new_owner: &u64) -> DispatchResult {
+        Err(DispatchError::Other("the class doesn't exist"))
+    }
+}
+
+#[cfg(feature = "runtime-benchmarks")]
+impl crate::benchmarking::BenchmarkHelper<u32, WideInstanceId, u64> for WideIdNftEngine {
+    fn create_class(_owner: &u64) -> u32 {
+        0
+    }
+
+    fn mint_instance(_class_id: &u32, _owner: &u64) -> WideInstanceId {
+        [0; 48]
+    }
+
+    fn worst_case_dispatch_error() -> DispatchError {
+        DispatchError::Other("the worst case dispatch error")
+    }
+}
+
+// The pallet instance whose NFT engine uses the variable-length `BytesInstanceId`.
+impl pallet_xnft::Config<pallet_xnft::Instance2> for Test {
+    type RuntimeEvent = RuntimeEvent;
+    type WeightInfo = ();
+    type NftEngine = BytesIdNftEngine;
+    type PalletAccountId = ConstU64<PALLET_ACCOUNT>;
+    type PerClassCustody = ConstBool<false>;
+    type PalletId = XnftPalletId;
+    type LocalAssetIdConvert = InteriorGeneralIndex<LocalClassPrefix, u32, JustTry>;
+    type AssetInstanceConvert = BytesAssetInstance;
+    type UniversalLocation = UniversalLocation;
+    type LocationToAccountId = MockLocationToAccountId;
+    type AcceptForeignNetworkAccounts = ConstBool<false>;
+    type UnconvertibleDestinationEscrow = UnconvertibleDestinationEscrow;
+    type DynamicTrustModel = Nothing;
+    type XcmContextFilter = MockXcmContextFilter;
+    type AllowedInteriorJunctions = SupportedInteriorJunctions;
+    type ForeignAssetRegisterOrigin =
+        AsEnsureOriginWithArg<EitherOfDiverse<EnsureRoot<u64>, EnsureSigned<u64>>>;
+    type ForeignAssetAdminOrigin = EnsureRoot<u64>;
+    type SignerToNftEngineAccount = Identity;
+    type DispatchErrorsConvert = ();
+    type MinForeignParents = MinForeignParents;
+    type BulkWithdrawOrigin = EnsureSigned<u64>;
+    type MaxBulkWithdraw = ConstU32<4>;
+    type MaxWithdrawalDestinations = ConstU32<2>;
+    type DerivativeClassIdTransform = Identity;
+    type DeduplicateByMessageId = DeduplicateByMessageId;
+    type ProcessedDepositTtl = ConstU64<10>;
+    type ObserverMode = ObserverMode;
+    type ReregistrationCooldown = ConstU64<5>;
+    type MaxRegistrationsPerWindow = MaxRegistrationsPerWindow;
+    type MaxDerivativesPerClass = MaxDerivativesPerClass;
+    type RecordFailedMints = RecordFailedMints;
+    type RegistrationWindow = ConstU64<10>;
+    type InstanceSharding = InstanceSharding;
+    type InstanceRangeRouter = IndexRangeRouter<ConstU128<100>>;
+    type ClassLevelTransfer = ClassLevelTransfer;
+    type MaxClassLevelWithdrawal = ConstU32<3>;
+    type MaxDeregistrationDerivatives = ConstU32<3>;
+    type MetadataLimit = ConstU32<32>;
+    type LocalDepositMode = LocalDeposit;
+    type RoyaltySource = ();
+    type MetadataSource = ();
+    type OnDerivativeMinted = ();
+    type FallbackTransactor = MockFallbackTransactor;
+    type DeriveReverseMapping = DeriveReverseMapping;
+    type DerivativeInstanceConvert = BytesAssetInstance;
+    type PreserveIdAcrossBurn = PreserveIdAcrossBurn;
+    type AllowApprovedOperators = AllowApprovedOperators;
+    type PauseRegistrations = ConstBool<true>;
+    type IndexOnlyForeignInstances = IndexOnlyForeignInstances;
+    type EmitInstanceEvents = ConstBool<true>;
+    type EmitBlockSummary = EmitBlockSummary;
+    #[cfg(feature = "runtime-benchmarks")]
+    type BenchmarkHelper = BytesIdNftEngine;
+}
+
+/// The state of the [`BytesIdNftEngine`].
+#[derive(Encode, Decode, Default)]
+pub struct BytesIdNftState {
+    next_class_id: u32,
+    class_owners: BTreeMap<u32, u64>,
+    next_instance_id: u32,
+    instance_owners: BTreeMap<(u32, BytesInstanceId), u64>,
+}
+
+const BYTES_ID_NFT_STATE_KEY: &[u8] = b":xnft:mock:bytes_id_nft_state";
+
+fn bytes_id_nft_state() -> BytesIdNftState {
+    unhashed::get_or_default(BYTES_ID_NFT_STATE_KEY)
+}
+
+fn mutate_bytes_id_nft_state<R>(mutate: impl FnOnce(&mut BytesIdNftState) -> R) -> R {
+    let mut state = bytes_id_nft_state();
+    let result = mutate(&mut state);
+    unhashed::put(BYTES_ID_NFT_STATE_KEY, &state);
+
+    result
+}
+
+/// The NFT engine using the variable-length [`BytesInstanceId`]s.
+///
+/// It mints the derivatives at the instance ID hint if it is free,
+/// otherwise at the next 4-byte ID.
+pub struct BytesIdNftEngine;
+
+impl BytesIdNftEngine {
+    /// Creates a new class owned by the `owner`.
+    pub fn create(owner: u64) -> u32 {
+        <Self as NftEngine>::create_class(&owner, ()).expect("the class creation never fails")
+    }
+
+    /// Mints the instance out-of-band.
+    pub fn mint(class_id: u32, instance_id: BytesInstanceId, owner: u64) {
+        mutate_bytes_id_nft_state(|state| {
+            state.instance_owners.insert((class_id, instance_id), owner)
+        });
+    }
+}
+
+impl NftTransactor for BytesIdNftEngine {
+    type AccountId = u64;
+    type ClassId = u32;
+    type InstanceId = BytesInstanceId;
+
+    fn transfer_class_instance(
+        class_id: &u32,
+        instance_id: &BytesInstanceId,
+        from: &u64,
+        to: &u64,
+    ) -> DispatchResult {
+        mutate_bytes_id_nft_state(|state| {
+            let owner = state
+                .instance_owners
+                .get_mut(&(*class_id, instance_id.clone()))
+                .ok_or(DispatchError::Other("the instance doesn't exist"))?;
+
+            ensure!(
+                owner == from,
+                DispatchError::Other("the instance isn't owned by the account"),
+            );
+
+            *owner = *to;
+
+            Ok(())
+        })
+    }
+
+    fn class_instance_owner(class_id: &u32, instance_id: &BytesInstanceId) -> Option<u64> {
+        bytes_id_nft_state()
+            .instance_owners
+            .get(&(*class_id, instance_id.clone()))
+            .copied()
+    }
+
+    fn mint_derivative(
+        class_id: &u32,
+        to: &u64,
+        instance_id_hint: Option<&BytesInstanceId>,
+    ) -> Result<BytesInstanceId, DispatchError> {
+        mutate_bytes_id_nft_state(|state| {
+            ensure!(
+                state.class_owners.contains_key(class_id),
+                DispatchError::Other("the class doesn't exist"),
+            );
+
+            let instance_id = match instance_id_hint {
+                Some(hint)
+                    if !state
+                        .instance_owners
+                        .contains_key(&(*class_id, hint.clone())) =>
+                {
+                    hint.clone()
+                }
+                _ => {
+                    let instance_id = state.next_instance_id;
+                    state.next_instance_id += 1;
+
+                    BytesInstanceId::truncate_from(instance_id.to_be_bytes().to_vec())
+                }
+            };
+
+            state
+                .instance_owners
+                .insert((*class_id, instance_id.clone()), *to);
+
+            Ok(instance_id)
+        })
+    }
+
+    fn withdraw_derivative(
+        class_id: &u32,
+        instance_id: &BytesInstanceId,
+        from: &u64,
+        _foreign_asset_instance: &ForeignAssetInstance,
+    ) -> Result<DerivativeWithdrawal, DispatchError> {
+        mutate_bytes_id_nft_state(|state| {
+            let key = (*class_id, instance_id.clone());
+
+            ensure!(
+                state.instance_owners.get(&key) == Some(from),
+                DispatchError::Other("the instance isn't owned by the account"),
+            );
+
+            state.instance_owners.remove(&key);
+
+            Ok(DerivativeWithdrawal::Burned)
+        })
+    }
+}
+
+impl NftEngine for BytesIdNftEngine {
+    type Transactor = Self;
+    type ClassInitData = ();
+
+    fn create_class_weight(_data: &()) -> Weight {
+        Weight::zero()
+    }
+
+    fn create_class(owner: &u64, _data: ()) -> Result<u32, DispatchError> {
+        Ok(mutate_bytes_id_nft_state(|state| {
+            let class_id = state.next_class_id;
+            state.next_class_id += 1;
+            state.class_owners.insert(class_id, *owner);
+
+            class_id
+        }))
+    }
+
+    fn class_owner(class_id: &u32) -> Option<u64> {
+        bytes_id_nft_state().class_owners.get(class_id).copied()
+    }
+
+    fn set_class_owner_weight() -> Weight {
+        Weight::zero()
+    }
+
+    fn set_class_owner(class_id: &u32, new_owner: &u64) -> DispatchResult {
+        mutate_bytes_id_nft_state(|state| {
+            let owner = state
+                .class_owners
+                .get_mut(class_id)
+                .ok_or(DispatchError::Other("the class doesn't exist"))?;
+            *owner = *new_owner;
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "runtime-benchmarks")]
+impl crate::benchmarking::BenchmarkHelper<u32, BytesInstanceId, u64> for BytesIdNftEngine {
+    fn create_class(owner: &u64) -> u32 {
+        Self::create(*owner)
+    }
+
+    fn mint_instance(class_id: &u32, owner: &u64) -> BytesInstanceId {
+        Self::mint_derivative(class_id, owner, None).expect("the class exists")
+    }
+
+    fn worst_case_dispatch_error() -> DispatchError {
+        DispatchError::Other("the worst case dispatch error")
+    }
+}
+
+/// Converts the `AccountIndex64` locations and the sibling parachains into the test accounts.
+pub struct MockLocationToAccountId;
+impl ConvertLocation<u64> for MockLocationToAccountId {
+    fn convert_location(location: &MultiLocation) -> Option<u64> {
+        match location {
+            MultiLocation {
+                parents: 0,
+                interior:
+                    X1(AccountIndex64 {
+                        network: None,
+                        index,
+                    }),
+            } => Some(*index),
+            MultiLocation {
+                parents: 1,
+                interior: X1(Parachain(para_id)),
+            } => Some(SIBLING_ACCOUNT_BASE + u64::from(*para_id)),
+            _ => None,
+        }
+    }
+}
+
+parameter_types! {
+    /// The XCM origins whose NFT operations the `MockXcmContextFilter` rejects.
+    pub static DisallowedOrigins: Vec<MultiLocation> = vec![];
+
+    /// The operations the `MockXcmContextFilter` was consulted about.
+    pub static FilteredOps: Vec<XnftOp> = vec![];
+}
+
+/// Records the filtered operations and rejects the ones from the `DisallowedOrigins`.
+pub struct MockXcmContextFilter;
+impl XcmContextFilter for MockXcmContextFilter {
+    fn filter(context: Option<&XcmContext>, op: XnftOp) -> Result<(), XcmError> {
+        let mut ops = FilteredOps::get();
+        ops.push(op);
+        FilteredOps::set(ops);
+
+        let origin = context.and_then(|context| context.origin);
+        if origin.map_or(false, |origin| DisallowedOrigins::get().contains(&origin)) {
+            return Err(XcmError::NoPermission);
+        }
+
+        Ok(())
+    }
+}
+
+parameter_types! {
+    /// The XCM origins the `MockTrustModel` treats as the teleporters of every asset.
+    pub static TeleportOrigins: Vec<MultiLocation> = vec![];
+}
+
+/// Treats the messages from the `TeleportOrigins` as teleports.
+pub struct MockTrustModel;
+impl Contains<(MultiLocation, AssetId)> for MockTrustModel {
+    fn contains((origin, _asset_id): &(MultiLocation, AssetId)) -> bool {
+        TeleportOrigins::get().contains(origin)
+    }
+}
+
+parameter_types! {
+    /// The tag the `MockClassIdTransform` puts on the stored derivative class IDs.
+    pub static ClassIdTag: u32 = 0;
+}
+
+/// Tags the stored derivative class IDs by XOR-ing them with the `ClassIdTag`.
+///
+/// XOR is its own inverse, so the transform is a bijection for any tag.
+pub struct MockClassIdTransform;
+impl Convert<u32, u32> for MockClassIdTransform {
+    fn convert(class_id: u32) -> u32 {
+        class_id ^ ClassIdTag::get()
+    }
+}
+impl ConvertBack<u32, u32> for MockClassIdTransform {
+    fn convert_back(stored_class_id: u32) -> u32 {
+        stored_class_id ^ ClassIdTag::get()
+    }
+}
+
+parameter_types! {
+    /// The royalty the `MockRoyaltySource` reports for every incoming asset.
+    pub static IncomingRoyalty: Option<Royalty<u64>> = None;
+}
+
+pub struct MockRoyaltySource;
+impl RoyaltySource<u64> for MockRoyaltySource {
+    fn royalty(_asset: &MultiAsset, _context: Option<&XcmContext>) -> Option<Royalty<u64>> {
+        IncomingRoyalty::get()
+    }
+}
+
+parameter_types! {
+    /// The metadata the `MockMetadataSource` reports for every incoming asset.
+    pub static IncomingMetadata: Option<Vec<u8>> = None;
+}
+
+pub struct MockMetadataSource;
+impl MetadataSource for MockMetadataSource {
+    fn metadata(_asset: &MultiAsset, _context: Option<&XcmContext>) -> Option<Vec<u8>> {
+        IncomingMetadata::get()
+    }
+}
+
+parameter_types! {
+    /// Whether the `MockFallbackTransactor` accepts the deposits.
+    pub static FallbackEnabled: bool = false;
+
+    /// The deposits the `MockFallbackTransactor` accepted.
+    pub static FallbackDeposits: Vec<(MultiAsset, MultiLocation)> = vec![];
+}
+
+/// Records the deposits delegated to it while the `FallbackEnabled` is set.
+pub struct MockFallbackTransactor;
+impl TransactAsset for MockFallbackTransactor {
+    fn deposit_asset(
+        what: &MultiAsset,
+        who: &MultiLocation,
+        _context: Option<&XcmContext>,
+    ) -> XcmResult {
+        if !FallbackEnabled::get() {
+            return Err(XcmError::AssetNotFound);
+        }
+
+        let mut deposits = FallbackDeposits::get();
+        deposits.push((what.clone(), *who));
+        FallbackDeposits::set(deposits);
+
+        Ok(())
+    }
+}
+
+parameter_types! {
+    /// Whether the `MockNftEngine` stashes the withdrawn derivatives instead of burning them.
+    pub static StashDerivatives: bool = true;
+
+    /// Whether the `MockNftEngine` mints the derivatives at the instance ID hint if it is free.
+    pub static HonorInstanceIdHint: bool = false;
+
+    /// The class the misbehaving `MockNftEngine` mints the derivatives into
+    /// instead of the requested one.
+    pub static MintIntoClass: Option<u32> = None;
+
+    /// The reason the `MockNftEngine` vetoes every derivative mint with, if any.
+    pub static MintVeto: Option<&'static str> = None;
+
+    /// The reason the `MockNftEngine` fails every derivative mint with, if any,
+    /// after letting it pass the `can_mint` check.
+    pub static MintError: Option<&'static str> = None;
+
+    /// Whether the `MockNftEngine` mints the derivatives with their metadata in a single step.
+    pub static CombinedMint: bool = false;
+}
+
+/// The state of the [`MockNftEngine`].
+#[derive(Encode, Decode, Default)]
+pub struct NftState {
+    next_class_id: u32,
+    class_owners: BTreeMap<u32, u64>,
+    frozen_classes: BTreeSet<u32>,
+    next_instance_ids: BTreeMap<u32, u32>,
+    instance_owners: BTreeMap<(u32, u32), u64>,
+    approvals: BTreeMap<(u32, u32), u64>,
+    royalties: BTreeMap<(u32, u32), (u64, u16)>,
+    metadata: BTreeMap<(u32, u32), Vec<u8>>,
+    reserved_ranges: BTreeMap<u32, (u32, u32)>,
+}
+
+impl NftState {
+    fn ensure_operational(&self, class_id: &u32) -> DispatchResult {
+        ensure!(
+            self.class_owners.contains_key(class_id),
+            DispatchError::Other("the class doesn't exist"),
+        );
+
+        ensure!(
+            !self.frozen_classes.contains(class_id),
+            DispatchError::Other("the class is frozen"),
+        );
+
+        Ok(())
+    }
+
+    fn ensure_owner(&self, class_id: &u32, instance_id: &u32, who: &u64) -> DispatchResult {
+        match self.instance_owners.get(&(*class_id, *instance_id)) {
+            Some(owner) if owner == who => Ok(()),
+            Some(_) => Err(DispatchError::Other(
+                "the instance isn't owned by the account",
+            )),
+            None => Err(DispatchError::Other("the instance doesn't exist")),
+        }
+    }
+
+    fn allocate_instance_id(&mut self, class_id: u32) -> u32 {
+        let next_instance_id = self.next_instance_ids.entry(class_id).or_default();
+
+        while self
+            .instance_owners
+            .contains_key(&(class_id, *next_instance_id))
+        {
+            *next_instance_id += 1;
+        }
+
+        let instance_id = *next_instance_id;
+        *next_instance_id += 1;
+
+        instance_id
+    }
+
+    fn take_reserved_instance_id(&mut self, class_id: u32) -> Option<u32> {
+        loop {
+            let (start, end) = self.reserved_ranges.get_mut(&class_id)?;
+            let instance_id = *start;
+
+            *start += 1;
+            if start == end {
+                self.reserved_ranges.remove(&class_id);
+            }
+
+            // The reserved ID could be taken by a mint at an explicit instance ID.
+            if !self.instance_owners.contains_key(&(class_id, instance_id)) {
+                return Some(instance_id);
+            }
+        }
+    }
+
+    fn mint_derivative(
+        &mut self,
+        class_id: &u32,
+        to: &u64,
+        instance_id_hint: Option<&u32>,
+    ) -> Result<u32, DispatchError> {
+        let class_id = &MintIntoClass::get().unwrap_or(*class_id);
+
+        self.ensure_operational(class_id)?;
+
+        if let Some(reason) = MintError::get() {
+            return Err(DispatchError::Other(reason));
+        }
+
+        let instance_id = match instance_id_hint.filter(|_| HonorInstanceIdHint::get()) {
+            Some(hint) if !self.instance_owners.contains_key(&(*class_id, *hint)) => *hint,
+            _ => self
+                .take_reserved_instance_id(*class_id)
+                .unwrap_or_else(|| self.allocate_instance_id(*class_id)),
+        };
+
+        self.instance_owners.insert((*class_id, instance_id), *to);
+
+        Ok(instance_id)
+    }
+}
+
+const NFT_STATE_KEY: &[u8] = b":xnft:mock:nft_state";
+
+fn nft_state() -> NftState {
+    unhashed::get_or_default(NFT_STATE_KEY)
+}
+
+fn mutate_nft_state<R>(mutate: impl FnOnce(&mut NftState) -> R) -> R {
+    let mut state = nft_state();
+    let result = mutate(&mut state);
+    unhashed::put(NFT_STATE_KEY, &state);
+
+    result
+}
+
+/// The NFT engine keeping its classes and instances in `BTreeMap`s.
+///
+/// A frozen class rejects all the mints, transfers, and withdrawals.
+pub struct MockNftEngine;
+
+impl MockNftEngine {
+    /// Creates a new class owned by the `owner`.
+    pub fn create(owner: u64) -> u32 {
+        Self::create_class(&owner, ()).expect("the mock engine always creates a class")
+    }
+
+    /// Mints a new instance within the `class_id` class to the `owner`.
+    pub fn mint(class_id: u32, owner: u64) -> u32 {
+        mutate_nft_state(|state| {
+            let instance_id = state.allocate_instance_id(class_id);
+            state.instance_owners.insert((class_id, instance_id), owner);
+
+            instance_id
+        })
+    }
+
+    /// Burns the instance out-of-band.
+    pub fn burn(class_id: u32, instance_id: u32) {
+        mutate_nft_state(|state| {
+            state.instance_owners.remove(&(class_id, instance_id));
+            state.approvals.remove(&(class_id, instance_id));
+        });
+    }
+
+    /// Freezes the class.
+    pub fn freeze(class_id: u32) {
+        mutate_nft_state(|state| state.frozen_classes.insert(class_id));
+    }
+
+    /// Unfreezes the class.
+    pub fn unfreeze(class_id: u32) {
+        mutate_nft_state(|state| state.frozen_classes.remove(&class_id));
+    }
+
+    /// Approves the `operator` to transfer the instance on behalf of its owner.
+    pub fn approve(class_id: u32, instance_id: u32, operator: u64) {
+        mutate_nft_state(|state| state.approvals.insert((class_id, instance_id), operator));
+    }
+
+    /// Returns the owner of the instance.
+    pub fn owner(class_id: u32, instance_id: u32) -> Option<u64> {
+        nft_state()
+            .instance_owners
+            .get(&(class_id, instance_id))
+            .copied()
+    }
+
+    /// Returns the `(creator, basis points)` royalty of the instance.
+    pub fn royalty(class_id: u32, instance_id: u32) -> Option<(u64, u16)> {
+        nft_state().royalties.get(&(class_id, instance_id)).copied()
+    }
+
+    /// Returns the `start..end` instance IDs still reserved for the derivatives of the class.
+    pub fn reserved_range(class_id: u32) -> Option<(u32, u32)> {
+        nft_state().reserved_ranges.get(&class_id).copied()
+    }
+
+    /// Returns the metadata of the instance.
+    pub fn metadata(class_id: u32, instance_id: u32) -> Option<Vec<u8>> {
+        nft_state().metadata.get(&(class_id, instance_id)).cloned()
+    }
+
+    /// Returns the number of the existing instances within the class.
+    pub fn instance_count(class_id: u32) -> usize {
+        nft_state()
+            .instance_owners
+            .keys()
+            .filter(|(instance_class_id, _)| *instance_class_id == class_id)
+            .count()
+    }
+}
+
+impl NftTransactor for MockNftEngine {
+    type AccountId = u64;
+    type ClassId = u32;
+    type InstanceId = u32;
+
+    fn transfer_class_instance(
+        class_id: &u32,
+        instance_id: &u32,
+        from: &u64,
+        to: &u64,
+    ) -> DispatchResult {
+        mutate_nft_state(|state| {
+            state.ensure_operational(class_id)?;
+            state.ensure_owner(class_id, instance_id, from)?;
+
+            state.instance_owners.insert((*class_id, *instance_id), *to);
+            state.approvals.remove(&(*class_id, *instance_id));
+
+            Ok(())
+        })
+    }
+
+    fn class_instance_owner(class_id: &u32, instance_id: &u32) -> Option<u64> {
+        Self::owner(*class_id, *instance_id)
+    }
+
+    fn is_approved_or_owner(class_id: &u32, instance_id: &u32, who: &u64) -> bool {
+        let state = nft_state();
+        let key = (*class_id, *instance_id);
+
+        state.instance_owners.get(&key) == Some(who) || state.approvals.get(&key) == Some(who)
+    }
+
+    fn can_mint(class_id: &u32) -> DispatchResult {
+        if let Some(reason) = MintVeto::get() {
+            return Err(DispatchError::Other(reason));
+        }
+
+        nft_state().ensure_operational(class_id)
+    }
+
+    fn mint_derivative(
+        class_id: &u32,
+        to: &u64,
+        instance_id_hint: Option<&u32>,
+    ) -> Result<u32, DispatchError> {
+        mutate_nft_state(|state| state.mint_derivative(class_id, to, instance_id_hint))
+    }
+
+    fn set_derivative_metadata(
+        class_id: &u32,
+        instance_id: &u32,
+        metadata: &[u8],
+    ) -> DispatchResult {
+        mutate_nft_state(|state| {
+            state
+                .metadata
+                .insert((*class_id, *instance_id), metadata.to_vec());
+        });
+
+        Ok(())
+    }
+
+    fn mint_derivative_full(
+        class_id: &u32,
+        to: &u64,
+        instance_id_hint: Option<&u32>,
+        metadata: Option<&[u8]>,
+    ) -> Result<u32, DispatchError> {
+        if !CombinedMint::get() {
+            let instance_id = Self::mint_derivative(class_id, to, instance_id_hint)?;
+
+            if let Some(metadata) = metadata {
+                Self::set_derivative_metadata(class_id, &instance_id, metadata)?;
+            }
+
+            return Ok(instance_id);
+        }
+
+        mutate_nft_state(|state| {
+            let instance_id = state.mint_derivative(class_id, to, instance_id_hint)?;
+
+            if let Some(metadata) = metadata {
+                state
+                    .metadata
+                    .insert((*class_id, instance_id), metadata.to_vec());
+            }
+
+            Ok(instance_id)
+        })
+    }
+
+    fn set_derivative_royalty(
+        class_id: &u32,
+        instance_id: &u32,
+        creator: &u64,
+        basis_points: u16,
+    ) -> DispatchResult {
+        mutate_nft_state(|state| {
+            state
+                .royalties
+                .insert((*class_id, *instance_id), (*creator, basis_points));
+        });
+
+        Ok(())
+    }
+
+    fn withdraw_derivative(
+        class_id: &u32,
+        instance_id: &u32,
+        from: &u64,
+        _foreign_asset_instance: &ForeignAssetInstance,
+    ) -> Result<DerivativeWithdrawal, DispatchError> {
+        mutate_nft_state(|state| {
+            state.ensure_operational(class_id)?;
+            state.ensure_owner(class_id, instance_id, from)?;
+
+            if StashDerivatives::get() {
+                return Ok(DerivativeWithdrawal::Stash);
+            }
+
+            state.instance_owners.remove(&(*class_id, *instance_id));
+            state.approvals.remove(&(*class_id, *instance_id));
+
+            Ok(DerivativeWithdrawal::Burned)
+        })
+    }
+}
+
+impl NftEngine for MockNftEngine {
+    type Transactor = Self;
+    type ClassInitData = ();
+
+    fn create_class_weight(_data: &()) -> Weight {
+        Weight::zero()
+    }
+
+    fn create_class(owner: &u64, _data: ()) -> Result<u32, DispatchError> {
+        Ok(mutate_nft_state(|state| {
+            let class_id = state.next_class_id;
+            state.next_class_id += 1;
+            state.class_owners.insert(class_id, *owner);
+
+            class_id
+        }))
+    }
+
+    fn class_owner(class_id: &u32) -> Option<u64> {
+        nft_state().class_owners.get(class_id).copied()
+    }
+
+    fn is_class_empty(class_id: &u32) -> bool {
+        MockNftEngine::instance_count(*class_id) == 0
+    }
+
+    fn is_class_operational(class_id: &u32) -> bool {
+        nft_state().ensure_operational(class_id).is_ok()
+    }
+
+    fn can_transfer(class_id: &u32, _instance_id: &u32, _from: &u64, _to: &u64) -> DispatchResult {
+        nft_state().ensure_operational(class_id)
+    }
+
+    fn mint_local(class_id: &u32, instance_id: &u32, to: &u64) -> DispatchResult {
+        mutate_nft_state(|state| {
+            state.ensure_operational(class_id)?;
+
+            ensure!(
+                !state
+                    .instance_owners
+                    .contains_key(&(*class_id, *instance_id)),
+                DispatchError::Other("the instance already exists"),
+            );
+
+            state.instance_owners.insert((*class_id, *instance_id), *to);
+
+            Ok(())
+        })
+    }
+
+    fn set_class_owner_weight() -> Weight {
+        Weight::zero()
+    }
+
+    fn set_class_owner(class_id: &u32, new_owner: &u64) -> DispatchResult {
+        mutate_nft_state(|state| {
+            let owner = state
+                .class_owners
+                .get_mut(class_id)
+                .ok_or(DispatchError::Other("the class doesn't exist"))?;
+            *owner = *new_owner;
+
+            Ok(())
+        })
+    }
+
+    fn reserve_instance_range(class_id: &u32, count: u32) -> DispatchResult {
+        mutate_nft_state(|state| {
+            state.ensure_operational(class_id)?;
+
+            ensure!(
+                !state.reserved_ranges.contains_key(class_id),
+                DispatchError::Other("the class already has a reserved range"),
+            );
+
+            if count == 0 {
+                return Ok(());
+            }
+
+            let start = state.allocate_instance_id(*class_id);
+            let next_instance_id = state.next_instance_ids.entry(*class_id).or_default();
+            *next_instance_id = start.saturating_add(count);
+
+            state
+                .reserved_ranges
+                .insert(*class_id, (start, *next_instance_id));
+
+            Ok(())
+        })
+    }
+}
+
+#[cfg(feature = "runtime-benchmarks")]
+impl crate::benchmarking::BenchmarkHelper<u32, u32, u64> for MockNftEngine {
+    fn create_class(owner: &u64) -> u32 {
+        Self::create(*owner)
+    }
+
+    fn mint_instance(class_id: &u32, owner: &u64) -> u32 {
+        Self::mint(*class_id, *owner)
+    }
+
+    fn worst_case_dispatch_error() -> DispatchError {
+        DispatchError::Other("the worst case dispatch error")
+    }
+
+    fn prepare_derivative_withdrawal(
+        _class_id: &u32,
+        _instance_id: &u32,
+        withdrawal: DerivativeWithdrawal,
+    ) {
+        StashDerivatives::set(matches!(withdrawal, DerivativeWithdrawal::Stash));
+    }
+}
+
+/// Returns the location of the test account.
+pub fn account_location(account: u64) -> MultiLocation {
+    MultiLocation::new(
+        0,
+        X1(AccountIndex64 {
+            network: None,
+            index: account,
+        }),
+    )
+}
+
+/// Returns the location of the parachain hosting the foreign NFT collections.
+pub fn reserve_location() -> MultiLocation {
+    MultiLocation::new(1, X1(Parachain(RESERVE_PARA_ID)))
+}
+
+/// Returns the asset ID of the foreign NFT collection hosted by the reserve parachain.
+pub fn foreign_asset_id(collection: u128) -> AssetId {
+    Concrete(MultiLocation::new(
+        1,
+        X2(Parachain(RESERVE_PARA_ID), GeneralIndex(collection)),
+    ))
+}
+
+/// Returns the asset ID of the local class.
+pub fn local_asset_id(class_id: u32) -> AssetId {
+    let mut interior = LocalClassPrefix::get();
+    interior
+        .push(GeneralIndex(class_id.into()))
+        .expect("the local class prefix is short");
+
+    Concrete(MultiLocation::new(0, interior))
+}
+
+/// Returns the NFT of the `asset_id` collection.
+pub fn nft(asset_id: AssetId, index: u128) -> MultiAsset {
+    MultiAsset {
+        id: asset_id,
+        fun: NonFungible(AssetInstance::Index(index)),
+    }
+}
+
+/// Returns the XCM context of a message sent by the `origin`.
+pub fn xcm_context(origin: MultiLocation) -> XcmContext {
+    XcmContext {
+        origin: Some(origin),
+        message_id: [0; 32],
+        topic: None,
+    }
+}
+
+/// Returns the events deposited by the xnft pallet.
+pub fn xnft_events() -> Vec<pallet_xnft::Event<Test>> {
+    System::events()
+        .into_iter()
+        .filter_map(|record| match record.event {
+            RuntimeEvent::Xnft(event) => Some(event),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn new_test_ext() -> sp_io::TestExternalities {
+    let storage = frame_system::GenesisConfig::<Test>::default()
+        .build_storage()
+        .unwrap();
+
+    let mut ext = sp_io::TestExternalities::new(storage);
+    ext.execute_with(|| System::set_block_number(1));
+
+    ext
+}
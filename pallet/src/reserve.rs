@@ -0,0 +1,35 @@
+//! The reserve locations of the foreign assets registered in the xnft pallet.
+//!
+//! The XCM executor accepts the reserve-backed assets only from the locations
+//! its `IsReserve` filter recognizes as their reserves.
+//! The [`XnftReserveLocations`] filter recognizes the chain containing
+//! a registered foreign asset as its reserve, so it can be used as the `IsReserve` directly.
+
+use frame_support::traits::ContainsPair;
+use sp_std::marker::PhantomData;
+use xcm::v3::prelude::*;
+
+use crate::{transact_asset::chain_location, Config, ForeignAssetShardToLocalClass, Pallet};
+
+/// The `IsReserve` filter recognizing the reserves of the registered foreign assets.
+///
+/// It contains the `(asset, origin)` pair if the asset ID is registered in the `I` xnft pallet
+/// instance (as a whole or by shards) and the `origin` is the chain containing the asset.
+pub struct XnftReserveLocations<T, I = ()>(PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> ContainsPair<MultiAsset, MultiLocation>
+    for XnftReserveLocations<T, I>
+{
+    fn contains(asset: &MultiAsset, origin: &MultiLocation) -> bool {
+        let asset_id = <Pallet<T, I>>::simplify_asset_id(asset.id);
+
+        let Concrete(asset_location) = asset_id else {
+            return false;
+        };
+
+        let is_registered = <Pallet<T, I>>::foreign_asset_to_local_class(asset_id).is_some()
+            || <ForeignAssetShardToLocalClass<T, I>>::contains_prefix(asset_id);
+
+        is_registered && chain_location(&asset_location) == *origin
+    }
+}
@@ -0,0 +1,107 @@
+//! The runtime API of the xnft pallet.
+
+use parity_scale_codec::Codec;
+use sp_runtime::DispatchError;
+use sp_std::vec::Vec;
+use xcm::{v3::Error as XcmError, VersionedAssetId, VersionedMultiAsset};
+
+use crate::{
+    CategorizedClassInstance, ClassInstance, DerivativeStatus, ForeignAssetInstance,
+    StoredAssetInstance as AssetInstance,
+};
+
+/// The categorized class instance as returned by the runtime API.
+pub type CategorizedClassInstanceOf<ClassId, InstanceId> = CategorizedClassInstance<
+    ClassInstance<ClassId, InstanceId>,
+    ClassInstance<ClassId, DerivativeStatus<InstanceId>>,
+>;
+
+sp_api::decl_runtime_apis! {
+    /// The runtime API to query the xnft pallet state.
+    pub trait XnftApi<ClassId, InstanceId, AccountId>
+    where
+        ClassId: Codec,
+        InstanceId: Codec,
+        AccountId: Codec,
+    {
+        /// Returns the version of the pallet's event schema.
+        fn event_schema_version() -> u16;
+
+        /// Returns the versioned asset ID of the foreign asset backing the derivative class.
+        ///
+        /// Returns `None` if the class isn't a derivative one.
+        fn derivative_reserve_asset(class_id: ClassId) -> Option<VersionedAssetId>;
+
+        /// Categorizes each of the given asset instances.
+        ///
+        /// Returns `None` for an asset instance that isn't managed by the pallet.
+        /// The results reflect the current storage state, not the historical one.
+        fn categorize_batch(
+            asset_instances: Vec<(VersionedAssetId, AssetInstance)>,
+        ) -> Vec<Option<CategorizedClassInstanceOf<ClassId, InstanceId>>>;
+
+        /// Simulates the deposit of the foreign asset instance to the `to` account
+        /// and returns the resulting categorized class instance.
+        ///
+        /// The state isn't changed.
+        /// For a stash reactivation, the existing derivative instance ID is returned.
+        /// For a fresh mint, the ID the engine would allocate right now is returned;
+        /// the actual ID can differ if other mints into the same class precede the deposit.
+        fn simulate_deposit(
+            asset_id: VersionedAssetId,
+            asset_instance: AssetInstance,
+            to: AccountId,
+        ) -> Result<CategorizedClassInstanceOf<ClassId, InstanceId>, XcmError>;
+
+        /// Returns the foreign asset instance the derivative corresponds to.
+        ///
+        /// Returns `None` if the class instance isn't a derivative.
+        fn derivative_of_foreign(
+            class_id: ClassId,
+            instance_id: InstanceId,
+        ) -> Option<ForeignAssetInstance>;
+
+        /// Returns the status of the derivative corresponding to the foreign asset instance.
+        ///
+        /// Returns `None` if the asset isn't a registered foreign asset
+        /// or if the derivative doesn't exist.
+        fn foreign_of_derivative(
+            asset_id: VersionedAssetId,
+            asset_instance: AssetInstance,
+        ) -> Option<DerivativeStatus<InstanceId>>;
+
+        /// Checks if the foreign asset can be registered and returns its simplified asset ID.
+        ///
+        /// The origin-dependent checks are skipped.
+        fn can_register(asset_id: VersionedAssetId) -> Result<VersionedAssetId, DispatchError>;
+
+        /// Returns the derivative class of the foreign asset registered as a whole.
+        fn local_class_of_foreign_asset(asset_id: VersionedAssetId) -> Option<ClassId>;
+
+        /// Checks if the asset is an NFT managed by the pallet.
+        ///
+        /// Returns `false` if the asset can't be converted into the current XCM version.
+        fn is_managed_nft(asset: VersionedMultiAsset) -> bool;
+
+        /// Returns a page of at most `limit` derivative classes
+        /// as `(class ID, foreign asset ID, active count, stashed count)` tuples,
+        /// along with the key to request the next page with, if the page is full.
+        ///
+        /// Each class costs three storage reads, so the `limit` should be kept moderate.
+        fn derivative_class_summary(
+            start_key: Option<Vec<u8>>,
+            limit: u32,
+        ) -> (Vec<(ClassId, VersionedAssetId, u32, u32)>, Option<Vec<u8>>);
+
+        /// Returns a page of at most `limit` stashed derivatives across all derivative classes
+        /// as `(class ID, foreign asset instance, derivative instance ID)` tuples,
+        /// along with the key to request the next page with, if the page is full.
+        ///
+        /// NOTE: the stashed derivatives are filtered from all the derivatives,
+        /// so a single call may iterate over every derivative on the chain.
+        fn stashed_derivatives(
+            start_key: Option<Vec<u8>>,
+            limit: u32,
+        ) -> (Vec<(ClassId, AssetInstance, InstanceId)>, Option<Vec<u8>>);
+    }
+}
@@ -1,269 +1,4653 @@
-use cumulus_pallet_parachain_system::AnyRelayNumber;
-use cumulus_primitives_core::ParaId;
 use frame_support::{
-    construct_runtime, parameter_types,
-    traits::{Everything, Nothing},
+    assert_noop, assert_ok,
+    pallet_prelude::MaxEncodedLen,
+    storage::{with_transaction, TransactionOutcome},
+    traits::{
+        ConstU32, ContainsPair, Get, GetStorageVersion, Hooks, OnRuntimeUpgrade, StorageVersion,
+    },
+    weights::{RuntimeDbWeight, Weight},
+    Blake2_128Concat, BoundedVec, StorageHasher,
 };
-use frame_system::EnsureRoot;
-use polkadot_runtime_common::xcm_sender::NoPriceForMessageDelivery;
-use sp_core::{ConstU128, ConstU32, ConstU64, H256};
-use sp_runtime::{traits::IdentityLookup, AccountId32};
-use xcm::prelude::*;
-use xcm_builder::{
-    AllowTopLevelPaidExecutionFrom, EnsureXcmOrigin, FixedWeightBounds, SignedToAccountId32,
-    TakeWeightCredit,
+use sp_core::U256;
+use sp_runtime::{
+    traits::{BlakeTwo256, Hash, MaybeEquivalence},
+    ArithmeticError, BuildStorage, DispatchError, DispatchResult, ModuleError,
 };
+use xcm::{v2, v3::prelude::*, VersionedAssetId};
 use xcm_executor::{
-    traits::{TransactAsset, WeightTrader},
-    Assets, XcmExecutor,
+    traits::{Error as XcmExecutorError, JustTry, TransactAsset, WeightTrader},
+    Assets,
 };
+use xnft_primitives::{
+    conversion::{
+        BridgedConsensusConvert, BytesAssetInstance, BytesInstanceId,
+        CompositeArray32AssetInstance, HashedLocationClassId, InteriorGeneralIndexMultiPrefix,
+        JunctionConvert, TryFromAssetInstance, U256AssetInstance,
+    },
+    topology::{
+        asset_hub_nfts_collection, normalize_reserve_location, ParachainUniversalLocation,
+        PolkadotNetwork, ASSET_HUB_NFTS_PALLET_INDEX, ASSET_HUB_PARA_ID,
+    },
+    traits::{
+        default_dispatch_error_to_xcm, DispatchErrorsConvert, ForeignAssetInstance, NftEngine,
+        NftTransactor, Royalty,
+    },
+};
+
+use crate::{
+    fees::RejectNftFees, migrations::v1::MigrateToV1, mock::*, reserve::XnftReserveLocations,
+    weights::WeightInfo, BlockFlow, CategorizedClassInstance, ClassInstance,
+    DeregisteredForeignAssets, DeregistrationTombstonesCleanupCursor, DerivativeCountPerClass,
+    DerivativeStatus, DerivativeToForeignInstance, DormantDerivativeClasses, Error, Event,
+    EventSchemaVersion, FlowSummary, ForeignAssetToLocalClass, ForeignInstanceToDerivativeStatus,
+    Instance2, LocalClassToForeignAsset, LocalDepositMode, ProcessedDepositKey,
+    RegistrationBuckets, RegistrationBucketsCleanupCursor, RetainedDerivativeIds,
+    RetainedDerivativeIdsCleanups, StashedCountPerClass, XnftOp, EVENT_SCHEMA_VERSION,
+    MAX_ID_ENCODED_LEN,
+};
+
+/// Registers the foreign asset as a whole on behalf of root.
+fn register(asset_id: AssetId) -> DispatchResult {
+    Xnft::register_foreign_asset(
+        RuntimeOrigin::root(),
+        Box::new(VersionedAssetId::V3(asset_id)),
+        (),
+        None,
+        None,
+    )
+}
+
+/// Registers the foreign NFT collection of the reserve parachain
+/// and returns its derivative class ID.
+fn register_foreign_collection(collection: u128) -> u32 {
+    assert_ok!(register(foreign_asset_id(collection)));
+
+    Xnft::foreign_asset_to_local_class(foreign_asset_id(collection))
+        .expect("the foreign asset is registered")
+}
+
+#[test]
+fn registration_rejects_an_abstract_asset_id() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(register(Abstract([1; 32])), Error::<Test>::MalformedAssetId);
+    });
+}
+
+#[test]
+fn registration_rejects_a_location_above_the_universal_root() {
+    new_test_ext().execute_with(|| {
+        // The `UniversalLocation` of the mock has two junctions.
+        assert_noop!(
+            register(Concrete(MultiLocation::new(3, X1(Parachain(1000))))),
+            Error::<Test>::MalformedAssetId,
+        );
+    });
+}
+
+fn deposit(asset: &MultiAsset, to: u64) -> XcmResult {
+    Xnft::deposit_asset(
+        asset,
+        &account_location(to),
+        Some(&xcm_context(reserve_location())),
+    )
+}
+
+fn withdraw(asset: &MultiAsset, from: u64) -> XcmResult {
+    Xnft::withdraw_asset(
+        asset,
+        &account_location(from),
+        Some(&xcm_context(reserve_location())),
+    )
+    .map(|_| ())
+}
+
+fn transfer(asset: &MultiAsset, from: u64, to: u64) -> XcmResult {
+    Xnft::transfer_asset(
+        asset,
+        &account_location(from),
+        &account_location(to),
+        &xcm_context(reserve_location()),
+    )
+    .map(|_| ())
+}
+
+fn derivative_instance(
+    collection: u128,
+    index: u128,
+    class_id: u32,
+    instance_id: u32,
+) -> CategorizedClassInstance<ClassInstance<u32, u32>, ClassInstance<u32, u32>> {
+    CategorizedClassInstance::derivative(
+        foreign_asset_id(collection),
+        AssetInstance::Index(index),
+        class_id,
+        instance_id,
+    )
+}
+
+#[test]
+fn foreign_nft_round_trip_stashes_and_reactivates_the_derivative() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+        assert_eq!(
+            Xnft::derivative_to_foreign_instance(class_id, instance_id),
+            Some(AssetInstance::Index(7)),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+        System::assert_last_event(
+            Event::<Test>::Deposited {
+                class_instance: derivative_instance(1, 7, class_id, instance_id),
+                to: ALICE,
+                xcm_asset_id: Some(Box::new(foreign_asset_id(1))),
+            }
+            .into(),
+        );
+
+        assert_ok!(transfer(&foreign_nft, ALICE, BOB));
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(BOB));
+        System::assert_last_event(
+            Event::<Test>::Transferred {
+                class_instance: derivative_instance(1, 7, class_id, instance_id),
+                from: ALICE,
+                to: BOB,
+                xcm_asset_id: Some(Box::new(foreign_asset_id(1))),
+            }
+            .into(),
+        );
+
+        assert_ok!(withdraw(&foreign_nft, BOB));
+
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(instance_id),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 1);
+        assert!(xnft_events().ends_with(&[
+            Event::DerivativeStashed {
+                foreign_asset_instance: Box::new(
+                    (foreign_asset_id(1), AssetInstance::Index(7)).into(),
+                ),
+                derivative: (class_id, instance_id).into(),
+                from: BOB,
+            },
+            Event::Withdrawn {
+                class_instance: derivative_instance(1, 7, class_id, instance_id),
+                from: BOB,
+                xcm_asset_id: Some(Box::new(foreign_asset_id(1))),
+            },
+        ]));
+
+        assert_ok!(deposit(&foreign_nft, CHARLIE));
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(CHARLIE));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(instance_id),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+        assert_eq!(MockNftEngine::instance_count(class_id), 1);
+        System::assert_last_event(
+            Event::<Test>::Deposited {
+                class_instance: derivative_instance(1, 7, class_id, instance_id),
+                to: CHARLIE,
+                xcm_asset_id: Some(Box::new(foreign_asset_id(1))),
+            }
+            .into(),
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn foreign_nft_round_trip_burns_and_remints_the_derivative() {
+    new_test_ext().execute_with(|| {
+        StashDerivatives::set(false);
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        let DerivativeStatus::Active(burned_instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+
+        assert_eq!(MockNftEngine::owner(class_id, burned_instance_id), None);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(
+            Xnft::derivative_to_foreign_instance(class_id, burned_instance_id),
+            None,
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 0);
+        assert!(xnft_events().ends_with(&[
+            Event::DerivativeBurned {
+                foreign_asset_instance: Box::new(
+                    (foreign_asset_id(1), AssetInstance::Index(7)).into(),
+                ),
+                derivative: (class_id, burned_instance_id).into(),
+                from: ALICE,
+            },
+            Event::Withdrawn {
+                class_instance: derivative_instance(1, 7, class_id, burned_instance_id),
+                from: ALICE,
+                xcm_asset_id: Some(Box::new(foreign_asset_id(1))),
+            },
+        ]));
+
+        assert_ok!(deposit(&foreign_nft, BOB));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the second deposit");
+        };
+
+        assert_ne!(instance_id, burned_instance_id);
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(BOB));
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+/// Deposits the foreign NFT to ALICE, withdraws it into the stash, and burns the stashed derivative out-of-band.
+fn lose_stashed_derivative(class_id: u32, foreign_nft: &MultiAsset) -> u32 {
+    assert_ok!(deposit(foreign_nft, ALICE));
+    assert_ok!(withdraw(foreign_nft, ALICE));
+
+    let DerivativeStatus::Stashed(lost_instance_id) =
+        Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+    else {
+        panic!("the derivative must be stashed after the withdrawal");
+    };
+
+    MockNftEngine::burn(class_id, lost_instance_id);
+
+    lost_instance_id
+}
+
+#[test]
+fn lost_stashed_derivative_is_reminted_as_a_new_one() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+        let lost_instance_id = lose_stashed_derivative(class_id, &foreign_nft);
+
+        IncomingRoyalty::set(Some(Royalty {
+            creator: CHARLIE,
+            basis_points: 250,
+        }));
+
+        assert_ok!(deposit(&foreign_nft, BOB));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_ne!(instance_id, lost_instance_id);
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(BOB));
+        assert_eq!(
+            MockNftEngine::royalty(class_id, instance_id),
+            Some((CHARLIE, 250))
+        );
+        assert_eq!(
+            Xnft::derivative_to_foreign_instance(class_id, lost_instance_id),
+            None,
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+        assert!(
+            xnft_events().contains(&Event::StashLostReminted {
+                foreign_asset_instance: Box::new(
+                    (foreign_asset_id(1), AssetInstance::Index(7)).into(),
+                ),
+                lost_derivative: (class_id, lost_instance_id).into(),
+                derivative: (class_id, instance_id).into(),
+            })
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn lost_stashed_derivative_remint_respects_the_derivative_cap() {
+    new_test_ext().execute_with(|| {
+        MaxDerivativesPerClass::set(Some(2));
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&nft(foreign_asset_id(1), 8), ALICE));
+        let lost_instance_id = lose_stashed_derivative(class_id, &foreign_nft);
+
+        MaxDerivativesPerClass::set(Some(1));
+
+        assert_eq!(deposit(&foreign_nft, BOB), Err(XcmError::NotDepositable),);
+
+        // The rejected deposit keeps the lost stash as is.
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(lost_instance_id),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+        assert_eq!(Xnft::stashed_count(class_id), 1);
+    });
+}
+
+#[test]
+fn lost_stashed_derivative_remint_into_a_frozen_class_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+        let lost_instance_id = lose_stashed_derivative(class_id, &foreign_nft);
+
+        MockNftEngine::freeze(class_id);
+
+        assert!(deposit(&foreign_nft, BOB).is_err());
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(lost_instance_id),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+
+        MockNftEngine::unfreeze(class_id);
+
+        assert_ok!(deposit(&foreign_nft, BOB));
+        assert_eq!(MockNftEngine::instance_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+/// Deposits the asset to the `to` account by a message with the given topic.
+fn deposit_with_topic(asset: &MultiAsset, to: u64, topic: Option<XcmHash>) -> XcmResult {
+    let context = XcmContext {
+        topic,
+        ..xcm_context(reserve_location())
+    };
+
+    Xnft::deposit_asset(asset, &account_location(to), Some(&context))
+}
+
+#[test]
+fn replayed_deposit_mints_the_derivative_once() {
+    new_test_ext().execute_with(|| {
+        DeduplicateByMessageId::set(true);
+        StashDerivatives::set(false);
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+        let topic = Some([1; 32]);
+
+        assert_ok!(deposit_with_topic(&foreign_nft, ALICE, topic));
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+
+        // The duplicate delivery of the first message after the derivative was burned.
+        assert_ok!(deposit_with_topic(&foreign_nft, ALICE, topic));
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(
+            xnft_events()
+                .iter()
+                .filter(|event| matches!(event, Event::Deposited { .. }))
+                .count(),
+            1,
+        );
+    });
+}
+
+#[test]
+fn deposits_without_the_topic_are_not_deduplicated() {
+    new_test_ext().execute_with(|| {
+        DeduplicateByMessageId::set(true);
+        StashDerivatives::set(false);
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit_with_topic(&foreign_nft, ALICE, None));
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+
+        // An identical message without the topic has the same message ID,
+        // yet it is a legitimate new deposit.
+        assert_ok!(deposit_with_topic(&foreign_nft, ALICE, None));
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 1);
+    });
+}
+
+#[test]
+fn failed_deposit_is_not_recorded_as_processed() {
+    new_test_ext().execute_with(|| {
+        DeduplicateByMessageId::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+        let topic = Some([1; 32]);
+
+        MockNftEngine::freeze(class_id);
+        assert!(deposit_with_topic(&foreign_nft, ALICE, topic).is_err());
+
+        MockNftEngine::unfreeze(class_id);
+        assert_ok!(deposit_with_topic(&foreign_nft, ALICE, topic));
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 1);
+    });
+}
+
+#[test]
+fn deposit_from_a_disallowed_origin_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        DisallowedOrigins::set(vec![reserve_location()]);
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::NoPermission),
+        );
+
+        // The filter isn't consulted for the assets the pallet doesn't manage.
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(2), 7), ALICE),
+            Err(XcmError::AssetNotFound),
+        );
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            FilteredOps::get(),
+            vec![XnftOp::Deposit {
+                reactivation: false
+            }],
+        );
+    });
+}
+
+#[test]
+fn each_asset_path_is_filtered_as_its_operation() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        deposit_derivative(class_id, 1, 7, ALICE);
+        assert_ok!(transfer(&foreign_nft, ALICE, BOB));
+        assert_ok!(withdraw(&foreign_nft, BOB));
+
+        // The stashed derivative is reactivated.
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        let local_class_id = MockNftEngine::create(ALICE);
+        let local_instance_id = MockNftEngine::mint(local_class_id, ALICE);
+        let local_nft = nft(local_asset_id(local_class_id), local_instance_id.into());
+
+        assert_ok!(withdraw(&local_nft, ALICE));
+        assert_ok!(deposit(&local_nft, BOB));
+
+        ClassLevelTransfer::set(true);
+        assert_ok!(withdraw(&whole_class(1), ALICE));
+
+        assert_eq!(
+            FilteredOps::get(),
+            vec![
+                XnftOp::Deposit {
+                    reactivation: false
+                },
+                XnftOp::Transfer,
+                XnftOp::Withdraw { stash: false },
+                XnftOp::Deposit { reactivation: true },
+                XnftOp::Withdraw { stash: false },
+                XnftOp::Deposit {
+                    reactivation: false
+                },
+                XnftOp::Withdraw { stash: false },
+            ],
+        );
+    });
+}
+
+#[test]
+fn lost_stashed_derivative_deposit_is_filtered_as_a_new_mint() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        lose_stashed_derivative(class_id, &foreign_nft);
+        FilteredOps::set(vec![]);
+
+        assert_ok!(deposit(&foreign_nft, BOB));
+
+        assert_eq!(
+            FilteredOps::get(),
+            vec![XnftOp::Deposit {
+                reactivation: false
+            }],
+        );
+    });
+}
+
+#[test]
+fn op_weight_covers_the_costliest_path_of_each_operation() {
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Register),
+        <() as WeightInfo>::foreign_asset_registration_checks(),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Deregister),
+        <() as WeightInfo>::foreign_asset_registration_checks(),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Deposit {
+            reactivation: false
+        }),
+        <() as WeightInfo>::deposit_local().max(<() as WeightInfo>::deposit_derivative_new()),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Deposit { reactivation: true }),
+        <() as WeightInfo>::deposit_derivative_stashed(),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Withdraw { stash: false }),
+        <() as WeightInfo>::withdraw_local().max(<() as WeightInfo>::withdraw_derivative_burned()),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Withdraw { stash: true }),
+        <() as WeightInfo>::withdraw_derivative_stashed(),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Transfer),
+        <() as WeightInfo>::transfer_local().max(<() as WeightInfo>::transfer_derivative()),
+    );
+    assert_eq!(
+        Xnft::op_weight(XnftOp::Mint),
+        <() as WeightInfo>::deposit_derivative_new(),
+    );
+}
+
+#[test]
+fn fungible_assets_are_not_filtered() {
+    new_test_ext().execute_with(|| {
+        let fungible: MultiAsset = (Concrete(MultiLocation::parent()), 100).into();
+
+        assert_eq!(deposit(&fungible, ALICE), Err(XcmError::AssetNotFound));
+        assert_eq!(withdraw(&fungible, ALICE), Err(XcmError::AssetNotFound));
+        assert_eq!(
+            transfer(&fungible, ALICE, BOB),
+            Err(XcmError::AssetNotFound),
+        );
+
+        assert!(FilteredOps::get().is_empty());
+    });
+}
+
+#[test]
+fn local_nft_round_trip_goes_through_the_custody() {
+    new_test_ext().execute_with(|| {
+        let class_id = MockNftEngine::create(ALICE);
+        let instance_id = MockNftEngine::mint(class_id, ALICE);
+        let local_nft = nft(local_asset_id(class_id), instance_id.into());
+
+        assert_ok!(withdraw(&local_nft, ALICE));
+
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        System::assert_last_event(
+            Event::<Test>::Withdrawn {
+                class_instance: CategorizedClassInstance::Local((class_id, instance_id).into()),
+                from: ALICE,
+                xcm_asset_id: Some(Box::new(local_asset_id(class_id))),
+            }
+            .into(),
+        );
+
+        assert_ok!(deposit(&local_nft, BOB));
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(BOB));
+        System::assert_last_event(
+            Event::<Test>::Deposited {
+                class_instance: CategorizedClassInstance::Local((class_id, instance_id).into()),
+                to: BOB,
+                xcm_asset_id: Some(Box::new(local_asset_id(class_id))),
+            }
+            .into(),
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn unknown_assets_are_left_to_other_transactors() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        let unregistered_nft = nft(foreign_asset_id(2), 7);
+        let fungible: MultiAsset = (Concrete(MultiLocation::parent()), 100).into();
+
+        assert_eq!(
+            deposit(&unregistered_nft, ALICE),
+            Err(XcmError::AssetNotFound),
+        );
+        assert_eq!(deposit(&fungible, ALICE), Err(XcmError::AssetNotFound));
+        assert_eq!(
+            withdraw(&unregistered_nft, ALICE),
+            Err(XcmError::AssetNotFound),
+        );
+        assert_eq!(
+            transfer(&unregistered_nft, ALICE, BOB),
+            Err(XcmError::AssetNotFound),
+        );
+
+        assert!(xnft_events()
+            .iter()
+            .all(|event| matches!(event, Event::ForeignAssetRegistered { .. })));
+    });
+}
+
+#[test]
+fn class_resolution_follows_the_registrations_within_a_block() {
+    new_test_ext().execute_with(|| {
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_eq!(deposit(&foreign_nft, ALICE), Err(XcmError::AssetNotFound));
+
+        let derivative_class_id = register_foreign_collection(1);
+        deposit_derivative(derivative_class_id, 1, 7, ALICE);
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+
+        assert_ok!(Xnft::deregister_foreign_asset(
+            RuntimeOrigin::root(),
+            Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+        ));
+
+        assert_eq!(deposit(&foreign_nft, ALICE), Err(XcmError::AssetNotFound));
+    });
+}
+
+fn register_for_existing_class(
+    origin: RuntimeOrigin,
+    collection: u128,
+    class_id: u32,
+) -> DispatchResult {
+    Xnft::register_foreign_asset_for_existing_class(
+        origin,
+        Box::new(VersionedAssetId::V3(foreign_asset_id(collection))),
+        class_id,
+    )
+}
+
+#[test]
+fn class_owner_can_bind_its_empty_class() {
+    new_test_ext().execute_with(|| {
+        let class_id = MockNftEngine::create(ALICE);
+
+        assert_noop!(
+            register_for_existing_class(RuntimeOrigin::signed(BOB), 1, class_id),
+            DispatchError::BadOrigin,
+        );
+
+        assert_ok!(register_for_existing_class(
+            RuntimeOrigin::signed(ALICE),
+            1,
+            class_id
+        ));
+
+        assert_eq!(
+            Xnft::foreign_asset_to_local_class(foreign_asset_id(1)),
+            Some(class_id)
+        );
+        assert_eq!(deposit_derivative(class_id, 1, 7, BOB), 0);
+    });
+}
+
+#[test]
+fn root_can_bind_only_a_class_handed_over_to_the_pallet() {
+    new_test_ext().execute_with(|| {
+        let class_id = MockNftEngine::create(ALICE);
+
+        assert_noop!(
+            register_for_existing_class(RuntimeOrigin::root(), 1, class_id),
+            DispatchError::BadOrigin,
+        );
+
+        assert_ok!(MockNftEngine::set_class_owner(&class_id, &PALLET_ACCOUNT));
+
+        assert_ok!(register_for_existing_class(
+            RuntimeOrigin::root(),
+            1,
+            class_id
+        ));
+    });
+}
+
+#[test]
+fn binding_a_class_with_instances_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = MockNftEngine::create(ALICE);
+        let instance_id = MockNftEngine::mint(class_id, ALICE);
+
+        // The local NFT is exported, so it is kept in the custody.
+        assert_ok!(withdraw(
+            &nft(local_asset_id(class_id), instance_id.into()),
+            ALICE
+        ));
+
+        assert_noop!(
+            register_for_existing_class(RuntimeOrigin::signed(ALICE), 1, class_id),
+            Error::<Test>::ClassNotEmpty,
+        );
+
+        let bound_class_id = MockNftEngine::create(ALICE);
+        assert_ok!(register_for_existing_class(
+            RuntimeOrigin::signed(ALICE),
+            1,
+            bound_class_id
+        ));
+
+        assert_noop!(
+            register_for_existing_class(RuntimeOrigin::signed(ALICE), 2, bound_class_id),
+            Error::<Test>::ClassAlreadyBound,
+        );
+    });
+}
+
+#[test]
+fn deregistration_removes_all_the_retained_derivative_ids() {
+    new_test_ext().execute_with(|| {
+        PreserveIdAcrossBurn::set(true);
+        StashDerivatives::set(false);
+
+        let class_id = register_foreign_collection(1);
+
+        // More retained IDs than the `MaxDeregistrationDerivatives` (3) removes at once.
+        for index in 1..=5 {
+            deposit_derivative(class_id, 1, index, ALICE);
+            assert_ok!(withdraw(&nft(foreign_asset_id(1), index), ALICE));
+        }
+        assert_eq!(
+            RetainedDerivativeIds::<Test>::iter_prefix(class_id).count(),
+            5
+        );
+
+        let deregister = || {
+            Xnft::deregister_foreign_asset(
+                RuntimeOrigin::root(),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+            )
+        };
+
+        assert_ok!(deregister());
+
+        assert_eq!(
+            RetainedDerivativeIds::<Test>::iter_prefix(class_id).count(),
+            2
+        );
+        assert!(RetainedDerivativeIdsCleanups::<Test>::contains_key(
+            class_id
+        ));
+        assert_eq!(
+            Xnft::foreign_asset_to_local_class(foreign_asset_id(1)),
+            Some(class_id),
+        );
+        System::assert_last_event(
+            Event::<Test>::ForeignAssetDeregistrationContinues {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id: class_id,
+            }
+            .into(),
+        );
+
+        assert_ok!(deregister());
+
+        assert_eq!(
+            RetainedDerivativeIds::<Test>::iter_prefix(class_id).count(),
+            0
+        );
+        assert!(!RetainedDerivativeIdsCleanups::<Test>::contains_key(
+            class_id
+        ));
+        assert_eq!(
+            Xnft::foreign_asset_to_local_class(foreign_asset_id(1)),
+            None
+        );
+        System::assert_last_event(
+            Event::<Test>::ForeignAssetDeregistered {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id: class_id,
+            }
+            .into(),
+        );
+    });
+}
+
+#[test]
+fn genesis_registers_the_foreign_assets() {
+    let storage = RuntimeGenesisConfig {
+        xnft: crate::GenesisConfig {
+            foreign_assets: vec![(VersionedAssetId::V3(foreign_asset_id(1)), ())],
+            ..Default::default()
+        },
+        ..Default::default()
+    }
+    .build_storage()
+    .unwrap();
+
+    sp_io::TestExternalities::new(storage).execute_with(|| {
+        let class_id = Xnft::foreign_asset_to_local_class(foreign_asset_id(1))
+            .expect("the foreign asset is registered at genesis");
+
+        assert_eq!(
+            Xnft::local_class_to_foreign_asset(class_id),
+            Some(foreign_asset_id(1)),
+        );
+        assert_eq!(MockNftEngine::class_owner(&class_id), Some(PALLET_ACCOUNT));
+        assert!(xnft_events().is_empty());
+    });
+}
+
+/// Deposits the foreign NFT of the collection to the `to` account
+/// and returns its derivative instance ID.
+fn deposit_derivative(class_id: u32, collection: u128, index: u128, to: u64) -> u32 {
+    assert_ok!(deposit(&nft(foreign_asset_id(collection), index), to));
+
+    match Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(index)) {
+        DerivativeStatus::Active(instance_id) => instance_id,
+        status => panic!("the derivative must be active after the deposit: {status:?}"),
+    }
+}
+
+#[test]
+fn bulk_withdraw_moves_all_the_instances_into_the_custody() {
+    new_test_ext().execute_with(|| {
+        let derivative_class_id = register_foreign_collection(1);
+        let derivative_id = deposit_derivative(derivative_class_id, 1, 7, ALICE);
+
+        let local_class_id = MockNftEngine::create(BOB);
+        let local_instance_id = MockNftEngine::mint(local_class_id, ALICE);
+
+        assert_ok!(Xnft::bulk_withdraw_to_custody(
+            RuntimeOrigin::signed(ALICE),
+            BoundedVec::truncate_from(vec![
+                (derivative_class_id, derivative_id),
+                (local_class_id, local_instance_id),
+            ]),
+        ));
+
+        assert_eq!(
+            MockNftEngine::owner(derivative_class_id, derivative_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(
+            MockNftEngine::owner(local_class_id, local_instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(
+                derivative_class_id,
+                AssetInstance::Index(7)
+            ),
+            DerivativeStatus::Stashed(derivative_id),
+        );
+        assert!(xnft_events().contains(&Event::Withdrawn {
+            class_instance: derivative_instance(1, 7, derivative_class_id, derivative_id),
+            from: ALICE,
+            xcm_asset_id: Some(Box::new(foreign_asset_id(1))),
+        }));
+        System::assert_last_event(
+            Event::<Test>::Withdrawn {
+                class_instance: CategorizedClassInstance::Local(
+                    (local_class_id, local_instance_id).into(),
+                ),
+                from: ALICE,
+                xcm_asset_id: Some(Box::new(local_asset_id(local_class_id))),
+            }
+            .into(),
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn bulk_withdraw_rejects_a_batch_with_foreign_owned_instances() {
+    new_test_ext().execute_with(|| {
+        AllowApprovedOperators::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let alice_derivative_id = deposit_derivative(class_id, 1, 7, ALICE);
+        let bob_derivative_id = deposit_derivative(class_id, 1, 8, BOB);
+
+        // The approval doesn't make ALICE an owner for the bulk withdrawal.
+        MockNftEngine::approve(class_id, bob_derivative_id, ALICE);
+
+        assert_noop!(
+            Xnft::bulk_withdraw_to_custody(
+                RuntimeOrigin::signed(ALICE),
+                BoundedVec::truncate_from(vec![
+                    (class_id, alice_derivative_id),
+                    (class_id, bob_derivative_id),
+                ]),
+            ),
+            Error::<Test>::NotInstanceOwner,
+        );
+
+        assert_eq!(
+            MockNftEngine::owner(class_id, alice_derivative_id),
+            Some(ALICE),
+        );
+        assert_eq!(MockNftEngine::owner(class_id, bob_derivative_id), Some(BOB));
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+    });
+}
+
+#[test]
+fn bulk_withdraw_reports_the_engine_error() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let derivative_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        MockNftEngine::freeze(class_id);
+
+        assert_noop!(
+            Xnft::bulk_withdraw_to_custody(
+                RuntimeOrigin::signed(ALICE),
+                BoundedVec::truncate_from(vec![(class_id, derivative_id)]),
+            ),
+            DispatchError::Other("the class is frozen"),
+        );
+    });
+}
+
+#[test]
+fn deposit_into_a_frozen_class_is_delegated_to_the_fallback_transactor() {
+    new_test_ext().execute_with(|| {
+        let derivative_class_id = register_foreign_collection(1);
+        MockNftEngine::freeze(derivative_class_id);
+        FallbackEnabled::set(true);
+
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        assert_eq!(
+            FallbackDeposits::get(),
+            vec![(foreign_nft, account_location(ALICE))],
+        );
+        assert_eq!(MockNftEngine::instance_count(derivative_class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(
+                derivative_class_id,
+                AssetInstance::Index(7),
+            ),
+            DerivativeStatus::NotExists,
+        );
+    });
+}
+
+#[test]
+fn failures_within_an_operational_class_are_not_delegated() {
+    new_test_ext().execute_with(|| {
+        let derivative_class_id = register_foreign_collection(1);
+        deposit_derivative(derivative_class_id, 1, 7, ALICE);
+        FallbackEnabled::set(true);
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), BOB),
+            Err(XcmError::NotDepositable),
+        );
+
+        assert!(FallbackDeposits::get().is_empty());
+    });
+}
+
+type AssetWeight = crate::fees::XnftAssetWeight<Test, ConstU32<20>>;
+
+/// Returns the weight of the `operation` on a single NFT as the `AssetWeight` computes it.
+fn per_nft(operation: Weight) -> Weight {
+    <() as WeightInfo>::class_instance_lookup()
+        .saturating_add(<() as WeightInfo>::dispatch_error_conversion())
+        .saturating_add(operation)
+}
+
+#[test]
+fn xcm_weight_accounts_for_the_nfts_only() {
+    let deposit = per_nft(
+        <() as WeightInfo>::deposit_local()
+            .max(<() as WeightInfo>::deposit_derivative_new())
+            .max(<() as WeightInfo>::deposit_derivative_stashed()),
+    );
+    let withdrawal = per_nft(
+        <() as WeightInfo>::withdraw_local()
+            .max(<() as WeightInfo>::withdraw_derivative_burned())
+            .max(<() as WeightInfo>::withdraw_derivative_stashed()),
+    );
+    let transfer = per_nft(
+        <() as WeightInfo>::transfer_local().max(<() as WeightInfo>::transfer_derivative()),
+    );
+
+    let fungible: MultiAsset = (Concrete(MultiLocation::parent()), 100).into();
+    let nfts = MultiAssets::from(vec![
+        fungible.clone(),
+        nft(foreign_asset_id(1), 7),
+        nft(foreign_asset_id(2), 7),
+    ]);
+
+    assert_eq!(
+        AssetWeight::withdraw_asset(&fungible.clone().into()),
+        Weight::zero(),
+    );
+    assert_eq!(
+        AssetWeight::withdraw_asset(&nfts),
+        withdrawal.saturating_mul(2)
+    );
+    assert_eq!(
+        AssetWeight::transfer_asset(&nfts),
+        transfer.saturating_mul(2)
+    );
+    assert_eq!(
+        AssetWeight::deposit_asset(&Definite(nfts)),
+        deposit.saturating_mul(2),
+    );
+
+    assert_eq!(
+        AssetWeight::deposit_asset(&Wild(AllOf {
+            id: Concrete(MultiLocation::parent()),
+            fun: WildFungibility::Fungible,
+        })),
+        Weight::zero(),
+    );
+    assert_eq!(
+        AssetWeight::deposit_asset(&Wild(AllCounted(3))),
+        deposit.saturating_mul(3),
+    );
+    assert_eq!(
+        AssetWeight::deposit_asset(&Wild(All)),
+        deposit.saturating_mul(20),
+    );
+}
+
+#[test]
+fn xcm_weight_of_a_class_level_withdrawal_covers_the_whole_class() {
+    new_test_ext().execute_with(|| {
+        let class_nft: MultiAssets = MultiAsset {
+            id: foreign_asset_id(1),
+            fun: NonFungible(AssetInstance::Undefined),
+        }
+        .into();
+
+        let single = AssetWeight::withdraw_asset(&class_nft);
+
+        ClassLevelTransfer::set(true);
+
+        let withdrawal = <() as WeightInfo>::withdraw_local()
+            .max(<() as WeightInfo>::withdraw_derivative_burned())
+            .max(<() as WeightInfo>::withdraw_derivative_stashed());
+
+        assert_eq!(single, per_nft(withdrawal));
+        assert_eq!(
+            AssetWeight::withdraw_asset(&class_nft),
+            per_nft(withdrawal.saturating_mul(3)),
+        );
+    });
+}
+
+fn whole_class(collection: u128) -> MultiAsset {
+    MultiAsset {
+        id: foreign_asset_id(collection),
+        fun: NonFungible(AssetInstance::Undefined),
+    }
+}
+
+#[test]
+fn whole_class_withdrawal_withdraws_all_the_active_derivatives() {
+    new_test_ext().execute_with(|| {
+        ClassLevelTransfer::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let first_id = deposit_derivative(class_id, 1, 1, ALICE);
+        let second_id = deposit_derivative(class_id, 1, 2, ALICE);
+
+        assert_ok!(withdraw(&whole_class(1), ALICE));
+
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(1)),
+            DerivativeStatus::Stashed(first_id),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(2)),
+            DerivativeStatus::Stashed(second_id),
+        );
+        assert!(DormantDerivativeClasses::<Test>::contains_key(class_id));
+        assert_eq!(
+            xnft_events().last(),
+            Some(&Event::ClassWithdrawn {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id: class_id,
+                from: ALICE,
+                withdrawn_count: 2,
+            }),
+        );
+    });
+}
+
+#[test]
+fn whole_class_withdrawal_requires_a_single_owner() {
+    new_test_ext().execute_with(|| {
+        ClassLevelTransfer::set(true);
+
+        let class_id = register_foreign_collection(1);
+        deposit_derivative(class_id, 1, 1, ALICE);
+        let bob_derivative_id = deposit_derivative(class_id, 1, 2, BOB);
+
+        assert!(withdraw(&whole_class(1), ALICE).is_err());
+
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(2)),
+            DerivativeStatus::Active(bob_derivative_id),
+        );
+        assert!(!DormantDerivativeClasses::<Test>::contains_key(class_id));
+    });
+}
+
+#[test]
+fn whole_class_withdrawal_rejects_too_many_derivatives() {
+    new_test_ext().execute_with(|| {
+        ClassLevelTransfer::set(true);
+
+        let class_id = register_foreign_collection(1);
+
+        // The stashed derivatives count towards the `MaxClassLevelWithdrawal` (3),
+        // since each of them is read.
+        for index in 1..=3 {
+            deposit_derivative(class_id, 1, index, ALICE);
+        }
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 3), ALICE));
+        deposit_derivative(class_id, 1, 4, ALICE);
+
+        assert_eq!(
+            withdraw(&whole_class(1), ALICE),
+            Err(XcmError::NotWithdrawable),
+        );
+        assert!(!DormantDerivativeClasses::<Test>::contains_key(class_id));
+    });
+}
+
+#[test]
+fn whole_class_withdrawal_is_rejected_without_the_class_level_transfer() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        deposit_derivative(class_id, 1, 1, ALICE);
+
+        assert!(withdraw(&whole_class(1), ALICE).is_err());
+        assert!(!DormantDerivativeClasses::<Test>::contains_key(class_id));
+    });
+}
+
+#[test]
+fn integrity_test_accepts_the_mock_config() {
+    Xnft::do_integrity_test();
+}
+
+#[test]
+#[should_panic(expected = "the instance ID is wider than any XCM asset instance")]
+fn integrity_test_rejects_instance_ids_wider_than_asset_instances() {
+    WideIdXnft::do_integrity_test();
+}
+
+#[test]
+#[should_panic(expected = "the `UniversalLocation` must start with the `GlobalConsensus` junction")]
+fn integrity_test_rejects_a_universal_location_without_the_network() {
+    UniversalLocation::set(X1(Parachain(2000)));
+
+    Xnft::do_integrity_test();
+}
+
+#[test]
+#[should_panic(expected = "the local class location must not point to another chain")]
+fn integrity_test_rejects_local_classes_on_another_chain() {
+    LocalClassPrefix::set(X2(Parachain(3000), PalletInstance(42)));
+
+    Xnft::do_integrity_test();
+}
+
+#[test]
+#[should_panic(expected = "the local class location doesn't fit an XCM location")]
+fn integrity_test_rejects_local_classes_unreachable_from_other_consensus_systems() {
+    LocalClassPrefix::set(X6(
+        PalletInstance(42),
+        GeneralIndex(1),
+        GeneralIndex(2),
+        GeneralIndex(3),
+        GeneralIndex(4),
+        GeneralIndex(5),
+    ));
+
+    Xnft::do_integrity_test();
+}
+
+// The storage keys and values are a part of the PoV of every block touching them,
+// so the tests below pin their maximum encoded lengths.
+// A change of an ID type (e.g., to a `BoundedVec`) must fail here rather than silently
+// inflate the PoV. The storage key prefix (the pallet and storage name hashes) takes 32 bytes.
+const STORAGE_PREFIX_LEN: usize = 32;
+
+#[test]
+fn id_bound_matches_the_widest_asset_instance() {
+    // An instance ID must fit an XCM asset instance, the widest of which is `Array32`:
+    // 1 byte of the variant index and 32 bytes of the data.
+    assert_eq!(AssetInstance::max_encoded_len(), 33);
+    assert_eq!(MAX_ID_ENCODED_LEN, AssetInstance::max_encoded_len());
+}
+
+#[test]
+fn class_instance_max_encoded_len() {
+    // The mock IDs are `u32`s, so the class instance is just the two of them.
+    assert_eq!(ClassInstance::<u32, u32>::max_encoded_len(), 4 + 4);
+
+    // The worst case is two IDs at the bound.
+    assert_eq!(
+        ClassInstance::<[u8; MAX_ID_ENCODED_LEN], [u8; MAX_ID_ENCODED_LEN]>::max_encoded_len(),
+        2 * MAX_ID_ENCODED_LEN,
+    );
+}
+
+#[test]
+fn derivative_status_max_encoded_len() {
+    // 1 byte of the variant index and the instance ID of the `Active` or `Stashed` variant.
+    assert_eq!(DerivativeStatus::<u32>::max_encoded_len(), 1 + 4);
+    assert_eq!(
+        DerivativeStatus::<[u8; MAX_ID_ENCODED_LEN]>::max_encoded_len(),
+        1 + MAX_ID_ENCODED_LEN,
+    );
+}
+
+#[test]
+fn derivative_mapping_keys_max_encoded_len() {
+    // Each key of the derivative mappings is hashed via the `Blake2_128Concat`,
+    // which prepends 16 bytes of the hash to the encoded key.
+    let class_key_len = Blake2_128Concat::max_len::<u32>();
+    let asset_instance_key_len = Blake2_128Concat::max_len::<AssetInstance>();
+    let instance_key_len = Blake2_128Concat::max_len::<u32>();
+
+    assert_eq!(class_key_len, 16 + 4);
+    assert_eq!(asset_instance_key_len, 16 + 33);
+
+    // The `ForeignInstanceToDerivativeStatus` key: the class ID and the asset instance.
+    assert_eq!(
+        STORAGE_PREFIX_LEN + class_key_len + asset_instance_key_len,
+        101
+    );
+
+    // The `DerivativeToForeignInstance` key: the class ID and the derivative instance ID.
+    assert_eq!(STORAGE_PREFIX_LEN + class_key_len + instance_key_len, 72);
+
+    // The worst case within the ID bound, as documented at the `MAX_ID_ENCODED_LEN`.
+    assert_eq!(
+        STORAGE_PREFIX_LEN
+            + Blake2_128Concat::max_len::<[u8; MAX_ID_ENCODED_LEN]>()
+            + asset_instance_key_len,
+        130,
+    );
+}
+
+#[test]
+fn processed_deposit_key_is_independent_of_the_id_types() {
+    // The deduplication key consists of the XCM types only:
+    // 32 bytes of the message ID, the asset ID, and the asset instance.
+    assert_eq!(
+        ProcessedDepositKey::max_encoded_len(),
+        32 + AssetId::max_encoded_len() + AssetInstance::max_encoded_len(),
+    );
+}
+
+#[frame_support::storage_alias]
+type ForeignInstanceToDerivativeIdStatus = StorageDoubleMap<
+    Xnft,
+    Blake2_128Concat,
+    u32,
+    Blake2_128Concat,
+    AssetInstance,
+    DerivativeStatus<u32>,
+>;
+
+#[frame_support::storage_alias]
+type DerivativeIdToForeignInstance =
+    StorageDoubleMap<Xnft, Blake2_128Concat, u32, Blake2_128Concat, u32, AssetInstance>;
+
+/// Puts the storage version 0 with the derivative mappings under their old names.
+fn put_v0_mappings(count: u32) {
+    StorageVersion::new(0).put::<Xnft>();
+
+    for index in 0..count {
+        ForeignInstanceToDerivativeIdStatus::insert(
+            0,
+            AssetInstance::Index(index.into()),
+            DerivativeStatus::Active(index),
+        );
+        DerivativeIdToForeignInstance::insert(0, index, AssetInstance::Index(index.into()));
+    }
+}
+
+#[test]
+fn migration_to_v1_moves_the_derivative_mappings() {
+    new_test_ext().execute_with(|| {
+        put_v0_mappings(3);
+
+        MigrateToV1::<Test, (), ConstU32<3>>::on_runtime_upgrade();
+
+        assert_eq!(Xnft::on_chain_storage_version(), 1);
+        assert_eq!(ForeignInstanceToDerivativeIdStatus::iter().count(), 0);
+        assert_eq!(DerivativeIdToForeignInstance::iter().count(), 0);
+
+        for index in 0..3 {
+            assert_eq!(
+                Xnft::foreign_instance_to_derivative_status(0, AssetInstance::Index(index.into())),
+                DerivativeStatus::Active(index),
+            );
+            assert_eq!(
+                Xnft::derivative_to_foreign_instance(0, index),
+                Some(AssetInstance::Index(index.into())),
+            );
+        }
+    });
+}
+
+#[test]
+fn migration_to_v1_skips_the_mappings_exceeding_the_bound() {
+    new_test_ext().execute_with(|| {
+        put_v0_mappings(3);
+
+        MigrateToV1::<Test, (), ConstU32<2>>::on_runtime_upgrade();
+
+        assert_eq!(Xnft::on_chain_storage_version(), 0);
+        assert_eq!(ForeignInstanceToDerivativeIdStatus::iter().count(), 3);
+        assert_eq!(DerivativeIdToForeignInstance::iter().count(), 3);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(0, AssetInstance::Index(0)),
+            DerivativeStatus::NotExists,
+        );
+    });
+}
+
+#[test]
+fn public_types_keep_the_full_debug_output_under_std() {
+    let class_instance = derivative_instance(1, 7, 2, 3);
+
+    assert_eq!(
+        format!("{:?}", DerivativeStatus::Stashed(3u32)),
+        "Stashed(3)",
+    );
+    assert_eq!(
+        format!("{:?}", ClassInstance::<u32, u32>::from((2, 3))),
+        "ClassInstance { class_id: 2, instance_id: 3 }",
+    );
+    assert!(format!("{class_instance:?}").starts_with("Derivative { foreign_asset_instance: "));
+}
+
+/// Returns the foreign collection asset ID located under the given network's parachain.
+fn remote_consensus_asset_id(network: NetworkId, collection: u128) -> AssetId {
+    Concrete(MultiLocation::new(
+        2,
+        X3(
+            GlobalConsensus(network),
+            Parachain(RESERVE_PARA_ID),
+            GeneralIndex(collection),
+        ),
+    ))
+}
+
+#[test]
+fn registration_accepts_the_sibling_assets_by_default() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(register(foreign_asset_id(1)));
+        assert_ok!(register(remote_consensus_asset_id(NetworkId::Kusama, 1)));
+    });
+}
+
+#[test]
+fn registration_rejects_the_reserves_closer_than_the_min_foreign_parents() {
+    new_test_ext().execute_with(|| {
+        MinForeignParents::set(2);
+
+        assert_noop!(
+            register(foreign_asset_id(1)),
+            Error::<Test>::ReserveTooClose,
+        );
+        assert_ok!(register(remote_consensus_asset_id(NetworkId::Kusama, 1)));
+    });
+}
+
+#[test]
+fn registration_rejects_the_local_assets_regardless_of_the_min_foreign_parents() {
+    new_test_ext().execute_with(|| {
+        MinForeignParents::set(0);
+
+        assert_noop!(
+            register(local_asset_id(1)),
+            Error::<Test>::AttemptToRegisterLocalAsset,
+        );
+    });
+}
+
+#[test]
+fn min_foreign_parents_applies_to_the_simplified_location() {
+    new_test_ext().execute_with(|| {
+        MinForeignParents::set(2);
+
+        // The sibling's location expressed through our own consensus simplifies to one parent.
+        let sibling_via_own_consensus = Concrete(MultiLocation::new(
+            2,
+            X3(
+                GlobalConsensus(NetworkId::Polkadot),
+                Parachain(RESERVE_PARA_ID),
+                GeneralIndex(1),
+            ),
+        ));
+
+        assert_noop!(
+            register(sibling_via_own_consensus),
+            Error::<Test>::ReserveTooClose,
+        );
+    });
+}
+
+#[test]
+fn is_managed_nft_recognizes_only_the_registered_nfts() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        assert!(Xnft::is_managed_nft(&nft(foreign_asset_id(1), 7)));
+        assert!(!Xnft::is_managed_nft(
+            &(foreign_asset_id(1), 100u128).into()
+        ));
+        assert!(!Xnft::is_managed_nft(&nft(foreign_asset_id(2), 7)));
+    });
+}
+
+#[test]
+fn simulated_fresh_mint_predicts_the_deposited_derivative() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let asset_id = VersionedAssetId::V3(foreign_asset_id(1));
+
+        let simulated = Xnft::simulate_deposit(asset_id, AssetInstance::Index(7), ALICE).unwrap();
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+
+        assert_ok!(deposit(&nft(foreign_asset_id(1), 7), ALICE));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_eq!(simulated, derivative_instance(1, 7, class_id, instance_id));
+    });
+}
+
+#[test]
+fn simulated_reactivation_returns_the_stashed_derivative() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&foreign_nft, ALICE));
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+
+        let DerivativeStatus::Stashed(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be stashed after the withdrawal");
+        };
+
+        assert_eq!(
+            Xnft::simulate_deposit(
+                VersionedAssetId::V3(foreign_asset_id(1)),
+                AssetInstance::Index(7),
+                BOB,
+            ),
+            Ok(derivative_instance(1, 7, class_id, instance_id)),
+        );
+
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(Xnft::stashed_count(class_id), 1);
+    });
+}
+
+#[test]
+fn simulated_deposit_of_an_unregistered_asset_fails() {
+    new_test_ext().execute_with(|| {
+        assert!(Xnft::simulate_deposit(
+            VersionedAssetId::V3(foreign_asset_id(1)),
+            AssetInstance::Index(7),
+            ALICE,
+        )
+        .is_err());
+    });
+}
+
+#[test]
+fn derivative_class_summary_reports_the_active_and_stashed_counts() {
+    new_test_ext().execute_with(|| {
+        let first_class_id = register_foreign_collection(1);
+        let second_class_id = register_foreign_collection(2);
+
+        for index in 0..3 {
+            assert_ok!(deposit(&nft(foreign_asset_id(1), index), ALICE));
+        }
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 0), ALICE));
+        assert_ok!(deposit(&nft(foreign_asset_id(2), 0), BOB));
+
+        let (mut summary, next_key) = Xnft::derivative_class_summary(None, 10);
+        summary.sort_by_key(|(class_id, ..)| *class_id);
+
+        assert_eq!(next_key, None);
+        assert_eq!(
+            summary,
+            vec![
+                (
+                    first_class_id,
+                    VersionedAssetId::V3(foreign_asset_id(1)),
+                    2,
+                    1
+                ),
+                (
+                    second_class_id,
+                    VersionedAssetId::V3(foreign_asset_id(2)),
+                    1,
+                    0
+                ),
+            ],
+        );
+    });
+}
+
+#[test]
+fn derivative_class_summary_is_paginated() {
+    new_test_ext().execute_with(|| {
+        let first_class_id = register_foreign_collection(1);
+        let second_class_id = register_foreign_collection(2);
+
+        let (first_page, next_key) = Xnft::derivative_class_summary(None, 1);
+        assert_eq!(first_page.len(), 1);
+        assert!(next_key.is_some());
+
+        let (second_page, next_key) = Xnft::derivative_class_summary(next_key, 1);
+        assert_eq!(second_page.len(), 1);
+
+        let mut class_ids = vec![first_page[0].0, second_page[0].0];
+        class_ids.sort();
+        assert_eq!(class_ids, vec![first_class_id, second_class_id]);
+
+        let (last_page, next_key) = Xnft::derivative_class_summary(next_key, 1);
+        assert!(last_page.is_empty());
+        assert_eq!(next_key, None);
+    });
+}
+
+#[test]
+fn reserve_filter_recognizes_the_reserve_of_a_registered_asset() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        let reserve = MultiLocation::new(1, X1(Parachain(RESERVE_PARA_ID)));
+
+        assert!(XnftReserveLocations::<Test>::contains(
+            &nft(foreign_asset_id(1), 7),
+            &reserve,
+        ));
+        assert!(!XnftReserveLocations::<Test>::contains(
+            &nft(foreign_asset_id(2), 7),
+            &reserve,
+        ));
+    });
+}
+
+#[test]
+fn reserve_filter_rejects_a_spoofed_origin() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        let spoofed_origins = [
+            MultiLocation::new(1, X1(Parachain(RESERVE_PARA_ID + 1))),
+            MultiLocation::parent(),
+            MultiLocation::new(1, X2(Parachain(RESERVE_PARA_ID), GeneralIndex(1))),
+        ];
+
+        for origin in spoofed_origins {
+            assert!(!XnftReserveLocations::<Test>::contains(
+                &nft(foreign_asset_id(1), 7),
+                &origin,
+            ));
+        }
+    });
+}
+
+#[test]
+fn can_register_returns_the_simplified_asset_id() {
+    new_test_ext().execute_with(|| {
+        let sibling_via_own_consensus = Concrete(MultiLocation::new(
+            2,
+            X3(
+                GlobalConsensus(NetworkId::Polkadot),
+                Parachain(RESERVE_PARA_ID),
+                GeneralIndex(1),
+            ),
+        ));
+
+        assert_eq!(
+            Xnft::can_register(&VersionedAssetId::V3(sibling_via_own_consensus)),
+            Ok(foreign_asset_id(1)),
+        );
+        assert_eq!(
+            Xnft::foreign_asset_to_local_class(foreign_asset_id(1)),
+            None
+        );
+    });
+}
+
+#[test]
+fn can_register_rejects_a_local_asset() {
+    new_test_ext().execute_with(|| {
+        assert_eq!(
+            Xnft::can_register(&VersionedAssetId::V3(local_asset_id(1))),
+            Err(Error::<Test>::AttemptToRegisterLocalAsset.into()),
+        );
+    });
+}
+
+#[test]
+fn can_register_rejects_an_already_registered_asset() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        assert_eq!(
+            Xnft::can_register(&VersionedAssetId::V3(foreign_asset_id(1))),
+            Err(Error::<Test>::AssetAlreadyRegistered.into()),
+        );
+    });
+}
+
+#[test]
+fn derivative_class_id_transform_is_applied_to_the_stored_class_ids() {
+    new_test_ext().execute_with(|| {
+        const TAG: u32 = 1 << 31;
+        ClassIdTag::set(TAG);
+
+        assert_ok!(register(foreign_asset_id(1)));
+
+        let stored_class_id = Xnft::foreign_asset_to_local_class(foreign_asset_id(1))
+            .expect("the foreign asset is registered");
+        let class_id = stored_class_id ^ TAG;
+
+        assert_eq!(
+            Xnft::local_class_to_foreign_asset(stored_class_id),
+            Some(foreign_asset_id(1)),
+        );
+        assert!(Xnft::is_derivative_class(&class_id));
+        assert!(!Xnft::is_derivative_class(&stored_class_id));
+        assert_eq!(
+            Xnft::derivative_reserve_asset(class_id),
+            Some(VersionedAssetId::V3(foreign_asset_id(1))),
+        );
+
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be minted in the engine's class");
+        };
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+/// The typed collection ID carried by a `GeneralIndex` junction.
+#[derive(Clone, Debug, PartialEq)]
+struct GeneralIndexCollectionId(u32);
+
+impl TryFrom<Junction> for GeneralIndexCollectionId {
+    type Error = ();
+
+    fn try_from(junction: Junction) -> Result<Self, Self::Error> {
+        match junction {
+            GeneralIndex(index) => index.try_into().map(Self).map_err(|_| ()),
+            _ => Err(()),
+        }
+    }
+}
+
+impl From<GeneralIndexCollectionId> for Junction {
+    fn from(collection_id: GeneralIndexCollectionId) -> Self {
+        GeneralIndex(collection_id.0.into())
+    }
+}
+
+type TypedCollectionConvert = JunctionConvert<LocalClassPrefix, GeneralIndexCollectionId>;
+
+#[test]
+fn junction_convert_round_trips_a_typed_collection_id() {
+    let location = X2(PalletInstance(42), GeneralIndex(5));
+
+    assert_eq!(
+        TypedCollectionConvert::convert(&location),
+        Some(GeneralIndexCollectionId(5)),
+    );
+    assert_eq!(
+        TypedCollectionConvert::convert_back(&GeneralIndexCollectionId(5)),
+        Some(location),
+    );
+}
+
+#[test]
+fn junction_convert_rejects_the_mismatching_locations() {
+    let locations = [
+        X2(PalletInstance(43), GeneralIndex(5)),
+        X2(
+            PalletInstance(42),
+            GeneralKey {
+                length: 1,
+                data: [5; 32],
+            },
+        ),
+        X2(PalletInstance(42), GeneralIndex(u128::from(u32::MAX) + 1)),
+        X1(PalletInstance(42)),
+    ];
+
+    for location in locations {
+        assert_eq!(TypedCollectionConvert::convert(&location), None);
+    }
+}
+
+#[test]
+fn deposit_from_a_foreign_asset_aliased_to_another_class_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        register_foreign_collection(2);
+
+        // The second foreign asset is repointed to the first one's derivative class.
+        ForeignAssetToLocalClass::<Test>::insert(foreign_asset_id(2), class_id);
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(2), 7), ALICE),
+            Err(XcmExecutorError::AssetIdConversionFailed.into()),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+
+        assert_ok!(deposit(&nft(foreign_asset_id(1), 7), ALICE));
+    });
+}
+
+#[test]
+fn derivative_reserve_asset_is_returned_only_for_the_derivative_classes() {
+    new_test_ext().execute_with(|| {
+        let derivative_class_id = register_foreign_collection(1);
+        let local_class_id = MockNftEngine::create(ALICE);
+
+        assert_eq!(
+            Xnft::derivative_reserve_asset(derivative_class_id),
+            Some(VersionedAssetId::V3(foreign_asset_id(1))),
+        );
+        assert_eq!(Xnft::derivative_reserve_asset(local_class_id), None);
+        assert_eq!(Xnft::derivative_reserve_asset(u32::MAX), None);
+    });
+}
+
+#[test]
+fn observer_mode_records_the_flows_without_performing_them() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let active_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&active_nft, ALICE));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        ObserverMode::set(true);
+
+        let new_nft = nft(foreign_asset_id(1), 8);
+        assert_ok!(deposit(&new_nft, BOB));
+
+        System::assert_last_event(
+            Event::<Test>::ObservedFlow {
+                op: XnftOp::Deposit {
+                    reactivation: false,
+                },
+                class_instance: CategorizedClassInstance::Derivative {
+                    foreign_asset_instance: Box::new(
+                        (foreign_asset_id(1), AssetInstance::Index(8)).into(),
+                    ),
+                    derivative: (class_id, DerivativeStatus::NotExists).into(),
+                },
+                from: None,
+                to: Some(BOB),
+            }
+            .into(),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(8)),
+            DerivativeStatus::NotExists,
+        );
+
+        assert_ok!(transfer(&active_nft, ALICE, BOB));
+        assert_ok!(withdraw(&active_nft, ALICE));
+
+        System::assert_last_event(
+            Event::<Test>::ObservedFlow {
+                op: XnftOp::Withdraw { stash: false },
+                class_instance: CategorizedClassInstance::Derivative {
+                    foreign_asset_instance: Box::new(
+                        (foreign_asset_id(1), AssetInstance::Index(7)).into(),
+                    ),
+                    derivative: (class_id, DerivativeStatus::Active(instance_id)).into(),
+                },
+                from: Some(ALICE),
+                to: None,
+            }
+            .into(),
+        );
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 1);
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(instance_id),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+    });
+}
+
+#[test]
+fn array_asset_instances_of_different_widths_never_alias() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        // The wider array starts with the same bytes as the narrower one.
+        let array16 = AssetInstance::Array16([7; 16]);
+        let mut array32 = AssetInstance::Array32([0; 32]);
+        if let AssetInstance::Array32(bytes) = &mut array32 {
+            bytes[..16].copy_from_slice(&[7; 16]);
+        }
+
+        let array16_key =
+            crate::ForeignInstanceToDerivativeStatus::<Test>::hashed_key_for(class_id, array16);
+        let array32_key =
+            crate::ForeignInstanceToDerivativeStatus::<Test>::hashed_key_for(class_id, array32);
+
+        assert_ne!(array16_key, array32_key);
+        assert!(!array32_key.starts_with(&array16_key));
+        assert!(!array16_key.starts_with(&array32_key));
+
+        let array16_nft = MultiAsset {
+            id: foreign_asset_id(1),
+            fun: NonFungible(array16),
+        };
+        let array32_nft = MultiAsset {
+            id: foreign_asset_id(1),
+            fun: NonFungible(array32),
+        };
+
+        assert_ok!(deposit(&array16_nft, ALICE));
+        assert_ok!(deposit(&array32_nft, BOB));
+
+        let (
+            DerivativeStatus::Active(array16_instance_id),
+            DerivativeStatus::Active(array32_instance_id),
+        ) = (
+            Xnft::foreign_instance_to_derivative_status(class_id, array16),
+            Xnft::foreign_instance_to_derivative_status(class_id, array32),
+        )
+        else {
+            panic!("both derivatives must be active after the deposits");
+        };
+
+        assert_ne!(array16_instance_id, array32_instance_id);
+        assert_eq!(
+            MockNftEngine::owner(class_id, array16_instance_id),
+            Some(ALICE)
+        );
+        assert_eq!(
+            MockNftEngine::owner(class_id, array32_instance_id),
+            Some(BOB)
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+fn deregister(collection: u128) -> DispatchResult {
+    Xnft::deregister_foreign_asset(
+        RuntimeOrigin::root(),
+        Box::new(VersionedAssetId::V3(foreign_asset_id(collection))),
+    )
+}
+
+#[test]
+fn reregistration_is_rejected_within_the_cooldown() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+        assert_ok!(deregister(1));
+
+        assert_eq!(
+            Xnft::foreign_asset_deregistration_block(foreign_asset_id(1)),
+            Some(1),
+        );
+
+        // The `ReregistrationCooldown` is 5 blocks.
+        System::set_block_number(5);
+        assert_noop!(
+            register(foreign_asset_id(1)),
+            Error::<Test>::ReregistrationTooSoon,
+        );
+
+        System::set_block_number(6);
+        assert_ok!(register(foreign_asset_id(1)));
+    });
+}
+
+#[test]
+fn expired_deregistration_tombstones_are_removed_on_idle() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+        assert_ok!(deregister(1));
+
+        Xnft::on_idle(5, Weight::MAX);
+        assert_eq!(
+            Xnft::foreign_asset_deregistration_block(foreign_asset_id(1)),
+            Some(1),
+        );
+
+        Xnft::on_idle(6, Weight::MAX);
+        assert_eq!(
+            Xnft::foreign_asset_deregistration_block(foreign_asset_id(1)),
+            None,
+        );
+    });
+}
+
+#[test]
+fn tombstone_cleanup_resumes_after_the_last_scanned_tombstone() {
+    new_test_ext().execute_with(|| {
+        for collection in 1..=3 {
+            register_foreign_collection(collection);
+            assert_ok!(deregister(collection));
+        }
+
+        System::set_block_number(3);
+        register_foreign_collection(4);
+        assert_ok!(deregister(4));
+
+        MockDbWeight::set(RuntimeDbWeight { read: 1, write: 1 });
+
+        // The cursor bookkeeping and a single tombstone.
+        let weight = Weight::from_parts(4, 0);
+
+        // The tombstone not expired yet doesn't block the cleanup of the others.
+        for _ in 0..4 {
+            assert_eq!(Xnft::cleanup_deregistration_tombstones(6, weight), weight);
+            assert!(DeregistrationTombstonesCleanupCursor::<Test>::get().is_some());
+        }
+
+        assert_eq!(
+            DeregisteredForeignAssets::<Test>::iter().collect::<Vec<_>>(),
+            vec![(foreign_asset_id(4), 3)],
+        );
+
+        // The scan is finished, so the next one starts over.
+        Xnft::cleanup_deregistration_tombstones(6, weight);
+        assert!(DeregistrationTombstonesCleanupCursor::<Test>::get().is_none());
+    });
+}
+
+fn register_shard(collection: u128, shard: u32) -> u32 {
+    assert_ok!(Xnft::register_foreign_asset_shard(
+        RuntimeOrigin::root(),
+        Box::new(VersionedAssetId::V3(foreign_asset_id(collection))),
+        shard,
+        (),
+        None,
+    ));
+
+    Xnft::foreign_asset_shard_to_local_class(foreign_asset_id(collection), shard)
+        .expect("the shard is registered")
+}
+
+#[test]
+fn sharded_instances_are_routed_to_their_shard_classes() {
+    new_test_ext().execute_with(|| {
+        InstanceSharding::set(true);
+
+        // The `InstanceRangeRouter` puts 100 consecutive indices into a shard.
+        let first_shard_class_id = register_shard(1, 0);
+        let second_shard_class_id = register_shard(1, 1);
+
+        assert_ok!(deposit(&nft(foreign_asset_id(1), 99), ALICE));
+        assert_ok!(deposit(&nft(foreign_asset_id(1), 100), BOB));
+
+        assert!(matches!(
+            Xnft::foreign_instance_to_derivative_status(
+                first_shard_class_id,
+                AssetInstance::Index(99),
+            ),
+            DerivativeStatus::Active(_),
+        ));
+        assert!(matches!(
+            Xnft::foreign_instance_to_derivative_status(
+                second_shard_class_id,
+                AssetInstance::Index(100),
+            ),
+            DerivativeStatus::Active(_),
+        ));
+        assert_eq!(MockNftEngine::instance_count(first_shard_class_id), 1);
+        assert_eq!(MockNftEngine::instance_count(second_shard_class_id), 1);
+
+        // No class is registered for the third shard.
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 200), ALICE),
+            Err(XcmError::AssetNotFound),
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn shard_registration_requires_the_sharding_mode() {
+    new_test_ext().execute_with(|| {
+        assert_noop!(
+            Xnft::register_foreign_asset_shard(
+                RuntimeOrigin::root(),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+                0,
+                (),
+                None,
+            ),
+            Error::<Test>::ShardingDisabled,
+        );
+    });
+}
+
+#[test]
+fn local_constructor_builds_the_local_class_instance() {
+    let class_instance =
+        CategorizedClassInstance::<ClassInstance<u32, u32>, ClassInstance<u32, u32>>::local(2, 3);
+
+    assert_eq!(
+        class_instance,
+        CategorizedClassInstance::Local(ClassInstance {
+            class_id: 2,
+            instance_id: 3,
+        }),
+    );
+    assert_eq!(class_instance.class_id(), &2);
+}
+
+#[test]
+fn derivative_constructor_builds_the_derivative_class_instance() {
+    let class_instance = CategorizedClassInstance::<
+        ClassInstance<u32, u32>,
+        ClassInstance<u32, DerivativeStatus<u32>>,
+    >::derivative(
+        foreign_asset_id(1),
+        AssetInstance::Index(7),
+        2,
+        DerivativeStatus::Stashed(3),
+    );
+
+    assert_eq!(
+        class_instance,
+        CategorizedClassInstance::Derivative {
+            foreign_asset_instance: Box::new(ForeignAssetInstance {
+                asset_id: foreign_asset_id(1),
+                asset_instance: AssetInstance::Index(7),
+            }),
+            derivative: ClassInstance {
+                class_id: 2,
+                instance_id: DerivativeStatus::Stashed(3),
+            },
+        },
+    );
+    assert_eq!(class_instance.class_id(), &2);
+}
+
+/// The weight trader consuming all the payment it is given.
+struct ConsumeAllPayment;
+
+impl WeightTrader for ConsumeAllPayment {
+    fn new() -> Self {
+        Self
+    }
+
+    fn buy_weight(
+        &mut self,
+        _weight: Weight,
+        payment: Assets,
+        _context: &XcmContext,
+    ) -> Result<Assets, XcmError> {
+        assert!(
+            payment.non_fungible.is_empty(),
+            "an NFT is offered as a fee"
+        );
+
+        Ok(Assets::new())
+    }
+}
+
+#[test]
+fn nft_offered_as_the_only_fee_payment_is_rejected() {
+    let mut trader = RejectNftFees::<ConsumeAllPayment>::new();
+    let payment: Assets = nft(foreign_asset_id(1), 7).into();
+
+    assert_eq!(
+        trader
+            .buy_weight(
+                Weight::from_parts(1, 1),
+                payment,
+                &XcmContext::with_message_id([0; 32]),
+            )
+            .map(|unused| unused.non_fungible.len()),
+        Err(XcmError::TooExpensive),
+    );
+}
+
+#[test]
+fn nft_offered_along_with_a_fungible_fee_is_returned_unused() {
+    let mut trader = RejectNftFees::<ConsumeAllPayment>::new();
+    let foreign_nft = nft(foreign_asset_id(1), 7);
+
+    let mut payment: Assets = foreign_nft.clone().into();
+    payment.subsume((Concrete(MultiLocation::parent()), 100).into());
+
+    let unused = trader
+        .buy_weight(
+            Weight::from_parts(1, 1),
+            payment,
+            &XcmContext::with_message_id([0; 32]),
+        )
+        .expect("the fungible payment is enough");
+
+    assert!(unused.fungible.is_empty());
+    assert_eq!(
+        unused.into_assets_iter().collect::<Vec<_>>(),
+        vec![foreign_nft]
+    );
+}
+
+#[test]
+fn categorize_batch_categorizes_a_mixed_batch() {
+    new_test_ext().execute_with(|| {
+        let local_class_id = MockNftEngine::create(ALICE);
+        let local_instance_id = MockNftEngine::mint(local_class_id, ALICE);
+
+        let derivative_class_id = register_foreign_collection(1);
+        let derivative_instance_id = deposit_derivative(derivative_class_id, 1, 7, ALICE);
+
+        let batch = vec![
+            (
+                VersionedAssetId::V3(local_asset_id(local_class_id)),
+                AssetInstance::Index(local_instance_id.into()),
+            ),
+            (
+                VersionedAssetId::V3(foreign_asset_id(1)),
+                AssetInstance::Index(7),
+            ),
+            (
+                VersionedAssetId::V3(foreign_asset_id(1)),
+                AssetInstance::Index(8),
+            ),
+            (
+                VersionedAssetId::V3(foreign_asset_id(2)),
+                AssetInstance::Index(7),
+            ),
+        ];
+
+        assert_eq!(
+            Xnft::categorize_batch(batch),
+            vec![
+                Some(CategorizedClassInstance::local(
+                    local_class_id,
+                    local_instance_id
+                )),
+                Some(CategorizedClassInstance::derivative(
+                    foreign_asset_id(1),
+                    AssetInstance::Index(7),
+                    derivative_class_id,
+                    DerivativeStatus::Active(derivative_instance_id),
+                )),
+                Some(CategorizedClassInstance::derivative(
+                    foreign_asset_id(1),
+                    AssetInstance::Index(8),
+                    derivative_class_id,
+                    DerivativeStatus::NotExists,
+                )),
+                None,
+            ],
+        );
+    });
+}
+
+#[test]
+fn derivative_minted_into_another_class_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let other_class_id = register_foreign_collection(2);
+
+        MintIntoClass::set(Some(other_class_id));
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::FailedToTransactAsset(
+                "the minted derivative doesn't belong to the derivative class",
+            )),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(MockNftEngine::instance_count(other_class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 0);
+    });
+}
+
+type HashedClassSeed = HashedLocationClassId<[u8; 32], JustTry>;
+
+#[test]
+fn hashed_location_class_id_is_deterministic() {
+    let location = X2(Parachain(RESERVE_PARA_ID), GeneralIndex(1));
+
+    assert_eq!(
+        HashedClassSeed::convert(&location),
+        Some(BlakeTwo256::hash_of(&location).to_fixed_bytes()),
+    );
+    assert_eq!(
+        HashedClassSeed::convert(&location),
+        HashedClassSeed::convert(&X2(Parachain(RESERVE_PARA_ID), GeneralIndex(1))),
+    );
+    assert_ne!(
+        HashedClassSeed::convert(&location),
+        HashedClassSeed::convert(&X2(Parachain(RESERVE_PARA_ID), GeneralIndex(2))),
+    );
+    assert_ne!(
+        HashedClassSeed::convert(&location),
+        HashedClassSeed::convert(&X1(Parachain(RESERVE_PARA_ID))),
+    );
+}
+
+#[test]
+fn hashed_location_class_id_is_one_way() {
+    let seed =
+        HashedClassSeed::convert(&X1(Parachain(RESERVE_PARA_ID))).expect("any location is hashed");
+
+    assert_eq!(HashedClassSeed::convert_back(&seed), None);
+}
+
+#[test]
+fn stashed_derivatives_are_listed_across_the_classes() {
+    new_test_ext().execute_with(|| {
+        let first_class_id = register_foreign_collection(1);
+        let second_class_id = register_foreign_collection(2);
+
+        let first_stashed = deposit_derivative(first_class_id, 1, 7, ALICE);
+        deposit_derivative(first_class_id, 1, 8, ALICE);
+        let second_stashed = deposit_derivative(second_class_id, 2, 9, ALICE);
+
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 7), ALICE));
+        assert_ok!(withdraw(&nft(foreign_asset_id(2), 9), ALICE));
+
+        let (mut page, next_key) = Xnft::stashed_derivatives(None, 10);
+        page.sort_by_key(|(class_id, ..)| *class_id);
+
+        assert_eq!(next_key, None);
+        assert_eq!(
+            page,
+            vec![
+                (first_class_id, AssetInstance::Index(7), first_stashed),
+                (second_class_id, AssetInstance::Index(9), second_stashed),
+            ],
+        );
+
+        let (first_page, next_key) = Xnft::stashed_derivatives(None, 1);
+        assert_eq!(first_page.len(), 1);
+
+        let (second_page, _) = Xnft::stashed_derivatives(next_key, 1);
+        assert_eq!(second_page.len(), 1);
+        assert_ne!(first_page, second_page);
+    });
+}
+
+#[test]
+fn derived_reverse_mapping_isnt_stored() {
+    new_test_ext().execute_with(|| {
+        DeriveReverseMapping::set(true);
+        HonorInstanceIdHint::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_eq!(deposit_derivative(class_id, 1, 7, ALICE), 7);
+        assert!(!DerivativeToForeignInstance::<Test>::contains_key(
+            class_id, 7
+        ));
+        assert_eq!(
+            Xnft::derivative_foreign_instance(&class_id, &7),
+            Some(AssetInstance::Index(7)),
+        );
+        assert_eq!(Xnft::derivative_foreign_instance(&class_id, &8), None);
+
+        assert_ok!(withdraw(&foreign_nft, ALICE));
+        assert_eq!(
+            Xnft::derivative_foreign_instance(&class_id, &7),
+            Some(AssetInstance::Index(7)),
+        );
+
+        assert_ok!(deposit(&foreign_nft, BOB));
+        assert_eq!(MockNftEngine::owner(class_id, 7), Some(BOB));
+        assert!(!DerivativeToForeignInstance::<Test>::contains_key(
+            class_id, 7
+        ));
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn stored_reverse_mapping_is_used_outside_the_derived_mode() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        assert_eq!(
+            DerivativeToForeignInstance::<Test>::get(class_id, instance_id),
+            Some(AssetInstance::Index(7)),
+        );
+        assert_eq!(
+            Xnft::derivative_foreign_instance(&class_id, &instance_id),
+            Some(AssetInstance::Index(7)),
+        );
+    });
+}
+
+#[test]
+fn derived_reverse_mapping_rejects_a_non_derivable_mint() {
+    new_test_ext().execute_with(|| {
+        DeriveReverseMapping::set(true);
+
+        let class_id = register_foreign_collection(1);
+
+        // The engine ignores the hint, so the minted ID isn't the derived one.
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::FailedToTransactAsset(
+                "the minted derivative ID can't be derived from the foreign asset instance",
+            )),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+    });
+}
+
+fn register_signed(who: u64, collection: u128) -> DispatchResult {
+    Xnft::register_foreign_asset(
+        RuntimeOrigin::signed(who),
+        Box::new(VersionedAssetId::V3(foreign_asset_id(collection))),
+        (),
+        None,
+        None,
+    )
+}
+
+#[test]
+fn registrations_are_rate_limited_per_origin() {
+    new_test_ext().execute_with(|| {
+        MaxRegistrationsPerWindow::set(Some(2));
+
+        assert_ok!(register_signed(ALICE, 1));
+        assert_ok!(register_signed(ALICE, 2));
+        assert_noop!(
+            register_signed(ALICE, 3),
+            Error::<Test>::RegistrationRateLimited,
+        );
+
+        // Other origins, including root, aren't affected.
+        assert_ok!(register_signed(BOB, 3));
+        assert_ok!(register(foreign_asset_id(4)));
+
+        // The `RegistrationWindow` is 10 blocks.
+        System::set_block_number(10);
+        assert_noop!(
+            register_signed(ALICE, 5),
+            Error::<Test>::RegistrationRateLimited,
+        );
+
+        System::set_block_number(11);
+        assert_ok!(register_signed(ALICE, 5));
+    });
+}
+
+#[test]
+fn outdated_registration_buckets_are_removed_on_idle() {
+    new_test_ext().execute_with(|| {
+        MaxRegistrationsPerWindow::set(Some(2));
+
+        assert_ok!(register_signed(ALICE, 1));
+        assert_eq!(RegistrationBuckets::<Test>::iter().count(), 1);
+
+        Xnft::on_idle(10, Weight::MAX);
+        assert_eq!(RegistrationBuckets::<Test>::iter().count(), 1);
+
+        Xnft::on_idle(11, Weight::MAX);
+        assert_eq!(RegistrationBuckets::<Test>::iter().count(), 0);
+    });
+}
+
+#[test]
+fn registration_bucket_cleanup_resumes_after_the_last_scanned_bucket() {
+    new_test_ext().execute_with(|| {
+        MaxRegistrationsPerWindow::set(Some(2));
+
+        assert_ok!(register_signed(ALICE, 1));
+        assert_ok!(register_signed(BOB, 2));
+        assert_ok!(register_signed(CHARLIE, 3));
+
+        System::set_block_number(5);
+        assert_ok!(register_signed(ALICE, 4));
+
+        MockDbWeight::set(RuntimeDbWeight { read: 1, write: 1 });
+
+        // The cursor bookkeeping and a single bucket.
+        let weight = Weight::from_parts(4, 0);
+
+        // The bucket within the window doesn't block the cleanup of the others.
+        for _ in 0..4 {
+            assert_eq!(Xnft::cleanup_registration_buckets(11, weight), weight);
+        }
+
+        assert_eq!(
+            RegistrationBuckets::<Test>::iter()
+                .map(|(_, block, count)| (block, count))
+                .collect::<Vec<_>>(),
+            vec![(5, 1)],
+        );
+
+        Xnft::cleanup_registration_buckets(11, weight);
+        assert!(RegistrationBucketsCleanupCursor::<Test>::get().is_none());
+    });
+}
+
+/// Deposits the foreign NFT to ALICE and withdraws it into the stash.
+fn stash_derivative(class_id: u32, collection: u128, index: u128) -> u32 {
+    let instance_id = deposit_derivative(class_id, collection, index, ALICE);
+    assert_ok!(withdraw(&nft(foreign_asset_id(collection), index), ALICE));
+
+    instance_id
+}
+
+#[test]
+fn reactivation_is_rolled_back_with_the_enclosing_transaction() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = stash_derivative(class_id, 1, 7);
+
+        let _: DispatchResult = with_transaction(|| {
+            let class_instance =
+                Xnft::class_instance(&foreign_asset_id(1), &AssetInstance::Index(7)).unwrap();
+
+            assert_ok!(Xnft::deposit_class_instance(class_instance, &BOB, None));
+            assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(BOB));
+
+            // A failure later within the same execution.
+            TransactionOutcome::Rollback(Ok(()))
+        });
+
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(instance_id),
+        );
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(Xnft::stashed_count(class_id), 1);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn failed_custody_release_keeps_the_derivative_stashed() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = stash_derivative(class_id, 1, 7);
+
+        MockNftEngine::freeze(class_id);
+
+        assert!(deposit(&nft(foreign_asset_id(1), 7), BOB).is_err());
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(instance_id),
+        );
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(Xnft::stashed_count(class_id), 1);
+    });
+}
+
+frame_support::parameter_types! {
+    pub CollectionPrefixes: Vec<InteriorMultiLocation> = vec![
+        X1(PalletInstance(52)),
+        X1(PalletInstance(42)),
+    ];
+}
+
+type MultiPrefixConvert = InteriorGeneralIndexMultiPrefix<CollectionPrefixes, u32, JustTry>;
+
+#[test]
+fn multi_prefix_converter_matches_every_prefix() {
+    assert_eq!(
+        MultiPrefixConvert::convert(&X2(PalletInstance(52), GeneralIndex(5))),
+        Some(5),
+    );
+    assert_eq!(
+        MultiPrefixConvert::convert(&X2(PalletInstance(42), GeneralIndex(5))),
+        Some(5),
+    );
+    assert_eq!(
+        MultiPrefixConvert::convert(&X2(PalletInstance(43), GeneralIndex(5))),
+        None,
+    );
+}
+
+#[test]
+fn multi_prefix_converter_round_trips_via_the_canonical_prefix() {
+    let location = MultiPrefixConvert::convert_back(&5).expect("the class ID is convertible");
+
+    assert_eq!(location, X2(PalletInstance(52), GeneralIndex(5)));
+    assert_eq!(MultiPrefixConvert::convert(&location), Some(5));
+}
+
+#[test]
+fn governance_transfers_the_derivative_class_ownership() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        assert_eq!(MockNftEngine::class_owner(&class_id), Some(PALLET_ACCOUNT));
+
+        assert_ok!(Xnft::set_derivative_class_owner(
+            RuntimeOrigin::root(),
+            Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+            CHARLIE,
+        ));
+
+        assert_eq!(MockNftEngine::class_owner(&class_id), Some(CHARLIE));
+        System::assert_last_event(
+            Event::<Test>::DerivativeClassOwnerChanged {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id: class_id,
+                new_owner: CHARLIE,
+            }
+            .into(),
+        );
+
+        // The pallet keeps transacting the derivatives.
+        deposit_derivative(class_id, 1, 7, ALICE);
+    });
+}
+
+#[test]
+fn derivative_class_ownership_transfer_requires_the_admin_origin() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        assert_noop!(
+            Xnft::set_derivative_class_owner(
+                RuntimeOrigin::signed(ALICE),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+                ALICE,
+            ),
+            DispatchError::BadOrigin,
+        );
+        assert_noop!(
+            Xnft::set_derivative_class_owner(
+                RuntimeOrigin::root(),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(2))),
+                CHARLIE,
+            ),
+            Error::<Test>::ForeignAssetNotRegistered,
+        );
+    });
+}
+
+/// Packs a `(collection, token)` pair of 32-bit numbers into a flat 64-bit instance ID.
+struct PackedCollectionToken;
+
+impl MaybeEquivalence<(u128, u128), u64> for PackedCollectionToken {
+    fn convert((collection, token): &(u128, u128)) -> Option<u64> {
+        let collection = u32::try_from(*collection).ok()?;
+        let token = u32::try_from(*token).ok()?;
+
+        Some((u64::from(collection) << 32) | u64::from(token))
+    }
+
+    fn convert_back(instance_id: &u64) -> Option<(u128, u128)> {
+        Some((
+            (instance_id >> 32).into(),
+            (instance_id & u64::from(u32::MAX)).into(),
+        ))
+    }
+}
+
+type CompositeInstanceConvert = CompositeArray32AssetInstance<u64, PackedCollectionToken>;
+
+#[test]
+fn composite_instance_round_trips_the_exact_bytes() {
+    let mut bytes = [0; 32];
+    bytes[..16].copy_from_slice(&5u128.to_be_bytes());
+    bytes[16..].copy_from_slice(&9u128.to_be_bytes());
+    let instance = AssetInstance::Array32(bytes);
+
+    assert_eq!(
+        CompositeInstanceConvert::convert(&instance),
+        Some((5 << 32) | 9)
+    );
+    assert_eq!(
+        CompositeInstanceConvert::convert_back(&((5 << 32) | 9)),
+        Some(instance),
+    );
+}
+
+#[test]
+fn composite_instance_rejects_the_unconvertible_instances() {
+    let mut too_large_token = [0; 32];
+    too_large_token[16..].copy_from_slice(&(u128::from(u32::MAX) + 1).to_be_bytes());
+
+    assert_eq!(
+        CompositeInstanceConvert::convert(&AssetInstance::Array32(too_large_token)),
+        None,
+    );
+    assert_eq!(
+        CompositeInstanceConvert::convert(&AssetInstance::Index(5)),
+        None,
+    );
+    assert_eq!(
+        CompositeInstanceConvert::convert(&AssetInstance::Array16([0; 16])),
+        None,
+    );
+}
+
+#[test]
+fn event_schema_version_matches_the_current_events() {
+    // Bump the version together with any event change affecting the decoding.
+    assert_eq!(EVENT_SCHEMA_VERSION, 5);
+}
+
+#[test]
+fn runtime_upgrade_announces_the_new_event_schema_version_once() {
+    new_test_ext().execute_with(|| {
+        <Xnft as Hooks<u64>>::on_runtime_upgrade();
+
+        assert_eq!(EventSchemaVersion::<Test>::get(), EVENT_SCHEMA_VERSION);
+        System::assert_last_event(
+            Event::<Test>::SchemaVersionChanged {
+                version: EVENT_SCHEMA_VERSION,
+            }
+            .into(),
+        );
+
+        System::reset_events();
+        <Xnft as Hooks<u64>>::on_runtime_upgrade();
+
+        assert!(xnft_events().is_empty());
+    });
+}
+
+#[test]
+fn approved_operator_withdraws_the_owners_derivative() {
+    new_test_ext().execute_with(|| {
+        AllowApprovedOperators::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        MockNftEngine::approve(class_id, instance_id, BOB);
+
+        assert!(withdraw(&foreign_nft, CHARLIE).is_err());
+        assert_ok!(withdraw(&foreign_nft, BOB));
+
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(PALLET_ACCOUNT),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(instance_id),
+        );
+    });
+}
+
+#[test]
+fn approved_operator_cant_withdraw_by_default() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        MockNftEngine::approve(class_id, instance_id, BOB);
+
+        assert!(withdraw(&nft(foreign_asset_id(1), 7), BOB).is_err());
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+    });
+}
+
+frame_support::parameter_types! {
+    pub const OurParaId: u32 = 2000;
+}
+
+type PolkadotParachain = ParachainUniversalLocation<PolkadotNetwork, OurParaId>;
+
+/// Returns the location of the Polkadot Asset Hub `pallet-nfts` collection
+/// expressed through the given global consensus.
+fn global_asset_hub_nfts_collection(network: NetworkId, collection_id: u32) -> MultiLocation {
+    MultiLocation::new(
+        2,
+        X4(
+            GlobalConsensus(network),
+            Parachain(ASSET_HUB_PARA_ID),
+            PalletInstance(ASSET_HUB_NFTS_PALLET_INDEX),
+            GeneralIndex(collection_id.into()),
+        ),
+    )
+}
+
+#[test]
+fn asset_hub_collection_normalizes_to_the_sibling_form() {
+    assert_eq!(PolkadotParachain::get(), UniversalLocation::get());
+
+    assert_eq!(
+        normalize_reserve_location(
+            global_asset_hub_nfts_collection(NetworkId::Polkadot, 1),
+            &PolkadotParachain::get(),
+        ),
+        asset_hub_nfts_collection(1),
+    );
+    assert_eq!(
+        normalize_reserve_location(asset_hub_nfts_collection(1), &PolkadotParachain::get()),
+        asset_hub_nfts_collection(1),
+    );
+
+    // The Kusama Asset Hub is another consensus system, so its location stays global.
+    assert_eq!(
+        normalize_reserve_location(
+            global_asset_hub_nfts_collection(NetworkId::Kusama, 1),
+            &PolkadotParachain::get(),
+        ),
+        global_asset_hub_nfts_collection(NetworkId::Kusama, 1),
+    );
+}
+
+#[test]
+fn asset_hub_nft_matches_its_registration_in_either_form() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(register(Concrete(asset_hub_nfts_collection(1))));
+        let class_id = Xnft::foreign_asset_to_local_class(Concrete(asset_hub_nfts_collection(1)))
+            .expect("the collection is registered");
+
+        let global_form = Concrete(global_asset_hub_nfts_collection(NetworkId::Polkadot, 1));
+        assert_ok!(deposit(&nft(global_form, 7), ALICE));
+
+        assert!(matches!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(_),
+        ));
+    });
+}
+
+#[test]
+fn engine_veto_rejects_the_mint_before_any_state_change() {
+    new_test_ext().execute_with(|| {
+        RecordFailedMints::set(true);
+
+        let class_id = register_foreign_collection(1);
+
+        MintVeto::set(Some("the class is full"));
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::FailedToTransactAsset("the class is full")),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(
+            Xnft::pending_deposit(class_id, AssetInstance::Index(7)),
+            None
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 0);
+
+        MintVeto::set(None);
+
+        deposit_derivative(class_id, 1, 7, ALICE);
+    });
+}
+
+#[test]
+fn accounts_with_own_network_are_accepted_if_enabled() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        let deposit_to = |index: u128, network: Option<NetworkId>| {
+            Xnft::deposit_asset(
+                &nft(foreign_asset_id(1), index),
+                &MultiLocation::new(
+                    0,
+                    X1(AccountIndex64 {
+                        network,
+                        index: ALICE,
+                    }),
+                ),
+                Some(&xcm_context(reserve_location())),
+            )
+        };
+
+        assert_eq!(
+            deposit_to(1, Some(Polkadot)),
+            Err(XcmExecutorError::AccountIdConversionFailed.into()),
+        );
+
+        AcceptForeignNetworkAccounts::set(true);
+
+        assert_ok!(deposit_to(1, None));
+        assert_ok!(deposit_to(2, Some(Polkadot)));
+        assert_eq!(
+            deposit_to(3, Some(Kusama)),
+            Err(XcmExecutorError::AccountIdConversionFailed.into()),
+        );
+
+        let derivative = |index| {
+            let status =
+                Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(index));
+            let DerivativeStatus::Active(instance_id) = status else {
+                panic!("the derivative of the instance #{index} isn't active: {status:?}");
+            };
+
+            instance_id
+        };
+
+        assert_eq!(MockNftEngine::owner(class_id, derivative(1)), Some(ALICE));
+        assert_eq!(MockNftEngine::owner(class_id, derivative(2)), Some(ALICE));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(3)),
+            DerivativeStatus::NotExists,
+        );
+    });
+}
+
+#[test]
+fn derivatives_move_only_to_the_reserve_or_allowed_chains() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        let random_chain = MultiLocation::new(1, X1(Parachain(3000)));
+        let transfer_to = |from: MultiLocation, to: MultiLocation| {
+            Xnft::transfer_asset(
+                &nft(foreign_asset_id(1), 7),
+                &from,
+                &to,
+                &xcm_context(reserve_location()),
+            )
+            .map(|_| ())
+        };
+
+        assert_eq!(
+            transfer_to(account_location(ALICE), random_chain),
+            Err(XcmError::Barrier),
+        );
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+
+        assert_ok!(transfer_to(account_location(ALICE), reserve_location()));
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(SIBLING_ACCOUNT_BASE + u64::from(RESERVE_PARA_ID)),
+        );
+
+        let destinations = BoundedVec::truncate_from(vec![random_chain]);
+
+        assert_noop!(
+            Xnft::set_allowed_withdrawal_destinations(
+                RuntimeOrigin::signed(ALICE),
+                class_id,
+                destinations.clone(),
+            ),
+            DispatchError::BadOrigin,
+        );
+        assert_ok!(Xnft::set_allowed_withdrawal_destinations(
+            RuntimeOrigin::root(),
+            class_id,
+            destinations.clone(),
+        ));
+        System::assert_last_event(
+            Event::<Test>::AllowedWithdrawalDestinationsSet {
+                derivative_class_id: class_id,
+                destinations,
+            }
+            .into(),
+        );
+
+        assert_ok!(transfer_to(reserve_location(), random_chain));
+        assert_eq!(
+            MockNftEngine::owner(class_id, instance_id),
+            Some(SIBLING_ACCOUNT_BASE + 3000),
+        );
+    });
+}
+
+#[test]
+fn derivatives_are_withdrawn_only_by_the_reserve_or_allowed_chains() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        let random_chain = MultiLocation::new(1, X1(Parachain(3000)));
+        let withdraw_by = |origin: MultiLocation| {
+            Xnft::withdraw_asset(
+                &foreign_nft,
+                &account_location(ALICE),
+                Some(&xcm_context(origin)),
+            )
+            .map(|_| ())
+        };
+
+        assert_eq!(withdraw_by(random_chain), Err(XcmError::Barrier));
+
+        // An account on the random chain is checked as the chain itself.
+        assert_eq!(
+            withdraw_by(MultiLocation::new(
+                1,
+                X2(
+                    Parachain(3000),
+                    AccountId32 {
+                        network: None,
+                        id: [1; 32],
+                    },
+                ),
+            )),
+            Err(XcmError::Barrier),
+        );
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+
+        assert_ok!(withdraw_by(reserve_location()));
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        // A withdrawal requested on this chain doesn't leave it by itself.
+        assert_ok!(withdraw_by(account_location(ALICE)));
+        assert_ok!(deposit(&foreign_nft, ALICE));
+
+        assert_ok!(Xnft::set_allowed_withdrawal_destinations(
+            RuntimeOrigin::root(),
+            class_id,
+            BoundedVec::truncate_from(vec![random_chain]),
+        ));
+
+        assert_ok!(withdraw_by(random_chain));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(instance_id),
+        );
+    });
+}
+
+#[test]
+fn combined_mint_produces_the_same_state_as_the_decomposed_one() {
+    let mint_derivatives = |combined: bool| {
+        new_test_ext().execute_with(|| {
+            CombinedMint::set(combined);
+            HonorInstanceIdHint::set(true);
+
+            let class_id = register_foreign_collection(1);
+            let deposited_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+            let minted_id = MockNftEngine::mint_derivative_full(
+                &class_id,
+                &BOB,
+                Some(&3),
+                Some(b"metadata".as_slice()),
+            )
+            .expect("the class is operational");
+
+            [deposited_id, minted_id].map(|instance_id| {
+                (
+                    instance_id,
+                    MockNftEngine::owner(class_id, instance_id),
+                    MockNftEngine::metadata(class_id, instance_id),
+                )
+            })
+        })
+    };
+
+    let decomposed = mint_derivatives(false);
+
+    assert_eq!(
+        decomposed,
+        [
+            (7, Some(ALICE), None),
+            (3, Some(BOB), Some(b"metadata".to_vec())),
+        ],
+    );
+    assert_eq!(mint_derivatives(true), decomposed);
+}
+
+#[test]
+fn deposit_mints_the_new_derivative_with_the_carried_metadata() {
+    for combined in [false, true] {
+        new_test_ext().execute_with(|| {
+            CombinedMint::set(combined);
+            IncomingMetadata::set(Some(b"metadata".to_vec()));
+
+            let class_id = register_foreign_collection(1);
+            let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+            assert_eq!(
+                MockNftEngine::metadata(class_id, instance_id),
+                Some(b"metadata".to_vec()),
+            );
+
+            // The metadata over the `MetadataLimit` is ignored.
+            IncomingMetadata::set(Some(vec![0; 33]));
+            let oversized_instance_id = deposit_derivative(class_id, 1, 8, ALICE);
+
+            assert_eq!(
+                MockNftEngine::metadata(class_id, oversized_instance_id),
+                None
+            );
+
+            // A reactivated derivative keeps its original metadata.
+            let foreign_nft = nft(foreign_asset_id(1), 7);
+            assert_ok!(withdraw(&foreign_nft, ALICE));
+
+            IncomingMetadata::set(Some(b"other metadata".to_vec()));
+            assert_ok!(deposit(&foreign_nft, BOB));
+
+            assert_eq!(
+                MockNftEngine::metadata(class_id, instance_id),
+                Some(b"metadata".to_vec()),
+            );
+        });
+    }
+}
+
+#[test]
+fn pause_blocks_all_the_xcm_operations() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        assert_noop!(
+            Xnft::pause(RuntimeOrigin::signed(ALICE)),
+            DispatchError::BadOrigin
+        );
+        assert_ok!(Xnft::pause(RuntimeOrigin::root()));
+        System::assert_last_event(Event::<Test>::PalletPaused.into());
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 8), ALICE),
+            Err(XcmError::NoPermission),
+        );
+        assert_eq!(
+            withdraw(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::NoPermission),
+        );
+        assert_eq!(
+            transfer(&nft(foreign_asset_id(1), 7), ALICE, BOB),
+            Err(XcmError::NoPermission),
+        );
+        assert_noop!(register(foreign_asset_id(2)), Error::<Test>::Paused);
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+        assert_eq!(MockNftEngine::instance_count(class_id), 1);
+
+        assert_ok!(Xnft::unpause(RuntimeOrigin::root()));
+        System::assert_last_event(Event::<Test>::PalletUnpaused.into());
+
+        assert_ok!(transfer(&nft(foreign_asset_id(1), 7), ALICE, BOB));
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 7), BOB));
+        deposit_derivative(class_id, 1, 8, ALICE);
+        assert_ok!(register(foreign_asset_id(2)));
+    });
+}
+
+#[test]
+fn u256_token_ids_round_trip_through_array32() {
+    let mid = U256::from(u128::MAX) + U256::from(7);
+
+    for token_id in [U256::zero(), mid, U256::MAX] {
+        let instance = AssetInstance::from(U256AssetInstance(token_id));
+
+        assert_eq!(
+            U256AssetInstance::try_from(instance),
+            Ok(U256AssetInstance(token_id)),
+        );
+        assert_eq!(
+            TryFromAssetInstance::<U256AssetInstance>::convert(&instance),
+            Some(U256AssetInstance(token_id)),
+        );
+        assert_eq!(
+            TryFromAssetInstance::convert_back(&U256AssetInstance(token_id)),
+            Some(instance),
+        );
+    }
+
+    let mut big_endian_one = [0; 32];
+    big_endian_one[31] = 1;
+
+    assert_eq!(
+        AssetInstance::from(U256AssetInstance(U256::one())),
+        AssetInstance::Array32(big_endian_one),
+    );
+    assert_eq!(
+        U256AssetInstance::try_from(AssetInstance::Index(1)),
+        Err(()),
+    );
+    assert_eq!(
+        TryFromAssetInstance::<U256AssetInstance>::convert(&AssetInstance::Array16([1; 16])),
+        None,
+    );
+}
+
+#[test]
+fn registration_event_carries_the_location_details() {
+    new_test_ext().execute_with(|| {
+        let derivative_class_id = register_foreign_collection(1);
+
+        System::assert_last_event(
+            Event::<Test>::ForeignAssetRegistered {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id,
+                interior_location: Some(X2(Parachain(RESERVE_PARA_ID), GeneralIndex(1))),
+                reserve_para_id: Some(RESERVE_PARA_ID),
+            }
+            .into(),
+        );
+
+        let relay_asset_id = Concrete(MultiLocation::new(1, X1(GeneralIndex(5))));
+        assert_ok!(register(relay_asset_id));
+
+        let derivative_class_id = Xnft::foreign_asset_to_local_class(relay_asset_id)
+            .expect("the foreign asset is registered");
+
+        System::assert_last_event(
+            Event::<Test>::ForeignAssetRegistered {
+                foreign_asset_id: Box::new(relay_asset_id),
+                derivative_class_id,
+                interior_location: Some(X1(GeneralIndex(5))),
+                reserve_para_id: None,
+            }
+            .into(),
+        );
+    });
+}
+
+#[test]
+fn deprecated_asset_rejects_deposits_but_allows_withdrawals() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let active_id = deposit_derivative(class_id, 1, 7, ALICE);
+        let stashed_id = deposit_derivative(class_id, 1, 8, ALICE);
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 8), ALICE));
+
+        let versioned_asset_id = || Box::new(VersionedAssetId::V3(foreign_asset_id(1)));
 
-pub type Balance = u128;
-pub type AccountId = AccountId32;
-
-impl frame_system::Config for Runtime {
-    type RuntimeOrigin = RuntimeOrigin;
-    type RuntimeCall = RuntimeCall;
-    type Nonce = u64;
-    type Hash = H256;
-    type Hashing = ::sp_runtime::traits::BlakeTwo256;
-    type AccountId = AccountId;
-    type Lookup = IdentityLookup<Self::AccountId>;
-    type Block = Block;
-    type RuntimeEvent = RuntimeEvent;
-    type BlockHashCount = ConstU64<250>;
-    type BlockWeights = ();
-    type BlockLength = ();
-    type Version = ();
-    type PalletInfo = PalletInfo;
-    type AccountData = pallet_balances::AccountData<Balance>;
-    type OnNewAccount = ();
-    type OnKilledAccount = ();
-    type DbWeight = ();
-    type BaseCallFilter = Everything;
-    type SystemWeightInfo = ();
-    type SS58Prefix = ();
-    type OnSetCode = cumulus_pallet_parachain_system::ParachainSetCode<Runtime>;
-    type MaxConsumers = ConstU32<16>;
-}
-
-impl pallet_balances::Config for Runtime {
-    type MaxLocks = ConstU32<50>;
-    type Balance = Balance;
-    type RuntimeEvent = RuntimeEvent;
-    type DustRemoval = ();
-    type ExistentialDeposit = ConstU128<1>;
-    type AccountStore = System;
-    type WeightInfo = ();
-    type MaxReserves = ConstU32<50>;
-    type ReserveIdentifier = [u8; 8];
-    type RuntimeHoldReason = RuntimeHoldReason;
-    type RuntimeFreezeReason = RuntimeFreezeReason;
-    type FreezeIdentifier = [u8; 8];
-    type MaxHolds = ();
-    type MaxFreezes = ();
-}
-
-impl parachain_info::Config for Runtime {}
-
-impl cumulus_pallet_parachain_system::Config for Runtime {
-    type RuntimeEvent = RuntimeEvent;
-    type OnSystemEvent = ();
-    type SelfParaId = ();
-    type OutboundXcmpMessageSource = XcmpQueue;
-    type DmpMessageHandler = ();
-    type ReservedDmpWeight = ();
-    type XcmpMessageHandler = XcmpQueue;
-    type ReservedXcmpWeight = ();
-    type CheckAssociatedRelayNumber = AnyRelayNumber;
-}
-
-impl cumulus_pallet_xcmp_queue::Config for Runtime {
-    type RuntimeEvent = RuntimeEvent;
-    type XcmExecutor = XcmExecutor<XcmConfig>;
-    type ChannelInfo = ParachainSystem;
-    type VersionWrapper = ();
-    type ExecuteOverweightOrigin = EnsureRoot<AccountId>;
-    type ControllerOrigin = EnsureRoot<AccountId>;
-    type ControllerOriginConverter = ();
-    type WeightInfo = ();
-    type PriceForSiblingDelivery = NoPriceForMessageDelivery<ParaId>;
-}
-
-pub struct XcmConfig;
-impl xcm_executor::Config for XcmConfig {
-    type RuntimeCall = RuntimeCall;
-    type XcmSender = XcmRouter;
-    type AssetTransactor = DummyAssetTransactor;
-    type OriginConverter = ();
-    type IsReserve = ();
-    type IsTeleporter = ();
-    type UniversalLocation = UniversalLocation;
-    type Barrier = Barrier;
-    type Weigher = FixedWeightBounds<UnitWeightCost, RuntimeCall, MaxInstructions>;
-    type Trader = FreeForAll;
-    type ResponseHandler = ();
-    type AssetTrap = PolkadotXcm;
-    type AssetClaims = PolkadotXcm;
-    type SubscriptionService = PolkadotXcm;
-    type AssetLocker = PolkadotXcm;
-    type AssetExchanger = ();
-    type PalletInstancesInfo = ();
-    type MaxAssetsIntoHolding = MaxAssetsIntoHolding;
-    type FeeManager = ();
-    type MessageExporter = ();
-    type UniversalAliases = Nothing;
-    type CallDispatcher = RuntimeCall;
-    type SafeCallFilter = Everything;
-    type Aliasers = ();
-}
-
-pub struct FreeForAll;
-
-impl WeightTrader for FreeForAll {
-    fn new() -> Self {
-        Self
+        assert_noop!(
+            Xnft::deprecate_foreign_asset(RuntimeOrigin::signed(ALICE), versioned_asset_id()),
+            DispatchError::BadOrigin,
+        );
+        assert_noop!(
+            Xnft::deprecate_foreign_asset(
+                RuntimeOrigin::root(),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(2))),
+            ),
+            Error::<Test>::ForeignAssetNotRegistered,
+        );
+
+        assert_ok!(Xnft::deprecate_foreign_asset(
+            RuntimeOrigin::root(),
+            versioned_asset_id(),
+        ));
+        System::assert_last_event(
+            Event::<Test>::ForeignAssetDeprecated {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id: class_id,
+            }
+            .into(),
+        );
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 9), ALICE),
+            Err(XcmError::NoPermission),
+        );
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 8), ALICE),
+            Err(XcmError::NoPermission),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(8)),
+            DerivativeStatus::Stashed(stashed_id),
+        );
+
+        assert_noop!(deregister(1), Error::<Test>::DerivativesStillExist);
+
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 7), ALICE));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Stashed(active_id),
+        );
+
+        assert_ok!(deregister(1));
+        assert_eq!(Xnft::deprecated_derivative_class(class_id), None);
+    });
+}
+
+#[test]
+fn reserved_range_is_used_by_the_later_derivative_mints() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        assert_eq!(deposit_derivative(class_id, 1, 7, ALICE), 0);
+
+        let versioned_asset_id = || Box::new(VersionedAssetId::V3(foreign_asset_id(1)));
+
+        assert_noop!(
+            Xnft::reserve_derivative_range(RuntimeOrigin::signed(ALICE), versioned_asset_id(), 3),
+            DispatchError::BadOrigin,
+        );
+        assert_noop!(
+            Xnft::reserve_derivative_range(
+                RuntimeOrigin::root(),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(2))),
+                3,
+            ),
+            Error::<Test>::ForeignAssetNotRegistered,
+        );
+
+        assert_ok!(Xnft::reserve_derivative_range(
+            RuntimeOrigin::root(),
+            versioned_asset_id(),
+            3,
+        ));
+        System::assert_last_event(
+            Event::<Test>::DerivativeRangeReserved {
+                foreign_asset_id: Box::new(foreign_asset_id(1)),
+                derivative_class_id: class_id,
+                count: 3,
+            }
+            .into(),
+        );
+        assert_eq!(MockNftEngine::reserved_range(class_id), Some((1, 4)));
+
+        // The other mints don't take the reserved IDs.
+        assert_eq!(MockNftEngine::mint(class_id, BOB), 4);
+
+        assert_eq!(deposit_derivative(class_id, 1, 8, ALICE), 1);
+        assert_eq!(deposit_derivative(class_id, 1, 9, ALICE), 2);
+        assert_eq!(deposit_derivative(class_id, 1, 10, ALICE), 3);
+        assert_eq!(MockNftEngine::reserved_range(class_id), None);
+
+        assert_eq!(deposit_derivative(class_id, 1, 11, ALICE), 5);
+    });
+}
+
+#[test]
+fn versioned_asset_ids_resolve_to_the_stored_form() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        // The `xcm` crate in use has no XCM v4, so the equivalence is checked
+        // between the relative and the global forms of the same v3 asset ID.
+        let global_asset_id = Concrete(MultiLocation::new(
+            2,
+            X3(
+                GlobalConsensus(Polkadot),
+                Parachain(RESERVE_PARA_ID),
+                GeneralIndex(1),
+            ),
+        ));
+
+        for asset_id in [foreign_asset_id(1), global_asset_id] {
+            assert_eq!(
+                Xnft::simplify_versioned_asset_id_within(
+                    VersionedAssetId::V3(asset_id),
+                    &UniversalLocation::get(),
+                ),
+                Some(foreign_asset_id(1)),
+            );
+            assert_eq!(
+                Xnft::local_class_of_foreign_asset(VersionedAssetId::V3(asset_id)),
+                Some(class_id),
+            );
+            assert_eq!(
+                Xnft::foreign_of_derivative(
+                    VersionedAssetId::V3(asset_id),
+                    AssetInstance::Index(7),
+                ),
+                Some(DerivativeStatus::Active(instance_id)),
+            );
+        }
+    });
+}
+
+#[test]
+fn mint_reusing_a_mapped_derivative_id_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        // The next instance ID the engine allocates already maps to another foreign instance.
+        DerivativeToForeignInstance::<Test>::insert(class_id, 0, AssetInstance::Index(99));
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::FailedToTransactAsset("derivative id reuse")),
+        );
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(
+            DerivativeToForeignInstance::<Test>::get(class_id, 0),
+            Some(AssetInstance::Index(99)),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 0);
+    });
+}
+
+#[test]
+fn burn_only_engine_mints_a_fresh_derivative_on_redeposit() {
+    new_test_ext().execute_with(|| {
+        StashDerivatives::set(false);
+
+        let class_id = register_foreign_collection(1);
+        let first_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 7), ALICE));
+
+        assert_eq!(MockNftEngine::owner(class_id, first_id), None);
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(
+            DerivativeToForeignInstance::<Test>::get(class_id, first_id),
+            None
+        );
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+
+        let second_id = deposit_derivative(class_id, 1, 7, BOB);
+
+        assert_ne!(second_id, first_id);
+        assert_eq!(MockNftEngine::owner(class_id, second_id), Some(BOB));
+        assert_eq!(
+            DerivativeToForeignInstance::<Test>::get(class_id, first_id),
+            None
+        );
+        assert_eq!(
+            DerivativeToForeignInstance::<Test>::get(class_id, second_id),
+            Some(AssetInstance::Index(7)),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(Xnft::stashed_count(class_id), 0);
+    });
+}
+
+#[test]
+fn instance_id_hint_is_honored_only_by_the_engines_supporting_it() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        // The hint is ignored: the engine allocates the next free instance ID.
+        assert_eq!(deposit_derivative(class_id, 1, 7, ALICE), 0);
+
+        HonorInstanceIdHint::set(true);
+
+        // The hint is the foreign instance index.
+        assert_eq!(deposit_derivative(class_id, 1, 9, ALICE), 9);
+
+        // The hint is taken, so the engine falls back to the allocation.
+        MockNftEngine::mint_local(&class_id, &11, &BOB).expect("the instance ID is free");
+        assert_eq!(deposit_derivative(class_id, 1, 11, ALICE), 1);
+
+        // No hint can be computed from a non-index foreign instance.
+        assert_ok!(deposit(
+            &MultiAsset {
+                id: foreign_asset_id(1),
+                fun: NonFungible(AssetInstance::Array32([1; 32])),
+            },
+            ALICE,
+        ));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Array32([1; 32]),),
+            DerivativeStatus::Active(2),
+        );
+    });
+}
+
+#[test]
+fn array_instances_are_rejected_when_only_index_is_configured() {
+    new_test_ext().execute_with(|| {
+        IndexOnlyForeignInstances::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let array_nft = MultiAsset {
+            id: foreign_asset_id(1),
+            fun: NonFungible(AssetInstance::Array32([1; 32])),
+        };
+
+        assert_eq!(
+            deposit(&array_nft, ALICE),
+            Err(XcmExecutorError::InstanceConversionFailed.into()),
+        );
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Array32([1; 32])),
+            DerivativeStatus::NotExists,
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+
+        deposit_derivative(class_id, 1, 7, ALICE);
+
+        IndexOnlyForeignInstances::set(false);
+
+        assert_ok!(deposit(&array_nft, ALICE));
+    });
+}
+
+#[test]
+fn silenced_class_emits_no_instance_events() {
+    new_test_ext().execute_with(|| {
+        assert_ok!(Xnft::register_foreign_asset(
+            RuntimeOrigin::root(),
+            Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+            (),
+            None,
+            Some(false),
+        ));
+        let silenced_class_id = Xnft::foreign_asset_to_local_class(foreign_asset_id(1))
+            .expect("the foreign asset is registered");
+        let loud_class_id = register_foreign_collection(2);
+
+        assert!(!Xnft::class_events_enabled(&silenced_class_id));
+        assert!(Xnft::class_events_enabled(&loud_class_id));
+
+        System::reset_events();
+
+        for collection in [1, 2] {
+            let foreign_nft = nft(foreign_asset_id(collection), 7);
+
+            assert_ok!(deposit(&foreign_nft, ALICE));
+            assert_ok!(transfer(&foreign_nft, ALICE, BOB));
+            assert_ok!(withdraw(&foreign_nft, BOB));
+        }
+
+        let instance_event_classes: Vec<_> = xnft_events()
+            .into_iter()
+            .filter_map(|event| match event {
+                Event::Deposited { class_instance, .. }
+                | Event::Withdrawn { class_instance, .. }
+                | Event::Transferred { class_instance, .. } => Some(*class_instance.class_id()),
+                Event::DerivativeBurned { derivative, .. }
+                | Event::DerivativeStashed { derivative, .. } => Some(derivative.class_id),
+                _ => None,
+            })
+            .collect();
+
+        assert!(!instance_event_classes.is_empty());
+        assert!(instance_event_classes
+            .iter()
+            .all(|class_id| *class_id == loud_class_id));
+    });
+}
+
+#[test]
+fn derivative_cap_rejects_the_mints_beyond_it() {
+    new_test_ext().execute_with(|| {
+        MaxDerivativesPerClass::set(Some(2));
+
+        let class_id = register_foreign_collection(1);
+        deposit_derivative(class_id, 1, 7, ALICE);
+        deposit_derivative(class_id, 1, 8, ALICE);
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 9), ALICE),
+            Err(XcmError::NotDepositable),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 2);
+
+        // The stash and its reactivation reuse the existing derivative.
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 7), ALICE));
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+        deposit_derivative(class_id, 1, 7, BOB);
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+
+        StashDerivatives::set(false);
+
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 8), ALICE));
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+
+        deposit_derivative(class_id, 1, 9, ALICE);
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+    });
+}
+
+#[test]
+fn unconvertible_beneficiary_deposits_go_to_the_escrow_if_set() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        let unconvertible_location = MultiLocation::new(
+            0,
+            X1(AccountKey20 {
+                network: None,
+                key: [1; 20],
+            }),
+        );
+        let deposit_to_unconvertible = || {
+            Xnft::deposit_asset(
+                &nft(foreign_asset_id(1), 7),
+                &unconvertible_location,
+                Some(&xcm_context(reserve_location())),
+            )
+        };
+
+        assert_eq!(
+            deposit_to_unconvertible(),
+            Err(XcmExecutorError::AccountIdConversionFailed.into()),
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+
+        UnconvertibleDestinationEscrow::set(Some(CHARLIE));
+
+        assert_ok!(deposit_to_unconvertible());
+        System::assert_last_event(
+            Event::<Test>::DepositedToEscrow {
+                original_location: unconvertible_location,
+            }
+            .into(),
+        );
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(CHARLIE));
+
+        // The convertible beneficiaries are unaffected by the escrow.
+        assert_eq!(
+            MockNftEngine::owner(class_id, deposit_derivative(class_id, 1, 8, ALICE)),
+            Some(ALICE),
+        );
+    });
+}
+
+frame_support::parameter_types! {
+    pub EthereumMainnet: NetworkId = Ethereum { chain_id: 1 };
+}
+
+/// Converts the `AccountKey20` junction without a network into the ERC-721 contract address.
+struct ContractAddressConvert;
+impl MaybeEquivalence<Junction, [u8; 20]> for ContractAddressConvert {
+    fn convert(junction: &Junction) -> Option<[u8; 20]> {
+        match junction {
+            AccountKey20 { network: None, key } => Some(*key),
+            _ => None,
+        }
     }
 
-    fn buy_weight(
-        &mut self,
-        weight: Weight,
-        payment: Assets,
-        _xcm: &XcmContext,
-    ) -> Result<Assets, XcmError> {
-        log::trace!(target: "fassets::weight", "buy_weight weight: {:?}, payment: {:?}", weight, payment);
-        Ok(payment)
+    fn convert_back(key: &[u8; 20]) -> Option<Junction> {
+        Some(AccountKey20 {
+            network: None,
+            key: *key,
+        })
     }
 }
 
-parameter_types! {
-    pub const RelayNetwork: NetworkId = NetworkId::Kusama;
-    pub UniversalLocation: InteriorMultiLocation =
-        X2(GlobalConsensus(RelayNetwork::get()), Parachain(ParachainInfo::parachain_id().into()));
-
-    pub const UnitWeightCost: Weight = Weight::from_parts(10, 10);
-    pub const MaxInstructions: u32 = 100;
-    pub const MaxAssetsIntoHolding: u32 = 64;
-
-}
-
-pub type LocalOriginToLocation = SignedToAccountId32<RuntimeOrigin, AccountId, RelayNetwork>;
-
-// Needed decl_test_network
-pub type XcmRouter = ParachainXcmRouter<ParachainInfo>;
-pub type Barrier = (TakeWeightCredit, AllowTopLevelPaidExecutionFrom<Everything>);
-
-impl pallet_xcm::Config for Runtime {
-    type RuntimeEvent = RuntimeEvent;
-    type SendXcmOrigin = EnsureXcmOrigin<RuntimeOrigin, LocalOriginToLocation>;
-    type XcmRouter = XcmRouter;
-    type ExecuteXcmOrigin = EnsureXcmOrigin<RuntimeOrigin, LocalOriginToLocation>;
-    type XcmExecuteFilter = Everything;
-    type XcmExecutor = XcmExecutor<XcmConfig>;
-    type XcmTeleportFilter = Nothing;
-    type XcmReserveTransferFilter = Everything;
-    type Weigher = FixedWeightBounds<UnitWeightCost, RuntimeCall, MaxInstructions>;
-    type UniversalLocation = UniversalLocation;
-    type RuntimeOrigin = RuntimeOrigin;
-    type RuntimeCall = RuntimeCall;
-    const VERSION_DISCOVERY_QUEUE_SIZE: u32 = 100;
-    type AdvertisedXcmVersion = pallet_xcm::CurrentXcmVersion;
-    type Currency = Balances;
-    type CurrencyMatcher = ();
-    type TrustedLockers = ();
-    type SovereignAccountOf = ();
-    type MaxLockers = ConstU32<8>;
-    type WeightInfo = pallet_xcm::TestWeightInfo;
-    type AdminOrigin = EnsureRoot<AccountId>;
-    type MaxRemoteLockConsumers = ConstU32<0>;
-    type RemoteLockConsumerIdentifier = ();
-    #[cfg(feature = "runtime-benchmarks")]
-    type ReachableDest = ReachableDest;
-}
-
-type Block = frame_system::mocking::MockBlock<Runtime>;
-
-struct DummyAssetTransactor;
-impl TransactAsset for DummyAssetTransactor {
-    fn can_check_in(
-        _origin: &MultiLocation,
-        _what: &MultiAsset,
-        _context: &XcmContext,
-    ) -> XcmResult {
-        Err(XcmError::Unimplemented)
+type EthereumCollectionConvert =
+    BridgedConsensusConvert<EthereumMainnet, [u8; 20], ContractAddressConvert>;
+
+#[test]
+fn ethereum_collections_are_converted_registered_and_categorized() {
+    new_test_ext().execute_with(|| {
+        let contract = [7; 20];
+        let collection_location = MultiLocation::new(
+            2,
+            X2(
+                GlobalConsensus(EthereumMainnet::get()),
+                AccountKey20 {
+                    network: None,
+                    key: contract,
+                },
+            ),
+        );
+
+        assert_eq!(
+            EthereumCollectionConvert::convert(&collection_location),
+            Some(contract),
+        );
+        assert_eq!(
+            EthereumCollectionConvert::convert_back(&contract),
+            Some(collection_location),
+        );
+
+        let mismatched_locations = [
+            MultiLocation::new(
+                2,
+                X2(
+                    GlobalConsensus(Ethereum { chain_id: 11155111 }),
+                    AccountKey20 {
+                        network: None,
+                        key: contract,
+                    },
+                ),
+            ),
+            MultiLocation::new(1, collection_location.interior),
+            collection_location
+                .pushed_with_interior(GeneralIndex(1))
+                .expect("the location has room for a junction"),
+        ];
+        for location in mismatched_locations {
+            assert_eq!(EthereumCollectionConvert::convert(&location), None);
+        }
+
+        assert_ok!(register(Concrete(collection_location)));
+        let class_id = Xnft::foreign_asset_to_local_class(Concrete(collection_location))
+            .expect("the foreign asset is registered");
+
+        let categorized = Xnft::categorize_batch(vec![
+            (
+                VersionedAssetId::V3(Concrete(collection_location)),
+                AssetInstance::Index(5),
+            ),
+            (
+                VersionedAssetId::V3(Concrete(mismatched_locations[0])),
+                AssetInstance::Index(5),
+            ),
+        ]);
+
+        assert_eq!(
+            categorized,
+            vec![
+                Some(CategorizedClassInstance::derivative(
+                    Concrete(collection_location),
+                    AssetInstance::Index(5),
+                    class_id,
+                    DerivativeStatus::NotExists,
+                )),
+                None,
+            ],
+        );
+    });
+}
+
+#[test]
+fn recomputation_restores_the_corrupted_counters() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        for index in [7, 8, 9] {
+            deposit_derivative(class_id, 1, index, ALICE);
+        }
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 8), ALICE));
+
+        DerivativeCountPerClass::<Test>::insert(class_id, 10);
+        StashedCountPerClass::<Test>::insert(class_id, 5);
+
+        assert_noop!(
+            Xnft::recompute_class_counters(RuntimeOrigin::signed(ALICE), class_id, 2),
+            DispatchError::BadOrigin,
+        );
+
+        System::reset_events();
+
+        // Only two of the three entries are visited, so the counters are intact yet.
+        assert_ok!(Xnft::recompute_class_counters(
+            RuntimeOrigin::root(),
+            class_id,
+            2
+        ));
+        assert!(Xnft::counter_recomputation(class_id).is_some());
+        assert_eq!(Xnft::derivative_count(class_id), 10);
+        assert_eq!(Xnft::stashed_count(class_id), 5);
+        assert!(xnft_events().is_empty());
+
+        assert_ok!(Xnft::recompute_class_counters(
+            RuntimeOrigin::root(),
+            class_id,
+            2
+        ));
+        assert_eq!(Xnft::counter_recomputation(class_id), None);
+        assert_eq!(Xnft::derivative_count(class_id), 3);
+        assert_eq!(Xnft::stashed_count(class_id), 1);
+        System::assert_last_event(Event::<Test>::CountersRecomputed { class_id }.into());
+    });
+}
+
+#[test]
+fn instance_iterators_filter_the_derivative_statuses() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let other_class_id = register_foreign_collection(2);
+
+        let first_id = deposit_derivative(class_id, 1, 7, ALICE);
+        let second_id = deposit_derivative(class_id, 1, 8, ALICE);
+        let third_id = deposit_derivative(class_id, 1, 9, ALICE);
+        deposit_derivative(other_class_id, 2, 7, ALICE);
+
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 8), ALICE));
+        ForeignInstanceToDerivativeStatus::<Test>::insert(
+            class_id,
+            AssetInstance::Index(10),
+            DerivativeStatus::NotExists,
+        );
+
+        let sorted = |iter: &mut dyn Iterator<Item = (AssetInstance, u32)>| {
+            let mut instances: Vec<_> = iter.collect();
+            instances.sort_by_key(|(_, instance_id)| *instance_id);
+            instances
+        };
+
+        assert_eq!(
+            sorted(&mut Xnft::active_instances(&class_id)),
+            vec![
+                (AssetInstance::Index(7), first_id),
+                (AssetInstance::Index(9), third_id),
+            ],
+        );
+        assert_eq!(
+            sorted(&mut Xnft::stashed_instances(&class_id)),
+            vec![(AssetInstance::Index(8), second_id)],
+        );
+        assert_eq!(Xnft::stashed_instances(&other_class_id).count(), 0);
+    });
+}
+
+#[test]
+fn variable_length_instance_ids_flow_through_deposit_and_withdrawal() {
+    new_test_ext().execute_with(|| {
+        let context = xcm_context(reserve_location());
+
+        assert_ok!(BytesIdXnft::register_foreign_asset(
+            RuntimeOrigin::root(),
+            Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+            (),
+            None,
+            None,
+        ));
+        let derivative_class_id = BytesIdXnft::foreign_asset_to_local_class(foreign_asset_id(1))
+            .expect("the foreign asset is registered");
+        let local_class_id = BytesIdNftEngine::create(BOB);
+
+        let instances = [
+            (AssetInstance::Array4([1; 4]), vec![1; 4]),
+            (AssetInstance::Array16([2; 16]), vec![2; 16]),
+            (AssetInstance::Array32([3; 32]), vec![3; 32]),
+        ];
+
+        for (asset_instance, bytes) in instances {
+            let instance_id = BytesInstanceId::truncate_from(bytes);
+
+            assert_eq!(
+                BytesAssetInstance::convert(&asset_instance),
+                Some(instance_id.clone()),
+            );
+            assert_eq!(
+                BytesAssetInstance::convert_back(&instance_id),
+                Some(asset_instance),
+            );
+
+            // The local NFT is withdrawn into the custody and deposited back.
+            let local_nft = MultiAsset {
+                id: local_asset_id(local_class_id),
+                fun: NonFungible(asset_instance),
+            };
+            BytesIdNftEngine::mint(local_class_id, instance_id.clone(), ALICE);
+
+            assert_ok!(BytesIdXnft::withdraw_asset(
+                &local_nft,
+                &account_location(ALICE),
+                Some(&context),
+            ));
+            assert_eq!(
+                BytesIdNftEngine::class_instance_owner(&local_class_id, &instance_id),
+                Some(PALLET_ACCOUNT),
+            );
+
+            assert_ok!(BytesIdXnft::deposit_asset(
+                &local_nft,
+                &account_location(BOB),
+                Some(&context),
+            ));
+            assert_eq!(
+                BytesIdNftEngine::class_instance_owner(&local_class_id, &instance_id),
+                Some(BOB),
+            );
+
+            // The engine mints the derivative at the foreign instance bytes.
+            let foreign_nft = MultiAsset {
+                id: foreign_asset_id(1),
+                fun: NonFungible(asset_instance),
+            };
+
+            assert_ok!(BytesIdXnft::deposit_asset(
+                &foreign_nft,
+                &account_location(ALICE),
+                Some(&context),
+            ));
+            assert_eq!(
+                BytesIdXnft::foreign_instance_to_derivative_status(
+                    derivative_class_id,
+                    asset_instance,
+                ),
+                DerivativeStatus::Active(instance_id.clone()),
+            );
+            assert_eq!(
+                DerivativeToForeignInstance::<Test, Instance2>::get(
+                    derivative_class_id,
+                    &instance_id,
+                ),
+                Some(asset_instance),
+            );
+
+            assert_ok!(BytesIdXnft::withdraw_asset(
+                &foreign_nft,
+                &account_location(ALICE),
+                Some(&context),
+            ));
+            assert_eq!(
+                BytesIdNftEngine::class_instance_owner(&derivative_class_id, &instance_id),
+                None,
+            );
+            assert_eq!(
+                BytesIdXnft::foreign_instance_to_derivative_status(
+                    derivative_class_id,
+                    asset_instance,
+                ),
+                DerivativeStatus::NotExists,
+            );
+        }
+
+        BytesIdXnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn trust_model_selects_the_teleport_or_reserve_accounting_per_message() {
+    new_test_ext().execute_with(|| {
+        LocalDeposit::set(LocalDepositMode::MintIfMissing);
+
+        let teleporter = MultiLocation::new(1, X1(Parachain(3000)));
+        TeleportOrigins::set(vec![teleporter]);
+
+        let class_id = MockNftEngine::create(BOB);
+        let deposit_from = |origin: MultiLocation, index: u128| {
+            Xnft::deposit_asset(
+                &nft(local_asset_id(class_id), index),
+                &account_location(ALICE),
+                Some(&xcm_context(origin)),
+            )
+        };
+
+        // The reserve accounting mints the missing instance.
+        assert_ok!(deposit_from(reserve_location(), 5));
+        assert_eq!(MockNftEngine::owner(class_id, 5), Some(ALICE));
+
+        // The teleport accounting requires the instance to be checked out into the custody.
+        assert_eq!(deposit_from(teleporter, 6), Err(XcmError::NotDepositable));
+        assert_eq!(MockNftEngine::owner(class_id, 6), None);
+
+        assert_ok!(MockNftEngine::mint_local(&class_id, &6, &PALLET_ACCOUNT));
+        assert_ok!(deposit_from(teleporter, 6));
+        assert_eq!(MockNftEngine::owner(class_id, 6), Some(ALICE));
+
+        // The teleported-out instance is checked out into the custody.
+        assert_ok!(Xnft::withdraw_asset(
+            &nft(local_asset_id(class_id), 6),
+            &account_location(ALICE),
+            Some(&xcm_context(teleporter)),
+        ));
+        assert_eq!(MockNftEngine::owner(class_id, 6), Some(PALLET_ACCOUNT));
+    });
+}
+
+#[test]
+fn registration_rejects_the_disallowed_junctions() {
+    new_test_ext().execute_with(|| {
+        let disallowed_junctions = [
+            Plurality {
+                id: BodyId::Technical,
+                part: BodyPart::Voice,
+            },
+            OnlyChild,
+            AccountIndex64 {
+                network: None,
+                index: 1,
+            },
+        ];
+
+        for junction in disallowed_junctions {
+            assert_noop!(
+                register(Concrete(MultiLocation::new(
+                    1,
+                    X2(Parachain(RESERVE_PARA_ID), junction),
+                ))),
+                Error::<Test>::DisallowedJunction,
+            );
+        }
+
+        assert_ok!(register(Concrete(MultiLocation::new(
+            1,
+            X3(
+                Parachain(RESERVE_PARA_ID),
+                PalletInstance(52),
+                GeneralIndex(1),
+            ),
+        ))));
+    });
+}
+
+#[test]
+fn default_dispatch_error_conversion_mirrors_the_unit_converter() {
+    let module_error: DispatchError = Error::<Test>::Paused.into();
+    let arithmetic_errors = [
+        ArithmeticError::Underflow,
+        ArithmeticError::Overflow,
+        ArithmeticError::DivisionByZero,
+    ]
+    .map(DispatchError::Arithmetic);
+
+    assert_eq!(
+        default_dispatch_error_to_xcm(DispatchError::BadOrigin),
+        XcmError::BadOrigin,
+    );
+    assert_eq!(
+        default_dispatch_error_to_xcm(module_error),
+        XcmError::FailedToTransactAsset("Paused"),
+    );
+    assert_eq!(
+        default_dispatch_error_to_xcm(DispatchError::Module(ModuleError {
+            index: 1,
+            error: [0; 4],
+            message: None,
+        })),
+        XcmError::FailedToTransactAsset("Unknown module error"),
+    );
+
+    for error in arithmetic_errors {
+        assert_eq!(
+            default_dispatch_error_to_xcm(error),
+            XcmError::FailedToTransactAsset(error.into()),
+        );
     }
 
-    fn check_in(_origin: &MultiLocation, _what: &MultiAsset, _context: &XcmContext) {}
+    for error in [DispatchError::BadOrigin, module_error]
+        .into_iter()
+        .chain(arithmetic_errors)
+    {
+        assert_eq!(
+            default_dispatch_error_to_xcm(error),
+            <() as DispatchErrorsConvert<Test>>::convert(error),
+        );
+    }
+}
 
-    fn can_check_out(
-        _dest: &MultiLocation,
-        _what: &MultiAsset,
-        _context: &XcmContext,
-    ) -> XcmResult {
-        Err(XcmError::Unimplemented)
+#[test]
+fn reserve_royalty_is_set_only_on_the_freshly_minted_derivatives() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let royalty_nft = nft(foreign_asset_id(1), 7);
+        let plain_nft = nft(foreign_asset_id(1), 8);
+
+        IncomingRoyalty::set(Some(Royalty {
+            creator: CHARLIE,
+            basis_points: 500,
+        }));
+
+        assert_ok!(deposit(&royalty_nft, ALICE));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_eq!(
+            MockNftEngine::royalty(class_id, instance_id),
+            Some((CHARLIE, 500)),
+        );
+
+        assert_ok!(withdraw(&royalty_nft, ALICE));
+
+        IncomingRoyalty::set(Some(Royalty {
+            creator: BOB,
+            basis_points: 1_000,
+        }));
+
+        assert_ok!(deposit(&royalty_nft, BOB));
+
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(instance_id),
+        );
+        assert_eq!(
+            MockNftEngine::royalty(class_id, instance_id),
+            Some((CHARLIE, 500)),
+        );
+
+        IncomingRoyalty::set(None);
+
+        assert_ok!(deposit(&plain_nft, BOB));
+
+        let DerivativeStatus::Active(plain_instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(8))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_eq!(MockNftEngine::royalty(class_id, plain_instance_id), None);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn burned_derivative_id_is_reused_on_redeposit_if_preserved() {
+    new_test_ext().execute_with(|| {
+        PreserveIdAcrossBurn::set(true);
+        StashDerivatives::set(false);
+
+        let class_id = register_foreign_collection(1);
+        let burned_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        assert_ok!(withdraw(&nft(foreign_asset_id(1), 7), ALICE));
+
+        assert_eq!(MockNftEngine::owner(class_id, burned_id), None);
+        assert_eq!(
+            Xnft::retained_derivative_id(class_id, AssetInstance::Index(7)),
+            Some(burned_id),
+        );
+
+        // Another derivative takes the next allocated ID in the meantime.
+        assert_ne!(deposit_derivative(class_id, 1, 8, ALICE), burned_id);
+
+        // The retained ID takes precedence over the foreign instance index as the hint.
+        HonorInstanceIdHint::set(true);
+
+        assert_eq!(deposit_derivative(class_id, 1, 7, BOB), burned_id);
+        assert_eq!(MockNftEngine::owner(class_id, burned_id), Some(BOB));
+        assert_eq!(
+            Xnft::retained_derivative_id(class_id, AssetInstance::Index(7)),
+            None,
+        );
+        assert_eq!(
+            Xnft::derivative_to_foreign_instance(class_id, burned_id),
+            Some(AssetInstance::Index(7)),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 2);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+#[should_panic(expected = "the derivative doesn't map back to its foreign asset instance")]
+fn storage_consistency_assertion_detects_a_missing_reverse_mapping() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        let instance_id = deposit_derivative(class_id, 1, 7, ALICE);
+
+        Xnft::assert_storage_consistent();
+
+        DerivativeToForeignInstance::<Test>::remove(class_id, instance_id);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+#[should_panic(expected = "the foreign asset mappings are inconsistent")]
+fn storage_consistency_assertion_detects_a_one_sided_class_mapping() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        Xnft::assert_storage_consistent();
+
+        LocalClassToForeignAsset::<Test>::remove(class_id);
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn block_summary_counts_the_flow_and_skips_the_idle_blocks() {
+    new_test_ext().execute_with(|| {
+        EmitBlockSummary::set(true);
+        StashDerivatives::set(false);
+
+        register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+
+        assert_ok!(deposit(&foreign_nft, ALICE));
+        assert_ok!(transfer(&foreign_nft, ALICE, BOB));
+        assert_ok!(withdraw(&foreign_nft, BOB));
+
+        <Xnft as Hooks<u64>>::on_finalize(1);
+
+        System::assert_last_event(
+            Event::<Test>::BlockFlowSummary {
+                deposits: 1,
+                withdrawals: 1,
+                transfers: 1,
+                mints: 1,
+                burns: 1,
+            }
+            .into(),
+        );
+        assert_eq!(BlockFlow::<Test>::get(), FlowSummary::default());
+
+        let event_count = System::events().len();
+
+        <Xnft as Hooks<u64>>::on_finalize(2);
+
+        assert_eq!(System::events().len(), event_count);
+    });
+}
+
+#[test]
+fn block_summary_is_not_recorded_if_disabled() {
+    new_test_ext().execute_with(|| {
+        register_foreign_collection(1);
+
+        assert_ok!(deposit(&nft(foreign_asset_id(1), 7), ALICE));
+        assert_eq!(BlockFlow::<Test>::get(), FlowSummary::default());
+
+        <Xnft as Hooks<u64>>::on_finalize(1);
+
+        assert!(!xnft_events()
+            .iter()
+            .any(|event| matches!(event, Event::BlockFlowSummary { .. })));
+    });
+}
+
+#[test]
+fn deposit_categorized_before_another_mint_is_rejected() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+
+        // Both deposits are categorized before either of them mints the derivative.
+        let categorize = || {
+            Xnft::class_instance(&foreign_asset_id(1), &AssetInstance::Index(7))
+                .expect("the foreign asset is registered")
+        };
+        let first_deposit = categorize();
+        let stale_deposit = categorize();
+
+        assert_ok!(Xnft::deposit_class_instance(first_deposit, &ALICE, None));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the deposit");
+        };
+
+        assert_eq!(
+            Xnft::deposit_class_instance(stale_deposit, &BOB, None),
+            Err(XcmError::NotDepositable),
+        );
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+        assert_eq!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(instance_id),
+        );
+        assert_eq!(Xnft::derivative_count(class_id), 1);
+        assert_eq!(
+            Xnft::pending_deposit(class_id, AssetInstance::Index(7)),
+            None
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+mod simplification {
+    use super::*;
+
+    /// Simplifies the `location` as an asset ID relative to the mock `UniversalLocation`
+    /// and checks that simplifying it again changes nothing.
+    fn simplify(location: MultiLocation) -> MultiLocation {
+        let simplified = Xnft::simplify_asset_id(Concrete(location));
+
+        assert_eq!(Xnft::simplify_asset_id(simplified), simplified);
+
+        let Concrete(simplified) = simplified else {
+            panic!("the simplification must keep the asset ID concrete");
+        };
+
+        simplified
     }
 
-    fn check_out(_dest: &MultiLocation, _what: &MultiAsset, _context: &XcmContext) {}
+    #[test]
+    fn simplified_location_is_left_intact() {
+        let location = MultiLocation::new(1, X2(Parachain(1000), GeneralIndex(1)));
 
-    fn deposit_asset(
-        _what: &MultiAsset,
-        _who: &MultiLocation,
-        _context: Option<&XcmContext>,
-    ) -> XcmResult {
-        Err(XcmError::Unimplemented)
+        assert_eq!(simplify(location), location);
     }
 
-    fn withdraw_asset(
-        _what: &MultiAsset,
-        _who: &MultiLocation,
-        _maybe_context: Option<&XcmContext>,
-    ) -> Result<Assets, XcmError> {
-        Err(XcmError::Unimplemented)
+    #[test]
+    fn location_within_our_consensus_keeps_the_parents() {
+        assert_eq!(
+            simplify(MultiLocation::new(
+                2,
+                X3(
+                    GlobalConsensus(NetworkId::Polkadot),
+                    Parachain(1000),
+                    GeneralIndex(1),
+                ),
+            )),
+            MultiLocation::new(1, X2(Parachain(1000), GeneralIndex(1))),
+        );
+        assert_eq!(
+            simplify(MultiLocation::new(
+                2,
+                X1(GlobalConsensus(NetworkId::Polkadot))
+            )),
+            MultiLocation::parent(),
+        );
     }
 
-    fn internal_transfer_asset(
-        _asset: &MultiAsset,
-        _from: &MultiLocation,
-        _to: &MultiLocation,
-        _context: &XcmContext,
-    ) -> Result<Assets, XcmError> {
-        Err(XcmError::Unimplemented)
+    #[test]
+    fn location_within_another_consensus_is_left_intact() {
+        let location = MultiLocation::new(
+            2,
+            X3(
+                GlobalConsensus(NetworkId::Kusama),
+                Parachain(1000),
+                GeneralIndex(1),
+            ),
+        );
+
+        assert_eq!(simplify(location), location);
     }
 
-    fn transfer_asset(
-        asset: &MultiAsset,
-        from: &MultiLocation,
-        to: &MultiLocation,
-        context: &XcmContext,
-    ) -> Result<Assets, XcmError> {
-        match Self::internal_transfer_asset(asset, from, to, context) {
-            Err(XcmError::AssetNotFound | XcmError::Unimplemented) => {
-                let assets = Self::withdraw_asset(asset, from, Some(context))?;
-                // Not a very forgiving attitude; once we implement roll-backs then it'll be nicer.
-                Self::deposit_asset(asset, to, Some(context))?;
-                Ok(assets)
-            }
-            result => result,
-        }
+    #[test]
+    fn nested_global_consensus_is_left_intact() {
+        let location =
+            MultiLocation::new(1, X2(GlobalConsensus(NetworkId::Kusama), Parachain(1000)));
+
+        assert_eq!(simplify(location), location);
     }
-}
 
-construct_runtime! {
-    pub enum Runtime {
-        System: frame_system,
-        Balances: pallet_balances,
+    #[test]
+    fn location_above_the_universal_location_is_left_intact() {
+        let location = MultiLocation::new(3, X1(GlobalConsensus(NetworkId::Polkadot)));
 
-        ParachainInfo: parachain_info,
-        ParachainSystem: cumulus_pallet_parachain_system,
-        XcmpQueue: cumulus_pallet_xcmp_queue,
-        // DmpQueue: cumulus_pallet_dmp_queue,
-        // CumulusXcm: cumulus_pallet_xcm,
+        assert_eq!(simplify(location), location);
+    }
 
-        // Tokens: orml_tokens,
-        // XTokens: orml_xtokens,
+    #[test]
+    fn only_our_own_chain_drops_to_zero_parents() {
+        assert_eq!(
+            simplify(MultiLocation::new(
+                2,
+                X3(
+                    GlobalConsensus(NetworkId::Polkadot),
+                    Parachain(2000),
+                    PalletInstance(42),
+                ),
+            )),
+            MultiLocation::new(0, X1(PalletInstance(42))),
+        );
+        assert_eq!(
+            simplify(MultiLocation::new(1, X1(Parachain(2000)))),
+            MultiLocation::here(),
+        );
+        assert_eq!(
+            simplify(MultiLocation::new(1, X1(Parachain(2001)))),
+            MultiLocation::new(1, X1(Parachain(2001))),
+        );
+    }
 
-        PolkadotXcm: pallet_xcm,
-        // OrmlXcm: orml_xcm,
+    #[test]
+    fn fully_qualified_own_asset_is_rejected_as_local() {
+        new_test_ext().execute_with(|| {
+            assert_noop!(
+                register(Concrete(MultiLocation::new(
+                    2,
+                    X3(
+                        GlobalConsensus(NetworkId::Polkadot),
+                        Parachain(2000),
+                        PalletInstance(42),
+                    ),
+                ))),
+                Error::<Test>::AttemptToRegisterLocalAsset,
+            );
+        });
     }
+}
+
+/// The pallet composed with another transactor as a runtime would do.
+type ComposedTransactor = (Xnft, MockFallbackTransactor);
+
+fn composed_deposit(asset: &MultiAsset, to: u64) -> XcmResult {
+    ComposedTransactor::deposit_asset(
+        asset,
+        &account_location(to),
+        Some(&xcm_context(reserve_location())),
+    )
+}
+
+#[test]
+fn unrecognized_assets_fall_through_to_the_next_transactor() {
+    new_test_ext().execute_with(|| {
+        FallbackEnabled::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let foreign_nft = nft(foreign_asset_id(1), 7);
+        let unregistered_nft = nft(foreign_asset_id(2), 7);
+        let fungible: MultiAsset = (Concrete(MultiLocation::parent()), 100).into();
+
+        assert_ok!(composed_deposit(&fungible, ALICE));
+        assert_ok!(composed_deposit(&unregistered_nft, ALICE));
+        assert_ok!(composed_deposit(&foreign_nft, ALICE));
+
+        assert_eq!(
+            FallbackDeposits::get(),
+            vec![
+                (fungible, account_location(ALICE)),
+                (unregistered_nft, account_location(ALICE)),
+            ],
+        );
+        assert!(matches!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(_),
+        ));
+    });
+}
+
+#[test]
+fn recognized_nft_errors_abort_the_composed_transactor() {
+    new_test_ext().execute_with(|| {
+        FallbackEnabled::set(true);
+
+        register_foreign_collection(1);
+        assert_ok!(Xnft::pause(RuntimeOrigin::root()));
+
+        assert_eq!(
+            composed_deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::NoPermission),
+        );
+        assert!(FallbackDeposits::get().is_empty());
+    });
+}
+
+#[test]
+fn upgraded_v2_assets_resolve_the_registered_class() {
+    new_test_ext().execute_with(|| {
+        let v2_asset_id = v2::AssetId::Concrete(v2::MultiLocation::new(
+            1,
+            v2::Junctions::X2(v2::Junction::Parachain(1000), v2::Junction::GeneralIndex(1)),
+        ));
+
+        // The executor upgrades an incoming v2 asset this way before transacting it.
+        let asset_id: AssetId = v2_asset_id
+            .clone()
+            .try_into()
+            .expect("a v2 concrete asset ID is representable in v3");
+        assert_eq!(asset_id, foreign_asset_id(1));
+
+        let class_id = register_foreign_collection(1);
+
+        let v2_nft = v2::MultiAsset {
+            id: v2_asset_id,
+            fun: v2::Fungibility::NonFungible(v2::AssetInstance::Index(7)),
+        };
+        let upgraded_nft: MultiAsset = v2_nft.try_into().expect("a v2 NFT is representable in v3");
+
+        assert_ok!(deposit(&upgraded_nft, ALICE));
+        assert!(matches!(
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7)),
+            DerivativeStatus::Active(_),
+        ));
+        assert_eq!(
+            Xnft::simplify_versioned_asset_id_within(
+                VersionedAssetId::V3(asset_id),
+                &UniversalLocation::get(),
+            ),
+            Some(foreign_asset_id(1)),
+        );
+    });
+}
+
+#[test]
+fn disallowed_origin_rejects_only_the_managed_nfts_in_a_tuple() {
+    new_test_ext().execute_with(|| {
+        FallbackEnabled::set(true);
+        DisallowedOrigins::set(vec![reserve_location()]);
+
+        let class_id = register_foreign_collection(1);
+        let unregistered_nft = nft(foreign_asset_id(2), 7);
+        let fungible: MultiAsset = (Concrete(MultiLocation::parent()), 100).into();
+
+        assert_ok!(composed_deposit(&unregistered_nft, ALICE));
+        assert_ok!(composed_deposit(&fungible, ALICE));
+        assert_eq!(
+            composed_deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::NoPermission),
+        );
+
+        assert_eq!(
+            FallbackDeposits::get(),
+            vec![
+                (unregistered_nft, account_location(ALICE)),
+                (fungible, account_location(ALICE)),
+            ],
+        );
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+        assert_eq!(
+            FilteredOps::get(),
+            vec![XnftOp::Deposit {
+                reactivation: false
+            }],
+        );
+    });
+}
+
+/// Records a pending deposit of the foreign asset instance by failing its derivative mint.
+fn pending_deposit(class_id: u32, index: u128, to: u64) {
+    RecordFailedMints::set(true);
+    MintError::set(Some("the engine is busy"));
+
+    assert_ok!(deposit(&nft(foreign_asset_id(1), index), to));
+    assert_eq!(
+        Xnft::pending_deposit(class_id, AssetInstance::Index(index)),
+        Some(to),
+    );
+
+    MintError::set(None);
+}
+
+#[test]
+fn pending_deposit_is_completed_by_a_retry_unless_paused() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        pending_deposit(class_id, 7, ALICE);
+
+        assert_eq!(MockNftEngine::instance_count(class_id), 0);
+
+        assert_ok!(Xnft::pause(RuntimeOrigin::root()));
+        assert_noop!(
+            Xnft::retry_deposit(
+                RuntimeOrigin::signed(CHARLIE),
+                class_id,
+                AssetInstance::Index(7)
+            ),
+            Error::<Test>::Paused,
+        );
+        assert_ok!(Xnft::unpause(RuntimeOrigin::root()));
+
+        assert_ok!(Xnft::retry_deposit(
+            RuntimeOrigin::signed(CHARLIE),
+            class_id,
+            AssetInstance::Index(7),
+        ));
+
+        let DerivativeStatus::Active(instance_id) =
+            Xnft::foreign_instance_to_derivative_status(class_id, AssetInstance::Index(7))
+        else {
+            panic!("the derivative must be active after the retry");
+        };
+
+        assert_eq!(MockNftEngine::owner(class_id, instance_id), Some(ALICE));
+        assert_eq!(
+            Xnft::pending_deposit(class_id, AssetInstance::Index(7)),
+            None
+        );
+
+        Xnft::assert_storage_consistent();
+    });
+}
+
+#[test]
+fn permanent_mint_failures_are_not_recorded_as_pending() {
+    new_test_ext().execute_with(|| {
+        RecordFailedMints::set(true);
+
+        let class_id = register_foreign_collection(1);
+        let other_class_id = MockNftEngine::create(BOB);
+
+        // The derivative minted into another class can never satisfy the ownership check.
+        MintIntoClass::set(Some(other_class_id));
+
+        assert_eq!(
+            deposit(&nft(foreign_asset_id(1), 7), ALICE),
+            Err(XcmError::FailedToTransactAsset(
+                "the minted derivative doesn't belong to the derivative class",
+            )),
+        );
+        assert_eq!(
+            Xnft::pending_deposit(class_id, AssetInstance::Index(7)),
+            None
+        );
+        assert_eq!(MockNftEngine::instance_count(other_class_id), 0);
+        assert!(!xnft_events()
+            .iter()
+            .any(|event| matches!(event, Event::DepositPending { .. })));
+    });
+}
+
+#[test]
+fn dropped_pending_deposit_unblocks_the_deregistration() {
+    new_test_ext().execute_with(|| {
+        let class_id = register_foreign_collection(1);
+        pending_deposit(class_id, 7, ALICE);
+
+        let deregister = || {
+            Xnft::deregister_foreign_asset(
+                RuntimeOrigin::root(),
+                Box::new(VersionedAssetId::V3(foreign_asset_id(1))),
+            )
+        };
+
+        assert_noop!(deregister(), Error::<Test>::DerivativesStillExist);
+
+        assert_noop!(
+            Xnft::drop_pending_deposit(
+                RuntimeOrigin::signed(ALICE),
+                class_id,
+                AssetInstance::Index(7)
+            ),
+            DispatchError::BadOrigin,
+        );
+        assert_ok!(Xnft::drop_pending_deposit(
+            RuntimeOrigin::root(),
+            class_id,
+            AssetInstance::Index(7),
+        ));
+        System::assert_last_event(
+            Event::<Test>::PendingDepositDropped {
+                derivative_class_id: class_id,
+                asset_instance: AssetInstance::Index(7),
+                beneficiary: ALICE,
+            }
+            .into(),
+        );
+        assert_noop!(
+            Xnft::drop_pending_deposit(RuntimeOrigin::root(), class_id, AssetInstance::Index(7)),
+            Error::<Test>::NoPendingDeposit,
+        );
 
+        assert_ok!(deregister());
+    });
 }
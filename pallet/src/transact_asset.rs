@@ -1,26 +1,63 @@
 use cumulus_primitives_core::XcmContext;
-use frame_support::traits::Get;
-use sp_runtime::{traits::MaybeEquivalence, DispatchError};
-use sp_std::boxed::Box;
-use xcm::v3::{
-    prelude::{AssetId as XcmAssetId, AssetInstance as XcmAssetInstance, *},
-    Error as XcmError, Result as XcmResult,
+use frame_support::{
+    ensure,
+    storage::{with_transaction, TransactionOutcome},
+    traits::{Contains, Get},
+    BoundedVec,
+};
+use sp_runtime::{
+    traits::{Convert, MaybeEquivalence},
+    DispatchError, DispatchResult, Saturating,
+};
+use sp_std::{boxed::Box, vec::Vec};
+use xcm::{
+    v3::{prelude::*, Error as XcmError, Result as XcmResult},
+    VersionedAssetId,
 };
 use xcm_executor::{
     traits::{ConvertLocation, Error as XcmExecutorError, TransactAsset},
     Assets,
 };
 
-use xnft_primitives::traits::{DerivativeWithdrawal, DispatchErrorsConvert, NftTransactor};
+use xnft_primitives::{
+    conversion::strip_account_network,
+    traits::{DerivativeWithdrawal, DispatchErrorsConvert, NftEngine, NftTransactor, Royalty},
+};
 
 use crate::{
-    CategorizedClassInstance, ClassIdOf, ClassInstance, Config, DerivativeStatus,
-    DerivativeToForeignInstance, Event, ForeignAssetInstance, ForeignInstanceToDerivativeStatus,
-    InstanceIdOf, InstanceOf, LocationToAccountIdOf, NftEngineAccountIdOf, NftTransactorOf, Pallet,
+    CategorizedClassInstance, CategorizedClassInstanceOf, ClassIdOf, Config,
+    DerivativeCountPerClass, DerivativeStatus, DerivativeStatusOf, DerivativeToForeignInstance,
+    DormantDerivativeClasses, Error, Event, ForeignAssetInstance, ForeignAssetShardToLocalClass,
+    ForeignInstanceToDerivativeStatus, InstanceIdOf, InstanceOf, LocalDepositMode,
+    LocationToAccountIdOf, NftEngineAccountIdOf, NftTransactorOf, Pallet, PendingDeposits,
+    RetainedDerivativeIds, StashedCountPerClass, StoredAssetId as XcmAssetId,
+    StoredAssetInstance as XcmAssetInstance, XnftOp,
 };
 
 const LOG_TARGET: &str = "xcm::xnft::transactor";
 
+/// Returns the location of the chain containing the `location`,
+/// i.e., the `location` without its trailing junctions within the chain.
+pub(crate) fn chain_location(location: &MultiLocation) -> MultiLocation {
+    let mut chain_location = *location;
+
+    while let Some(junction) = chain_location.last() {
+        if matches!(junction, Parachain(_) | GlobalConsensus(_)) {
+            break;
+        }
+
+        chain_location.take_last();
+    }
+
+    chain_location
+}
+
+/// The pallet composes with other transactors (e.g., a fungible one) in a tuple as is.
+///
+/// Fungible assets and NFTs that are neither registered foreign assets nor local classes
+/// are rejected with the `AssetNotHandled` error (i.e., `AssetNotFound`)
+/// before any other check, so the XCM executor tries the next transactor in the tuple.
+/// Any other error aborts the tuple since the asset is recognized by the pallet.
 impl<T: Config<I>, I: 'static> TransactAsset for Pallet<T, I> {
     fn deposit_asset(
         xcm_asset: &MultiAsset,
@@ -34,16 +71,92 @@ impl<T: Config<I>, I: 'static> TransactAsset for Pallet<T, I> {
             "deposit_asset asset: {xcm_asset:?}, who: {who:?}, context: {context:?}",
         );
 
-        let Fungibility::NonFungible(xcm_asset_instance) = xcm_asset.fun else {
-            return Err(XcmExecutorError::AssetNotHandled.into());
-        };
+        let xcm_asset_instance = Self::non_fungible_instance(&xcm_asset)?;
 
-        let to = <LocationToAccountIdOf<T, I>>::convert_location(who)
-            .ok_or(XcmExecutorError::AccountIdConversionFailed)?;
+        Self::ensure_managed_asset_id(&xcm_asset.id)?;
 
         let class_instance = Self::class_instance(&xcm_asset.id, &xcm_asset_instance)?;
 
-        Self::deposit_class_instance(class_instance, &to)
+        let op = Self::deposit_op(&class_instance);
+
+        T::XcmContextFilter::filter(context, op)?;
+
+        Self::ensure_not_paused()?;
+
+        // Only a topic identifies a message uniquely,
+        // the message ID of a message without the topic is the hash of its content,
+        // so two legitimate identical messages would share it.
+        let processed_deposit_key = context
+            .filter(|_| T::DeduplicateByMessageId::get())
+            .and_then(|context| context.topic)
+            .map(|topic| (topic, xcm_asset.id, xcm_asset_instance));
+
+        if let Some(key) = &processed_deposit_key {
+            if Self::is_deposit_processed(key) {
+                log::debug!(
+                    target: LOG_TARGET,
+                    "deposit_asset: the deposit has already been processed: {key:?}",
+                );
+
+                return Ok(());
+            }
+        }
+
+        let (to, escrowed) = match Self::location_to_account(who) {
+            Ok(to) => (to, false),
+            Err(error) => (T::UnconvertibleDestinationEscrow::get().ok_or(error)?, true),
+        };
+
+        Self::ensure_destination_allowed(&class_instance, who)?;
+
+        if Self::is_teleport(&xcm_asset.id, context) {
+            Self::ensure_teleportable(&xcm_asset, true)?;
+        }
+
+        if T::ObserverMode::get() {
+            Self::observe_flow(op, class_instance, None, Some(to));
+            return Ok(());
+        }
+
+        let class_id = class_instance.class_id().clone();
+
+        let is_new_derivative = Self::is_new_derivative(&class_instance);
+
+        let royalty = is_new_derivative
+            .then(|| T::RoyaltySource::royalty(&xcm_asset, context))
+            .flatten();
+
+        let metadata = is_new_derivative
+            .then(|| Self::derivative_metadata(&xcm_asset, context))
+            .flatten();
+
+        let deposit = Self::transactional(|| {
+            Self::deposit_class_instance(
+                class_instance,
+                &to,
+                metadata.as_deref().map(Vec::as_slice),
+            )?;
+
+            if let Some(royalty) = &royalty {
+                Self::set_derivative_royalty(&xcm_asset.id, &xcm_asset_instance, royalty)?;
+            }
+
+            if escrowed {
+                Self::deposit_event(Event::DepositedToEscrow {
+                    original_location: *who,
+                });
+            }
+
+            if let Some(key) = processed_deposit_key {
+                Self::record_processed_deposit(key);
+            }
+
+            Ok(())
+        });
+
+        Self::with_fallback(&class_id, deposit, || {
+            T::FallbackTransactor::deposit_asset(&xcm_asset, who, context)
+        })
     }
 
     fn withdraw_asset(
@@ -58,16 +171,50 @@ impl<T: Config<I>, I: 'static> TransactAsset for Pallet<T, I> {
             "withdraw_asset asset: {xcm_asset:?}, who: {who:?}, context: {context:?}",
         );
 
-        let Fungibility::NonFungible(xcm_asset_instance) = xcm_asset.fun else {
-            return Err(XcmExecutorError::AssetNotHandled.into());
-        };
+        let xcm_asset_instance = Self::non_fungible_instance(&xcm_asset)?;
 
-        let from = <LocationToAccountIdOf<T, I>>::convert_location(who)
-            .ok_or(XcmExecutorError::AccountIdConversionFailed)?;
+        Self::ensure_managed_asset_id(&xcm_asset.id)?;
 
         let class_instance = Self::class_instance(&xcm_asset.id, &xcm_asset_instance)?;
 
-        Self::withdraw_class_instance(class_instance, &from).map(|()| xcm_asset.clone().into())
+        let op = XnftOp::Withdraw { stash: false };
+
+        T::XcmContextFilter::filter(context, op)?;
+
+        Self::ensure_not_paused()?;
+
+        if let Some(origin) = context.and_then(|context| context.origin) {
+            Self::ensure_destination_allowed(&class_instance, &chain_location(&origin))?;
+        }
+
+        let from = Self::location_to_account(who)?;
+
+        if T::ObserverMode::get() {
+            Self::observe_flow(op, class_instance, Some(from), None);
+            return Ok(xcm_asset.into());
+        }
+
+        let class_id = class_instance.class_id().clone();
+
+        let withdrawal = Self::transactional(|| {
+            if xcm_asset_instance == XcmAssetInstance::Undefined {
+                Self::withdraw_whole_class(&xcm_asset.id, &from)
+            } else {
+                Self::withdraw_class_instance(class_instance, &from)?;
+
+                if Self::is_teleport(&xcm_asset.id, context) {
+                    Self::ensure_teleportable(&xcm_asset, false)?;
+                }
+
+                Ok(())
+            }
+        });
+
+        Self::with_fallback(
+            &class_id,
+            withdrawal.map(|()| xcm_asset.clone().into()),
+            || T::FallbackTransactor::withdraw_asset(&xcm_asset, who, context),
+        )
     }
 
     fn transfer_asset(
@@ -83,67 +230,502 @@ impl<T: Config<I>, I: 'static> TransactAsset for Pallet<T, I> {
             "transfer_asset asset: {xcm_asset:?}, from: {from:?}, to: {to:?}, context: {context:?}",
         );
 
-        let Fungibility::NonFungible(xcm_asset_instance) = xcm_asset.fun else {
-            return Err(XcmExecutorError::AssetNotHandled.into());
-        };
-
-        let from = <LocationToAccountIdOf<T, I>>::convert_location(from)
-            .ok_or(XcmExecutorError::AccountIdConversionFailed)?;
+        let xcm_asset_instance = Self::non_fungible_instance(&xcm_asset)?;
 
-        let to = <LocationToAccountIdOf<T, I>>::convert_location(to)
-            .ok_or(XcmExecutorError::AccountIdConversionFailed)?;
+        Self::ensure_managed_asset_id(&xcm_asset.id)?;
 
         let class_instance = Self::class_instance(&xcm_asset.id, &xcm_asset_instance)?;
 
-        Self::transfer_class_instance(class_instance, &from, &to).map(|()| xcm_asset.clone().into())
+        let op = XnftOp::Transfer;
+
+        T::XcmContextFilter::filter(Some(context), op)?;
+
+        Self::ensure_not_paused()?;
+
+        let from_account = Self::location_to_account(from)?;
+
+        let to_account = Self::location_to_account(to)?;
+
+        Self::ensure_destination_allowed(&class_instance, to)?;
+
+        if T::ObserverMode::get() {
+            Self::observe_flow(op, class_instance, Some(from_account), Some(to_account));
+            return Ok(xcm_asset.into());
+        }
+
+        let class_id = class_instance.class_id().clone();
+
+        let transfer = Self::transactional(|| {
+            Self::transfer_class_instance(class_instance, &from_account, &to_account)
+        });
+
+        Self::with_fallback(
+            &class_id,
+            transfer.map(|()| xcm_asset.clone().into()),
+            || T::FallbackTransactor::transfer_asset(&xcm_asset, from, to, context),
+        )
     }
-}
 
-type CategorizedClassInstanceOf<T, I> =
-    CategorizedClassInstance<InstanceOf<T, I>, DerivativeStatusOf<T, I>>;
-type DerivativeIdStatusOf<T, I> = DerivativeStatus<InstanceIdOf<T, I>>;
-type DerivativeStatusOf<T, I> = ClassInstance<ClassIdOf<T, I>, DerivativeIdStatusOf<T, I>>;
+    fn can_check_in(
+        origin: &MultiLocation,
+        xcm_asset: &MultiAsset,
+        context: &XcmContext,
+    ) -> XcmResult {
+        log::trace!(
+            target: LOG_TARGET,
+            "can_check_in asset: {xcm_asset:?}, origin: {origin:?}, context: {context:?}",
+        );
+
+        Self::ensure_teleportable(xcm_asset, true)
+    }
+
+    fn check_in(origin: &MultiLocation, xcm_asset: &MultiAsset, context: &XcmContext) {
+        // The derivative status is updated by the subsequent `deposit_asset`.
+        log::trace!(
+            target: LOG_TARGET,
+            "check_in asset: {xcm_asset:?}, origin: {origin:?}, context: {context:?}",
+        );
+    }
+
+    fn can_check_out(
+        dest: &MultiLocation,
+        xcm_asset: &MultiAsset,
+        context: &XcmContext,
+    ) -> XcmResult {
+        log::trace!(
+            target: LOG_TARGET,
+            "can_check_out asset: {xcm_asset:?}, dest: {dest:?}, context: {context:?}",
+        );
+
+        Self::ensure_teleportable(xcm_asset, false)
+    }
+
+    fn check_out(dest: &MultiLocation, xcm_asset: &MultiAsset, context: &XcmContext) {
+        // The derivative status is updated by the preceding `withdraw_asset`.
+        log::trace!(
+            target: LOG_TARGET,
+            "check_out asset: {xcm_asset:?}, dest: {dest:?}, context: {context:?}",
+        );
+    }
+}
 
 // Common functions
 impl<T: Config<I>, I: 'static> Pallet<T, I> {
+    /// Converts the `error` into the XCM error.
+    ///
+    /// The conversion may decode the error against each of the configured pallet errors,
+    /// its weight is a part of the [`XnftAssetWeight`](crate::fees::XnftAssetWeight).
     fn dispatch_error_to_xcm_error(error: DispatchError) -> XcmError {
         T::DispatchErrorsConvert::convert(error)
     }
 
+    /// Returns the converter of an NFT engine error occurred during the `op` into the XCM error.
+    fn op_error(op: XnftOp) -> impl FnOnce(DispatchError) -> XcmError {
+        move |error| Self::dispatch_error_to_xcm_error(Self::op_failed(op)(error))
+    }
+
+    /// Returns the logger of an NFT engine error occurred during the `op`.
+    ///
+    /// The error itself is passed through, so the dispatchables can report it as is.
+    pub(crate) fn op_failed(op: XnftOp) -> impl FnOnce(DispatchError) -> DispatchError {
+        move |error| {
+            log::debug!(target: LOG_TARGET, "{op:?} failed: {error:?}");
+
+            error
+        }
+    }
+
+    /// Converts the `location` into the NFT engine's account.
+    ///
+    /// If the `AcceptForeignNetworkAccounts` is enabled, the account junction network
+    /// matching this chain's network is removed if the original location can't be converted.
+    fn location_to_account(
+        location: &MultiLocation,
+    ) -> Result<NftEngineAccountIdOf<T, I>, XcmError> {
+        let account = <LocationToAccountIdOf<T, I>>::convert_location(location)
+            .or_else(|| {
+                if !T::AcceptForeignNetworkAccounts::get() {
+                    return None;
+                }
+
+                let own_network = T::UniversalLocation::get().global_consensus().ok()?;
+                let location = strip_account_network(location, |network| *network == own_network)?;
+
+                <LocationToAccountIdOf<T, I>>::convert_location(&location)
+            })
+            .ok_or(XcmExecutorError::AccountIdConversionFailed)?;
+
+        Ok(account)
+    }
+
+    /// Ensures the derivative can be moved to the `destination`.
+    ///
+    /// The derivatives leave this chain by moving into the sovereign account of another chain
+    /// (e.g., via `DepositReserveAsset` or `TransferReserveAsset`).
+    /// Such a chain must be either the reserve of the derivative
+    /// or one of the `AllowedWithdrawalDestinations` of its class.
+    /// Otherwise, the operation is rejected with the [`XcmError::Barrier`].
+    ///
+    /// The XCM context of a withdrawal doesn't contain the onward destination.
+    /// A derivative withdrawn by a message from another chain moves under that chain's control,
+    /// so the withdrawal checks that chain as the destination instead.
+    /// The `InitiateReserveWithdraw` always sends the assets
+    /// to the reserve specified by the message, which is validated by the receiving chain.
+    fn ensure_destination_allowed(
+        class_instance: &CategorizedClassInstanceOf<T, I>,
+        destination: &MultiLocation,
+    ) -> XcmResult {
+        let CategorizedClassInstance::Derivative {
+            foreign_asset_instance,
+            derivative,
+        } = class_instance
+        else {
+            return Ok(());
+        };
+
+        let destination_chain = chain_location(destination);
+        if destination_chain != *destination || destination_chain == MultiLocation::here() {
+            return Ok(());
+        }
+
+        let Concrete(reserve_location) = foreign_asset_instance.asset_id else {
+            return Err(XcmError::Barrier);
+        };
+
+        let is_allowed = chain_location(&reserve_location) == destination_chain
+            || Self::allowed_withdrawal_destinations(&derivative.class_id)
+                .contains(&destination_chain);
+
+        if !is_allowed {
+            log::debug!(
+                target: LOG_TARGET,
+                "the derivative class {:?} can't be moved to {destination:?}",
+                derivative.class_id,
+            );
+
+            return Err(XcmError::Barrier);
+        }
+
+        Ok(())
+    }
+
+    /// Ensures the pallet isn't paused.
+    ///
+    /// It is checked only for the NFTs recognized by the pallet,
+    /// so the other transactors in the tuple are unaffected.
+    fn ensure_not_paused() -> XcmResult {
+        if Self::is_paused() {
+            return Err(XcmError::NoPermission);
+        }
+
+        Ok(())
+    }
+
+    /// Executes the `operation` in a storage transaction
+    /// so that its partial changes are discarded if it fails.
+    fn transactional<R>(operation: impl FnOnce() -> Result<R, XcmError>) -> Result<R, XcmError> {
+        Self::transactional_with(operation)
+    }
+
+    /// Executes the `operation` failing with a custom error in a storage transaction
+    /// so that its partial changes are discarded if it fails.
+    fn transactional_with<R, E: From<XcmError>>(
+        operation: impl FnOnce() -> Result<R, E>,
+    ) -> Result<R, E> {
+        with_transaction(|| {
+            let result = operation();
+
+            if result.is_ok() {
+                TransactionOutcome::Commit(Ok(result))
+            } else {
+                TransactionOutcome::Rollback(Ok(result))
+            }
+        })
+        .map_err(Self::dispatch_error_to_xcm_error)
+        .map_err(E::from)?
+    }
+
+    /// Returns the asset instance of the NFT.
+    ///
+    /// A fungible amount of an asset managed by the pallet is rejected with a descriptive error,
+    /// while other fungible assets are left to other transactors.
+    fn non_fungible_instance(xcm_asset: &MultiAsset) -> Result<XcmAssetInstance, XcmError> {
+        match xcm_asset.fun {
+            Fungibility::NonFungible(xcm_asset_instance) => Ok(xcm_asset_instance),
+            Fungibility::Fungible(_) if Self::is_managed_asset_id(&xcm_asset.id) => Err(
+                XcmError::FailedToTransactAsset("a fungible amount of a non-fungible asset"),
+            ),
+            Fungibility::Fungible(_) => Err(XcmExecutorError::AssetNotHandled.into()),
+        }
+    }
+
+    /// Ensures the asset ID belongs to a foreign asset or a local class.
+    ///
+    /// Otherwise, the `AssetNotHandled` error (i.e., `AssetNotFound`) is returned,
+    /// so the XCM executor tries the next transactor in the tuple.
+    /// The `xcm_asset_id` MUST be simplified before using this function.
+    fn ensure_managed_asset_id(xcm_asset_id: &XcmAssetId) -> XcmResult {
+        if Self::is_managed_asset_id(xcm_asset_id) {
+            Ok(())
+        } else {
+            Err(XcmExecutorError::AssetNotHandled.into())
+        }
+    }
+
+    /// Checks if the asset ID belongs to a foreign asset registered as a whole or sharded,
+    /// or to a local class.
+    /// The `xcm_asset_id` MUST be simplified before using this function.
+    fn is_managed_asset_id(xcm_asset_id: &XcmAssetId) -> bool {
+        Self::foreign_asset_to_local_class(xcm_asset_id).is_some()
+            || <ForeignAssetShardToLocalClass<T, I>>::contains_prefix(xcm_asset_id)
+            || Self::local_asset_to_class(xcm_asset_id).is_some()
+    }
+
+    /// Checks if the XCM message transacting the asset is a teleport
+    /// according to the `DynamicTrustModel`.
+    fn is_teleport(xcm_asset_id: &XcmAssetId, context: Option<&XcmContext>) -> bool {
+        context
+            .and_then(|context| context.origin)
+            .map_or(false, |origin| {
+                T::DynamicTrustModel::contains(&(origin, *xcm_asset_id))
+            })
+    }
+
+    /// Checks if the NFT can be teleported into this chain (`checking_in`) or out of it.
+    ///
+    /// The pallet's custody acts as the checking account:
+    /// * A local class instance must be in the custody.
+    ///   A checked-in one is released by the subsequent deposit,
+    ///   and a checked-out one is put there by the preceding withdrawal.
+    /// * A derivative must not be active.
+    ///   A checked-in one is minted or reactivated by the subsequent deposit,
+    ///   and a checked-out one is burned or stashed by the preceding withdrawal.
+    fn ensure_teleportable(xcm_asset: &MultiAsset, checking_in: bool) -> XcmResult {
+        let xcm_asset = Self::simplify_asset(xcm_asset.clone());
+
+        let Fungibility::NonFungible(xcm_asset_instance) = xcm_asset.fun else {
+            return Err(XcmExecutorError::AssetNotHandled.into());
+        };
+
+        let class_instance = Self::class_instance(&xcm_asset.id, &xcm_asset_instance)?;
+
+        Self::ensure_not_paused()?;
+
+        let not_teleportable = if checking_in {
+            XcmError::NotDepositable
+        } else {
+            XcmError::NotWithdrawable
+        };
+
+        match class_instance {
+            CategorizedClassInstance::Local(class_instance) => {
+                let owner = <NftTransactorOf<T, I>>::class_instance_owner(
+                    &class_instance.class_id,
+                    &class_instance.instance_id,
+                );
+
+                if owner != Some(Self::class_custody_account(&class_instance.class_id)) {
+                    return Err(not_teleportable);
+                }
+            }
+            CategorizedClassInstance::Derivative { derivative, .. } => {
+                match derivative.instance_id {
+                    DerivativeStatus::Active(_) => return Err(not_teleportable),
+                    DerivativeStatus::NotExists if checking_in => {
+                        <NftTransactorOf<T, I>>::can_mint(&derivative.class_id)
+                            .map_err(Self::op_error(XnftOp::Mint))?;
+                    }
+                    DerivativeStatus::NotExists | DerivativeStatus::Stashed(_) => {}
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Deposits the per-instance `event`
+    /// unless the events of its class are disabled (see the `EmitInstanceEvents`).
+    fn deposit_instance_event(event: Event<T, I>) {
+        let class_id = match &event {
+            Event::Deposited { class_instance, .. }
+            | Event::Withdrawn { class_instance, .. }
+            | Event::Transferred { class_instance, .. } => match class_instance {
+                CategorizedClassInstance::Local(class_instance) => Some(&class_instance.class_id),
+                CategorizedClassInstance::Derivative { derivative, .. } => {
+                    Some(&derivative.class_id)
+                }
+            },
+            Event::DerivativeBurned { derivative, .. }
+            | Event::DerivativeStashed { derivative, .. } => Some(&derivative.class_id),
+            _ => None,
+        };
+
+        if class_id.map_or(true, Self::class_events_enabled) {
+            Self::deposit_event(event);
+        }
+    }
+
+    /// Simulates the deposit of the foreign asset instance to the `to` account
+    /// and returns the resulting categorized class instance.
+    ///
+    /// The deposit is executed in a storage transaction that is always rolled back,
+    /// so the state isn't changed.
+    /// The derivative instance ID is the one the engine would allocate right now:
+    /// the existing ID for a stash reactivation and the freshly minted one otherwise.
+    /// The actual ID of a fresh mint can differ if other mints into the same class precede it.
+    pub fn simulate_deposit(
+        versioned_asset_id: VersionedAssetId,
+        asset_instance: XcmAssetInstance,
+        to: NftEngineAccountIdOf<T, I>,
+    ) -> Result<CategorizedClassInstanceOf<T, I>, XcmError> {
+        let asset_id = Self::simplify_versioned_asset_id_within(
+            versioned_asset_id,
+            &T::UniversalLocation::get(),
+        )
+        .ok_or(XcmExecutorError::AssetIdConversionFailed)?;
+
+        with_transaction(|| {
+            let simulation = (|| {
+                Self::ensure_not_paused()?;
+
+                let class_instance = Self::class_instance(&asset_id, &asset_instance)?;
+                Self::deposit_class_instance(class_instance, &to, None)?;
+
+                Self::class_instance(&asset_id, &asset_instance)
+            })();
+
+            TransactionOutcome::Rollback(Ok(simulation))
+        })
+        .map_err(Self::dispatch_error_to_xcm_error)?
+    }
+
+    /// Resolves the versioned `asset_id` and the `asset_instance` to the categorized class instance
+    /// using the same mapping rules as the pallet's `TransactAsset` implementation.
+    ///
+    /// The `asset_id` is converted into the stored XCM version and simplified
+    /// relative to the `UniversalLocation` first.
+    ///
+    /// NOTE: for a local class, the returned class instance ID may point to a non-existing NFT.
+    pub fn resolve_categorized_instance(
+        versioned_asset_id: &VersionedAssetId,
+        asset_instance: &XcmAssetInstance,
+    ) -> Result<CategorizedClassInstanceOf<T, I>, XcmError> {
+        let asset_id = Self::simplify_versioned_asset_id_within(
+            versioned_asset_id.clone(),
+            &T::UniversalLocation::get(),
+        )
+        .ok_or(XcmExecutorError::AssetIdConversionFailed)?;
+
+        Self::class_instance(&asset_id, asset_instance)
+    }
+
+    /// Delegates the recognized NFT to the fallback transactor if the pallet failed to process it
+    /// because the `class_id` class isn't operational.
+    ///
+    /// The pallet's own error is returned if the class is operational
+    /// or the fallback transactor fails too.
+    fn with_fallback<R>(
+        class_id: &ClassIdOf<T, I>,
+        result: Result<R, XcmError>,
+        fallback: impl FnOnce() -> Result<R, XcmError>,
+    ) -> Result<R, XcmError> {
+        result.or_else(|error| {
+            if T::NftEngine::is_class_operational(class_id) {
+                return Err(error);
+            }
+
+            log::debug!(
+                target: LOG_TARGET,
+                "delegating to the fallback transactor, the pallet's error: {error:?}",
+            );
+
+            fallback().map_err(|_| error)
+        })
+    }
+
+    /// Records the categorized class instance flow without performing it.
+    fn observe_flow(
+        op: XnftOp,
+        class_instance: CategorizedClassInstanceOf<T, I>,
+        from: Option<NftEngineAccountIdOf<T, I>>,
+        to: Option<NftEngineAccountIdOf<T, I>>,
+    ) {
+        Self::deposit_event(Event::ObservedFlow {
+            op,
+            class_instance,
+            from,
+            to,
+        });
+    }
+
     /// Converts the XCM `asset_instance` to the corresponding local class instance.
     ///
+    /// An asset ID that is neither a registered foreign asset nor a local class
+    /// yields the `AssetNotHandled` error (i.e., `AssetNotFound`),
+    /// so the XCM executor tries the next transactor in the tuple.
+    ///
     /// NOTE: for a local class, the returned class instance ID may point to a non-existing NFT.
-    fn class_instance(
+    pub(crate) fn class_instance(
         xcm_asset_id: &XcmAssetId,
         xcm_asset_instance: &XcmAssetInstance,
     ) -> Result<CategorizedClassInstanceOf<T, I>, XcmError> {
-        let (class_id, is_derivative) = Self::foreign_asset_to_local_class(xcm_asset_id)
-            .map(|class_id| (class_id, true))
-            .or_else(|| Self::local_asset_to_class(xcm_asset_id).map(|class_id| (class_id, false)))
-            .ok_or(XcmExecutorError::AssetIdConversionFailed)?;
+        let (class_id, is_derivative) = Self::lookup_class(xcm_asset_id, xcm_asset_instance)
+            .ok_or(XcmExecutorError::AssetNotHandled)?;
 
         let class_instance = if is_derivative {
             let derivative_status =
                 Self::foreign_instance_to_derivative_status(&class_id, xcm_asset_instance);
 
-            CategorizedClassInstance::Derivative {
-                foreign_asset_instance: Box::new((*xcm_asset_id, *xcm_asset_instance).into()),
-                derivative: (class_id, derivative_status).into(),
-            }
+            CategorizedClassInstance::derivative(
+                *xcm_asset_id,
+                *xcm_asset_instance,
+                class_id,
+                derivative_status,
+            )
         } else {
-            CategorizedClassInstance::Local(ClassInstance {
+            CategorizedClassInstance::local(
                 class_id,
-                instance_id: T::AssetInstanceConvert::convert(xcm_asset_instance)
+                T::AssetInstanceConvert::convert(xcm_asset_instance)
                     .ok_or(XcmExecutorError::InstanceConversionFailed)?,
-            })
+            )
         };
 
         Ok(class_instance)
     }
 
-    fn deposit_class_instance(
+    /// Returns the class ID of the XCM asset instance and whether the class is a derivative one.
+    pub(crate) fn lookup_class(
+        xcm_asset_id: &XcmAssetId,
+        xcm_asset_instance: &XcmAssetInstance,
+    ) -> Option<(ClassIdOf<T, I>, bool)> {
+        Self::foreign_asset_to_local_class(xcm_asset_id)
+            .or_else(|| Self::foreign_asset_shard_class(xcm_asset_id, xcm_asset_instance))
+            .map(|stored_class_id| (Self::engine_class_id(stored_class_id), true))
+            .or_else(|| Self::local_asset_to_class(xcm_asset_id).map(|class_id| (class_id, false)))
+    }
+
+    /// Returns the stored derivative class ID of the foreign asset shard
+    /// to which the `xcm_asset_instance` is routed.
+    fn foreign_asset_shard_class(
+        xcm_asset_id: &XcmAssetId,
+        xcm_asset_instance: &XcmAssetInstance,
+    ) -> Option<ClassIdOf<T, I>> {
+        if !T::InstanceSharding::get() {
+            return None;
+        }
+
+        let shard = T::InstanceRangeRouter::convert(*xcm_asset_instance)?;
+
+        Self::foreign_asset_shard_to_local_class(xcm_asset_id, shard)
+    }
+
+    /// Deposits the `class_instance` to the `to` account.
+    ///
+    /// The `metadata`, if given, is set on a freshly minted derivative.
+    pub(crate) fn deposit_class_instance(
         class_instance: CategorizedClassInstanceOf<T, I>,
         to: &NftEngineAccountIdOf<T, I>,
+        metadata: Option<&[u8]>,
     ) -> XcmResult {
         match class_instance {
             CategorizedClassInstance::Local(local_class_instance) => {
@@ -153,19 +735,29 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
             CategorizedClassInstance::Derivative {
                 foreign_asset_instance,
                 derivative: derivative_status,
-            } => {
-                Self::deposit_foreign_asset_instance(foreign_asset_instance, derivative_status, to)
-            }
+            } => Self::deposit_foreign_asset_instance(
+                foreign_asset_instance,
+                derivative_status,
+                to,
+                metadata,
+            ),
         }
     }
 
-    fn withdraw_class_instance(
+    pub(crate) fn withdraw_class_instance(
         class_instance: CategorizedClassInstanceOf<T, I>,
         from: &NftEngineAccountIdOf<T, I>,
     ) -> XcmResult {
         match class_instance {
             CategorizedClassInstance::Local(local_class_instance) => {
-                Self::withdraw_local_class_instance(local_class_instance, from)
+                let from = Self::withdrawal_source(
+                    &local_class_instance.class_id,
+                    &local_class_instance.instance_id,
+                    from,
+                );
+
+                Self::withdraw_local_class_instance(local_class_instance, &from)
+                    .map_err(Self::dispatch_error_to_xcm_error)
             }
 
             CategorizedClassInstance::Derivative {
@@ -174,23 +766,145 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
             } => {
                 let derivative_instance_id = derivative_status.instance_id.ensure_active()?;
 
+                let from = Self::withdrawal_source(
+                    &derivative_status.class_id,
+                    &derivative_instance_id,
+                    from,
+                );
+
                 Self::withdraw_foreign_asset_instance(
                     foreign_asset_instance,
                     (derivative_status.class_id, derivative_instance_id).into(),
-                    from,
+                    &from,
                 )
+                .map_err(Self::dispatch_error_to_xcm_error)
+            }
+        }
+    }
+
+    /// Checks if depositing the class instance mints a new derivative.
+    fn is_new_derivative(class_instance: &CategorizedClassInstanceOf<T, I>) -> bool {
+        let CategorizedClassInstance::Derivative { derivative, .. } = class_instance else {
+            return false;
+        };
+
+        match &derivative.instance_id {
+            DerivativeStatus::NotExists => true,
+            DerivativeStatus::Stashed(instance_id) => {
+                Self::is_stash_lost(&derivative.class_id, instance_id)
+            }
+            DerivativeStatus::Active(_) => false,
+        }
+    }
+
+    /// Returns the deposit operation of the `class_instance`.
+    ///
+    /// A stashed derivative is reactivated unless it was lost,
+    /// in which case a new one is minted.
+    fn deposit_op(class_instance: &CategorizedClassInstanceOf<T, I>) -> XnftOp {
+        let reactivation = match class_instance {
+            CategorizedClassInstance::Derivative { derivative, .. } => {
+                matches!(&derivative.instance_id, DerivativeStatus::Stashed(_))
+                    && !Self::is_new_derivative(class_instance)
+            }
+            CategorizedClassInstance::Local(_) => false,
+        };
+
+        XnftOp::Deposit { reactivation }
+    }
+
+    /// Checks if the stashed derivative no longer exists in the NFT engine
+    /// (e.g., it was burned out-of-band).
+    fn is_stash_lost(class_id: &ClassIdOf<T, I>, stashed_instance_id: &InstanceIdOf<T, I>) -> bool {
+        <NftTransactorOf<T, I>>::class_instance_owner(class_id, stashed_instance_id).is_none()
+    }
+
+    /// Returns the metadata the `MetadataSource` extracted from the deposited `asset`.
+    ///
+    /// A metadata longer than the `MetadataLimit` is ignored.
+    fn derivative_metadata(
+        asset: &MultiAsset,
+        context: Option<&XcmContext>,
+    ) -> Option<BoundedVec<u8, T::MetadataLimit>> {
+        let metadata = T::MetadataSource::metadata(asset, context)?;
+
+        BoundedVec::try_from(metadata)
+            .map_err(|metadata| {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "deposit_asset: the derivative metadata of {asset:?} exceeds the limit: {} bytes, ignoring it",
+                    metadata.len(),
+                );
+            })
+            .ok()
+    }
+
+    /// Sets the `royalty` on the derivative of the deposited foreign asset instance.
+    ///
+    /// Nothing is done if no derivative is active,
+    /// e.g., if the failed mint was recorded as a pending deposit.
+    fn set_derivative_royalty(
+        xcm_asset_id: &XcmAssetId,
+        xcm_asset_instance: &XcmAssetInstance,
+        royalty: &Royalty<NftEngineAccountIdOf<T, I>>,
+    ) -> XcmResult {
+        let CategorizedClassInstance::Derivative {
+            derivative: derivative_status,
+            ..
+        } = Self::class_instance(xcm_asset_id, xcm_asset_instance)?
+        else {
+            return Ok(());
+        };
+
+        let DerivativeStatus::Active(instance_id) = derivative_status.instance_id else {
+            return Ok(());
+        };
+
+        <NftTransactorOf<T, I>>::set_derivative_royalty(
+            &derivative_status.class_id,
+            &instance_id,
+            &royalty.creator,
+            royalty.basis_points,
+        )
+        .map_err(Self::op_error(XnftOp::Mint))
+    }
+
+    /// Returns the account from which the `who` account withdraws the class instance.
+    ///
+    /// It is the class instance owner if the `who` is an operator approved by the owner
+    /// and the `AllowApprovedOperators` is enabled. Otherwise, it is the `who` itself.
+    fn withdrawal_source(
+        class_id: &ClassIdOf<T, I>,
+        instance_id: &InstanceIdOf<T, I>,
+        who: &NftEngineAccountIdOf<T, I>,
+    ) -> NftEngineAccountIdOf<T, I> {
+        if !T::AllowApprovedOperators::get() {
+            return who.clone();
+        }
+
+        match <NftTransactorOf<T, I>>::class_instance_owner(class_id, instance_id) {
+            Some(owner)
+                if &owner != who
+                    && <NftTransactorOf<T, I>>::is_approved_or_owner(
+                        class_id,
+                        instance_id,
+                        who,
+                    ) =>
+            {
+                owner
             }
+            _ => who.clone(),
         }
     }
 
-    fn transfer_class_instance(
+    pub(crate) fn transfer_class_instance(
         class_instance: CategorizedClassInstanceOf<T, I>,
         from: &NftEngineAccountIdOf<T, I>,
         to: &NftEngineAccountIdOf<T, I>,
     ) -> XcmResult {
         match class_instance {
             CategorizedClassInstance::Local(class_instance) => {
-                <NftTransactorOf<T, I>>::transfer_class_instance(
+                T::NftEngine::can_transfer(
                     &class_instance.class_id,
                     &class_instance.instance_id,
                     from,
@@ -198,10 +912,21 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
                 )
                 .map_err(Self::dispatch_error_to_xcm_error)?;
 
-                Self::deposit_event(Event::Transferred {
+                <NftTransactorOf<T, I>>::transfer_class_instance(
+                    &class_instance.class_id,
+                    &class_instance.instance_id,
+                    from,
+                    to,
+                )
+                .map_err(Self::op_error(XnftOp::Transfer))?;
+
+                let xcm_asset_id = Self::local_class_asset_id(&class_instance.class_id);
+
+                Self::deposit_instance_event(Event::Transferred {
                     class_instance: CategorizedClassInstance::Local(class_instance),
                     from: from.clone(),
                     to: to.clone(),
+                    xcm_asset_id,
                 })
             }
             CategorizedClassInstance::Derivative {
@@ -211,20 +936,28 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
                 let class_id = derivative_status.class_id;
                 let instance_id = derivative_status.instance_id.ensure_active()?;
 
-                <NftTransactorOf<T, I>>::transfer_class_instance(&class_id, &instance_id, from, to)
+                T::NftEngine::can_transfer(&class_id, &instance_id, from, to)
                     .map_err(Self::dispatch_error_to_xcm_error)?;
 
-                Self::deposit_event(Event::Transferred {
+                <NftTransactorOf<T, I>>::transfer_class_instance(&class_id, &instance_id, from, to)
+                    .map_err(Self::op_error(XnftOp::Transfer))?;
+
+                let xcm_asset_id = Some(Box::new(foreign_asset_instance.asset_id));
+
+                Self::deposit_instance_event(Event::Transferred {
                     class_instance: CategorizedClassInstance::Derivative {
                         foreign_asset_instance,
                         derivative: (class_id, instance_id).into(),
                     },
                     from: from.clone(),
                     to: to.clone(),
+                    xcm_asset_id,
                 })
             }
         }
 
+        Self::note_flow(|flow| flow.transfers.saturating_inc());
+
         Ok(())
     }
 }
@@ -244,46 +977,89 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
 
         let class_id = T::LocalAssetIdConvert::convert(&asset_location.interior)?;
 
-        Self::local_class_to_foreign_asset(&class_id)
-            .is_none()
-            .then_some(class_id)
+        (!Self::is_derivative_class(&class_id)).then_some(class_id)
+    }
+
+    /// Returns the XCM asset ID of the local class for the transact events.
+    ///
+    /// Returns `None` if the `LocalAssetIdConvert` can't represent the class.
+    fn local_class_asset_id(class_id: &ClassIdOf<T, I>) -> Option<Box<XcmAssetId>> {
+        T::LocalAssetIdConvert::convert_back(class_id).map(|interior| {
+            Box::new(Concrete(MultiLocation {
+                parents: 0,
+                interior,
+            }))
+        })
     }
 
     fn deposit_local_class_instance(
         local_class_instance: InstanceOf<T, I>,
         to: &NftEngineAccountIdOf<T, I>,
     ) -> XcmResult {
-        <NftTransactorOf<T, I>>::transfer_class_instance(
-            &local_class_instance.class_id,
-            &local_class_instance.instance_id,
-            &T::PalletAccountId::get(),
-            to,
-        )
-        .map_err(Self::dispatch_error_to_xcm_error)?;
+        let is_missing = T::LocalDepositMode::get() == LocalDepositMode::MintIfMissing
+            && <NftTransactorOf<T, I>>::class_instance_owner(
+                &local_class_instance.class_id,
+                &local_class_instance.instance_id,
+            )
+            .is_none();
+
+        if is_missing {
+            T::NftEngine::mint_local(
+                &local_class_instance.class_id,
+                &local_class_instance.instance_id,
+                to,
+            )
+            .map_err(Self::op_error(XnftOp::Mint))?;
 
-        Self::deposit_event(Event::Deposited {
+            Self::note_flow(|flow| flow.mints.saturating_inc());
+        } else {
+            <NftTransactorOf<T, I>>::transfer_class_instance(
+                &local_class_instance.class_id,
+                &local_class_instance.instance_id,
+                &Self::class_custody_account(&local_class_instance.class_id),
+                to,
+            )
+            .map_err(Self::op_error(XnftOp::Deposit {
+                reactivation: false,
+            }))?;
+        }
+
+        Self::note_flow(|flow| flow.deposits.saturating_inc());
+
+        let xcm_asset_id = Self::local_class_asset_id(&local_class_instance.class_id);
+
+        Self::deposit_instance_event(Event::Deposited {
             class_instance: CategorizedClassInstance::Local(local_class_instance),
             to: to.clone(),
+            xcm_asset_id,
         });
 
         Ok(())
     }
 
-    fn withdraw_local_class_instance(
+    /// Withdraws the local class instance into the custody.
+    ///
+    /// The NFT engine error is returned as is.
+    pub(crate) fn withdraw_local_class_instance(
         local_class_instance: InstanceOf<T, I>,
         from: &NftEngineAccountIdOf<T, I>,
-    ) -> XcmResult {
+    ) -> DispatchResult {
         <NftTransactorOf<T, I>>::transfer_class_instance(
             &local_class_instance.class_id,
             &local_class_instance.instance_id,
             from,
-            &T::PalletAccountId::get(),
+            &Self::class_custody_account(&local_class_instance.class_id),
         )
-        .map_err(Self::dispatch_error_to_xcm_error)?;
+        .map_err(Self::op_failed(XnftOp::Withdraw { stash: false }))?;
+
+        Self::note_flow(|flow| flow.withdrawals.saturating_inc());
 
-        Self::deposit_event(Event::Withdrawn {
+        let xcm_asset_id = Self::local_class_asset_id(&local_class_instance.class_id);
+
+        Self::deposit_instance_event(Event::Withdrawn {
             class_instance: CategorizedClassInstance::Local(local_class_instance),
             from: from.clone(),
+            xcm_asset_id,
         });
 
         Ok(())
@@ -298,60 +1074,427 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
     ///
     /// If a new derivative is minted, it establishes the mapping
     /// between the foreign asset instance and the derivative.
+    ///
+    /// A stashed derivative the NFT engine no longer has (e.g., burned out-of-band) is forgotten,
+    /// and a new one is minted in its place under the same rules as any other new derivative.
     fn deposit_foreign_asset_instance(
         foreign_asset_instance: Box<ForeignAssetInstance>,
         derivative_status: DerivativeStatusOf<T, I>,
         to: &NftEngineAccountIdOf<T, I>,
+        metadata: Option<&[u8]>,
     ) -> XcmResult {
         let derivative_class_id = derivative_status.class_id;
-        let derivative_id_status = derivative_status.instance_id;
 
-        let deposited_instance_id = match derivative_id_status {
-            DerivativeStatus::NotExists => {
-                let instance_id =
-                    <NftTransactorOf<T, I>>::mint_derivative(&derivative_class_id, to)
-                        .map_err(Self::dispatch_error_to_xcm_error)?;
+        Self::ensure_class_reserve(&derivative_class_id, &foreign_asset_instance.asset_id)?;
 
-                <DerivativeToForeignInstance<T, I>>::insert(
-                    &derivative_class_id,
-                    &instance_id,
-                    foreign_asset_instance.asset_instance,
+        if Self::deprecated_derivative_class(&derivative_class_id).is_some() {
+            return Err(XcmError::NoPermission);
+        }
+
+        let mut lost_stashed_instance_id = None;
+
+        let derivative_id_status = match derivative_status.instance_id {
+            DerivativeStatus::Stashed(stashed_instance_id)
+                if Self::is_stash_lost(&derivative_class_id, &stashed_instance_id) =>
+            {
+                log::warn!(
+                    target: LOG_TARGET,
+                    "deposit_asset: the stashed derivative {stashed_instance_id:?} of the class {derivative_class_id:?} no longer exists, minting a new one",
                 );
 
-                <ForeignInstanceToDerivativeStatus<T, I>>::insert(
+                Self::remove_reverse_mapping(&derivative_class_id, &stashed_instance_id);
+                <ForeignInstanceToDerivativeStatus<T, I>>::remove(
                     &derivative_class_id,
                     foreign_asset_instance.asset_instance,
-                    DerivativeStatus::Active(instance_id.clone()),
                 );
+                <DerivativeCountPerClass<T, I>>::mutate(&derivative_class_id, |count| {
+                    *count = count.saturating_sub(1)
+                });
+                <StashedCountPerClass<T, I>>::mutate(&derivative_class_id, |count| {
+                    *count = count.saturating_sub(1)
+                });
 
-                instance_id
+                lost_stashed_instance_id = Some(stashed_instance_id);
+
+                DerivativeStatus::NotExists
             }
-            DerivativeStatus::Stashed(stashed_instance_id) => {
-                <NftTransactorOf<T, I>>::transfer_class_instance(
-                    &derivative_class_id,
-                    &stashed_instance_id,
-                    &T::PalletAccountId::get(),
-                    to,
+            status => status,
+        };
+
+        if matches!(derivative_id_status, DerivativeStatus::NotExists) {
+            if T::IndexOnlyForeignInstances::get()
+                && !matches!(
+                    foreign_asset_instance.asset_instance,
+                    XcmAssetInstance::Index(_)
                 )
-                .map_err(Self::dispatch_error_to_xcm_error)?;
+            {
+                return Err(XcmExecutorError::InstanceConversionFailed.into());
+            }
 
-                <ForeignInstanceToDerivativeStatus<T, I>>::insert(
+            if let Some(max_derivatives) = T::MaxDerivativesPerClass::get() {
+                if Self::derivative_count(&derivative_class_id) >= max_derivatives {
+                    return Err(XcmError::NotDepositable);
+                }
+            }
+
+            <NftTransactorOf<T, I>>::can_mint(&derivative_class_id)
+                .map_err(Self::op_error(XnftOp::Mint))?;
+        }
+
+        if <DormantDerivativeClasses<T, I>>::contains_key(&derivative_class_id) {
+            <DormantDerivativeClasses<T, I>>::remove(&derivative_class_id);
+        }
+
+        let deposited_instance_id = match derivative_id_status {
+            DerivativeStatus::NotExists => {
+                // The status is re-read right before the mint since the caller's one
+                // could be read before another deposit of the same foreign instance.
+                // Under the executor's transactional processing it can't change in between,
+                // but the check keeps a non-transactional executor from double-minting.
+                // It is done outside the mint so that it is never recorded as a pending deposit.
+                let current_status = Self::foreign_instance_to_derivative_status(
                     &derivative_class_id,
                     foreign_asset_instance.asset_instance,
-                    DerivativeStatus::Active(stashed_instance_id.clone()),
                 );
 
+                if current_status != DerivativeStatus::NotExists {
+                    log::error!(
+                        target: LOG_TARGET,
+                        "deposit_asset: the derivative of {foreign_asset_instance:?} appeared before the mint: {current_status:?}",
+                    );
+
+                    return Err(XcmError::NotDepositable);
+                }
+
+                let mint = Self::transactional_with(|| {
+                    Self::mint_derivative(
+                        &derivative_class_id,
+                        &foreign_asset_instance,
+                        to,
+                        metadata,
+                    )
+                });
+
+                // Only a failure the engine may recover from is worth retrying.
+                let instance_id = match mint {
+                    Ok(instance_id) => instance_id,
+                    Err(MintFailure::Transient(error)) if T::RecordFailedMints::get() => {
+                        log::warn!(
+                            target: LOG_TARGET,
+                            "deposit_asset: failed to mint a derivative of {foreign_asset_instance:?}, recording the pending deposit: {error:?}",
+                        );
+
+                        <PendingDeposits<T, I>>::insert(
+                            &derivative_class_id,
+                            foreign_asset_instance.asset_instance,
+                            to,
+                        );
+
+                        Self::deposit_event(Event::DepositPending {
+                            foreign_asset_instance,
+                            derivative_class_id,
+                            beneficiary: to.clone(),
+                        });
+
+                        return Ok(());
+                    }
+                    Err(MintFailure::Transient(error) | MintFailure::Permanent(error)) => {
+                        return Err(error)
+                    }
+                };
+
+                <DerivativeCountPerClass<T, I>>::mutate(&derivative_class_id, |count| {
+                    *count = count.saturating_add(1)
+                });
+
+                if let Some(lost_instance_id) = lost_stashed_instance_id {
+                    Self::deposit_event(Event::StashLostReminted {
+                        foreign_asset_instance: foreign_asset_instance.clone(),
+                        lost_derivative: (derivative_class_id.clone(), lost_instance_id).into(),
+                        derivative: (derivative_class_id.clone(), instance_id.clone()).into(),
+                    });
+                }
+
+                instance_id
+            }
+            DerivativeStatus::Stashed(stashed_instance_id) => {
+                // The custody release and the reactivation are applied together or not at all.
+                Self::transactional(|| {
+                    <NftTransactorOf<T, I>>::transfer_class_instance(
+                        &derivative_class_id,
+                        &stashed_instance_id,
+                        &Self::class_custody_account(&derivative_class_id),
+                        to,
+                    )
+                    .map_err(Self::op_error(XnftOp::Deposit { reactivation: true }))?;
+
+                    <ForeignInstanceToDerivativeStatus<T, I>>::insert(
+                        &derivative_class_id,
+                        foreign_asset_instance.asset_instance,
+                        DerivativeStatus::Active(stashed_instance_id.clone()),
+                    );
+                    <StashedCountPerClass<T, I>>::mutate(&derivative_class_id, |count| {
+                        *count = count.saturating_sub(1)
+                    });
+
+                    Ok(())
+                })?;
+
                 stashed_instance_id
             }
             DerivativeStatus::Active(_) => return Err(XcmError::NotDepositable),
         };
 
-        Self::deposit_event(Event::Deposited {
+        Self::note_flow(|flow| flow.deposits.saturating_inc());
+
+        let xcm_asset_id = Some(Box::new(foreign_asset_instance.asset_id));
+
+        Self::deposit_instance_event(Event::Deposited {
             class_instance: CategorizedClassInstance::Derivative {
                 foreign_asset_instance,
                 derivative: (derivative_class_id, deposited_instance_id).into(),
             },
             to: to.clone(),
+            xcm_asset_id,
+        });
+
+        Ok(())
+    }
+
+    /// Completes the pending deposit of the `asset_instance` to the `beneficiary`.
+    ///
+    /// Fails if the derivative still can't be minted.
+    pub(crate) fn retry_pending_deposit(
+        derivative_class_id: ClassIdOf<T, I>,
+        asset_instance: XcmAssetInstance,
+        beneficiary: NftEngineAccountIdOf<T, I>,
+    ) -> DispatchResult {
+        let asset_id =
+            Self::local_class_to_foreign_asset(Self::stored_class_id(derivative_class_id.clone()))
+                .ok_or(<Error<T, I>>::ForeignAssetNotRegistered)?;
+
+        let derivative_status =
+            Self::foreign_instance_to_derivative_status(&derivative_class_id, asset_instance);
+
+        Self::deposit_foreign_asset_instance(
+            Box::new((asset_id, asset_instance).into()),
+            (derivative_class_id.clone(), derivative_status).into(),
+            &beneficiary,
+            None,
+        )
+        .map_err(|_| <Error<T, I>>::DepositRetryFailed)?;
+
+        // A failed mint is recorded again instead of failing the deposit.
+        ensure!(
+            !<PendingDeposits<T, I>>::contains_key(&derivative_class_id, asset_instance),
+            <Error<T, I>>::DepositRetryFailed,
+        );
+
+        Ok(())
+    }
+
+    /// Ensures the derivative class is registered for the given foreign asset.
+    ///
+    /// This prevents a foreign asset from injecting instances into a derivative class
+    /// registered for another foreign asset (e.g., via aliasing).
+    fn ensure_class_reserve(
+        derivative_class_id: &ClassIdOf<T, I>,
+        foreign_asset_id: &XcmAssetId,
+    ) -> XcmResult {
+        let registered_asset_id =
+            Self::local_class_to_foreign_asset(Self::stored_class_id(derivative_class_id.clone()));
+
+        if registered_asset_id.as_ref() != Some(foreign_asset_id) {
+            return Err(XcmExecutorError::AssetIdConversionFailed.into());
+        }
+
+        Ok(())
+    }
+
+    /// Mints a new derivative of the foreign asset instance with the given `metadata`
+    /// and establishes the mapping between them.
+    fn mint_derivative(
+        derivative_class_id: &ClassIdOf<T, I>,
+        foreign_asset_instance: &ForeignAssetInstance,
+        to: &NftEngineAccountIdOf<T, I>,
+        metadata: Option<&[u8]>,
+    ) -> Result<InstanceIdOf<T, I>, MintFailure> {
+        let derive_reverse_mapping = T::DeriveReverseMapping::get();
+
+        let expected_instance_id = if derive_reverse_mapping {
+            T::DerivativeInstanceConvert::convert(&foreign_asset_instance.asset_instance)
+        } else {
+            None
+        };
+
+        let retained_instance_id = if T::PreserveIdAcrossBurn::get() {
+            <RetainedDerivativeIds<T, I>>::take(
+                derivative_class_id,
+                foreign_asset_instance.asset_instance,
+            )
+        } else {
+            None
+        };
+
+        // Outside of the derived reverse mapping mode, the ID is only a hint the engine may ignore.
+        let instance_id_hint = if derive_reverse_mapping {
+            expected_instance_id.clone()
+        } else {
+            retained_instance_id.or_else(|| {
+                T::AssetInstanceConvert::convert(&foreign_asset_instance.asset_instance)
+            })
+        };
+
+        let instance_id = <NftTransactorOf<T, I>>::mint_derivative_full(
+            derivative_class_id,
+            to,
+            instance_id_hint.as_ref(),
+            metadata,
+        )
+        .map_err(Self::op_error(XnftOp::Mint))
+        .map_err(MintFailure::Transient)?;
+
+        let minted_instance_owner =
+            <NftTransactorOf<T, I>>::class_instance_owner(derivative_class_id, &instance_id);
+
+        if minted_instance_owner.as_ref() != Some(to) {
+            log::error!(
+                target: LOG_TARGET,
+                "deposit_asset: the minted derivative {instance_id:?} isn't owned by {to:?} within the class {derivative_class_id:?}",
+            );
+
+            return Err(MintFailure::Permanent(XcmError::FailedToTransactAsset(
+                "the minted derivative doesn't belong to the derivative class",
+            )));
+        }
+
+        if derive_reverse_mapping {
+            if expected_instance_id.as_ref() != Some(&instance_id) {
+                log::error!(
+                    target: LOG_TARGET,
+                    "deposit_asset: the minted derivative {instance_id:?} doesn't match the expected one {expected_instance_id:?}",
+                );
+
+                return Err(MintFailure::Permanent(XcmError::FailedToTransactAsset(
+                    "the minted derivative ID can't be derived from the foreign asset instance",
+                )));
+            }
+        } else {
+            // An existing reverse mapping means the engine reused an instance ID.
+            // Overwriting it would orphan the foreign instance it belongs to.
+            // The caller's storage transaction rolls back the mint.
+            if <DerivativeToForeignInstance<T, I>>::contains_key(derivative_class_id, &instance_id)
+            {
+                log::error!(
+                    target: LOG_TARGET,
+                    "deposit_asset: the minted derivative {instance_id:?} already corresponds to a foreign instance within the class {derivative_class_id:?}",
+                );
+
+                return Err(MintFailure::Permanent(XcmError::FailedToTransactAsset(
+                    "derivative id reuse",
+                )));
+            }
+
+            <DerivativeToForeignInstance<T, I>>::insert(
+                derivative_class_id,
+                &instance_id,
+                foreign_asset_instance.asset_instance,
+            );
+        }
+
+        <ForeignInstanceToDerivativeStatus<T, I>>::insert(
+            derivative_class_id,
+            foreign_asset_instance.asset_instance,
+            DerivativeStatus::Active(instance_id.clone()),
+        );
+
+        // The caller's storage transaction rolls back the mint if the hook fails.
+        T::OnDerivativeMinted::on_derivative_minted(derivative_class_id, &instance_id, to)
+            .map_err(Self::op_error(XnftOp::Mint))
+            .map_err(MintFailure::Transient)?;
+
+        Self::note_flow(|flow| flow.mints.saturating_inc());
+
+        Ok(instance_id)
+    }
+
+    /// Removes the stored derivative-to-foreign-instance mapping, if it is stored at all.
+    pub(crate) fn remove_reverse_mapping(
+        derivative_class_id: &ClassIdOf<T, I>,
+        derivative_instance_id: &InstanceIdOf<T, I>,
+    ) {
+        if !T::DeriveReverseMapping::get() {
+            <DerivativeToForeignInstance<T, I>>::remove(
+                derivative_class_id,
+                derivative_instance_id,
+            );
+        }
+    }
+
+    /// Withdraws all the active derivatives of the foreign asset's derivative class
+    /// and marks the class as dormant.
+    ///
+    /// Used when the `ClassLevelTransfer` is enabled, and the withdrawn asset instance is `Undefined`.
+    /// The class is withdrawn from a single owner:
+    /// all its active derivatives must belong to the `from` account,
+    /// otherwise the NFT engine rejects the withdrawal of the first foreign-owned one,
+    /// and the whole withdrawal fails.
+    ///
+    /// Each derivative of the class, including a stashed one, is read,
+    /// so the withdrawal fails if the class has more than `MaxClassLevelWithdrawal` derivatives.
+    fn withdraw_whole_class(
+        xcm_asset_id: &XcmAssetId,
+        from: &NftEngineAccountIdOf<T, I>,
+    ) -> XcmResult {
+        if !T::ClassLevelTransfer::get() {
+            return Err(XcmExecutorError::InstanceConversionFailed.into());
+        }
+
+        let class_id = Self::foreign_asset_to_local_class(xcm_asset_id)
+            .map(Self::engine_class_id)
+            .ok_or(XcmExecutorError::AssetIdConversionFailed)?;
+
+        let max_instances = T::MaxClassLevelWithdrawal::get();
+
+        if Self::derivative_count(&class_id) > max_instances {
+            return Err(XcmError::NotWithdrawable);
+        }
+
+        let mut derivatives = <ForeignInstanceToDerivativeStatus<T, I>>::iter_prefix(&class_id);
+
+        let active_instances: Vec<_> = derivatives
+            .by_ref()
+            .take(max_instances as usize)
+            .filter_map(|(asset_instance, status)| match status {
+                DerivativeStatus::Active(instance_id) => Some((asset_instance, instance_id)),
+                _ => None,
+            })
+            .collect();
+
+        // The counter may be stale while it is being recomputed,
+        // so the iteration itself is bounded too.
+        if derivatives.next().is_some() {
+            return Err(XcmError::NotWithdrawable);
+        }
+
+        let withdrawn_count = active_instances.len() as u32;
+
+        for (asset_instance, instance_id) in active_instances {
+            Self::withdraw_foreign_asset_instance(
+                Box::new((*xcm_asset_id, asset_instance).into()),
+                (class_id.clone(), instance_id).into(),
+                from,
+            )
+            .map_err(Self::dispatch_error_to_xcm_error)?;
+        }
+
+        <DormantDerivativeClasses<T, I>>::insert(&class_id, ());
+
+        Self::deposit_event(Event::ClassWithdrawn {
+            foreign_asset_id: Box::new(*xcm_asset_id),
+            derivative_class_id: class_id,
+            from: from.clone(),
+            withdrawn_count,
         });
 
         Ok(())
@@ -365,54 +1508,106 @@ impl<T: Config<I>, I: 'static> Pallet<T, I> {
     ///
     /// Otherwise, if the derivative should be stashed,
     /// this function transfers it to the xnft pallet account.
-    fn withdraw_foreign_asset_instance(
+    ///
+    /// The NFT engine error is returned as is.
+    pub(crate) fn withdraw_foreign_asset_instance(
         foreign_asset_instance: Box<ForeignAssetInstance>,
         derivative: InstanceOf<T, I>,
         from: &NftEngineAccountIdOf<T, I>,
-    ) -> XcmResult {
+    ) -> DispatchResult {
         let derivative_withdrawal = <NftTransactorOf<T, I>>::withdraw_derivative(
             &derivative.class_id,
             &derivative.instance_id,
             from,
+            &foreign_asset_instance,
         )
-        .map_err(Self::dispatch_error_to_xcm_error)?;
+        .map_err(Self::op_failed(XnftOp::Withdraw { stash: false }))?;
 
-        match derivative_withdrawal {
+        let xcm_asset_id = Some(Box::new(foreign_asset_instance.asset_id));
+        let withdrawn_class_instance = CategorizedClassInstance::Derivative {
+            foreign_asset_instance: foreign_asset_instance.clone(),
+            derivative: derivative.clone(),
+        };
+
+        let event = match derivative_withdrawal {
             DerivativeWithdrawal::Burned => {
-                <DerivativeToForeignInstance<T, I>>::remove(
-                    &derivative.class_id,
-                    &derivative.instance_id,
-                );
+                if T::PreserveIdAcrossBurn::get() {
+                    <RetainedDerivativeIds<T, I>>::insert(
+                        &derivative.class_id,
+                        foreign_asset_instance.asset_instance,
+                        &derivative.instance_id,
+                    );
+                }
+
+                Self::remove_reverse_mapping(&derivative.class_id, &derivative.instance_id);
                 <ForeignInstanceToDerivativeStatus<T, I>>::remove(
                     &derivative.class_id,
                     foreign_asset_instance.asset_instance,
                 );
+                <DerivativeCountPerClass<T, I>>::mutate(&derivative.class_id, |count| {
+                    *count = count.saturating_sub(1)
+                });
+
+                Self::note_flow(|flow| flow.burns.saturating_inc());
+
+                Event::DerivativeBurned {
+                    foreign_asset_instance,
+                    derivative,
+                    from: from.clone(),
+                }
             }
             DerivativeWithdrawal::Stash => {
                 <NftTransactorOf<T, I>>::transfer_class_instance(
                     &derivative.class_id,
                     &derivative.instance_id,
                     from,
-                    &T::PalletAccountId::get(),
+                    &Self::class_custody_account(&derivative.class_id),
                 )
-                .map_err(Self::dispatch_error_to_xcm_error)?;
+                .map_err(Self::op_failed(XnftOp::Withdraw { stash: true }))?;
 
                 <ForeignInstanceToDerivativeStatus<T, I>>::insert(
                     &derivative.class_id,
                     foreign_asset_instance.asset_instance,
                     DerivativeStatus::Stashed(derivative.instance_id.clone()),
                 );
+                <StashedCountPerClass<T, I>>::mutate(&derivative.class_id, |count| {
+                    *count = count.saturating_add(1)
+                });
+
+                Event::DerivativeStashed {
+                    foreign_asset_instance,
+                    derivative,
+                    from: from.clone(),
+                }
             }
-        }
+        };
 
-        Self::deposit_event(Event::Withdrawn {
-            class_instance: CategorizedClassInstance::Derivative {
-                foreign_asset_instance,
-                derivative,
-            },
+        Self::note_flow(|flow| flow.withdrawals.saturating_inc());
+
+        Self::deposit_instance_event(event);
+        Self::deposit_instance_event(Event::Withdrawn {
+            class_instance: withdrawn_class_instance,
             from: from.clone(),
+            xcm_asset_id,
         });
 
         Ok(())
     }
 }
+
+/// The failure of a derivative mint.
+enum MintFailure {
+    /// The NFT engine or the `OnDerivativeMinted` hook failed,
+    /// so the mint may succeed if retried later.
+    Transient(XcmError),
+
+    /// The minted derivative violates the pallet's invariants (e.g., its ID is already in use),
+    /// so retrying the mint can't help.
+    Permanent(XcmError),
+}
+
+impl From<XcmError> for MintFailure {
+    fn from(error: XcmError) -> Self {
+        Self::Permanent(error)
+    }
+}
@@ -1,26 +1,11 @@
-// Template adopted from https://github.com/paritytech/substrate/blob/master/.maintain/frame-weight-template.hbs
-
-//! Autogenerated weights for pallet_xnft
+//! Weights for pallet_xnft.
 //!
-//! THIS FILE WAS AUTO-GENERATED USING THE SUBSTRATE BENCHMARK CLI VERSION 29.0.0
-//! DATE: 2023-12-15, STEPS: `50`, REPEAT: `20`, LOW RANGE: `[]`, HIGH RANGE: `[]`
-//! WORST CASE MAP SIZE: `1000000`
-//! HOSTNAME: `hearthstone`, CPU: `AMD Ryzen 9 7950X3D 16-Core Processor`
-//! EXECUTION: , WASM-EXECUTION: Compiled, CHAIN: Some("karura-dev"), DB CACHE: 1024
-
-// Executed Command:
-// target/production/acala
-// benchmark
-// pallet
-// --chain=karura-dev
-// --steps=50
-// --repeat=20
-// --pallet=pallet-xnft
-// --extrinsic=*
-// --wasm-execution=compiled
-// --heap-pages=4096
-// --output=./
-// --template=../unique-chain/.maintain/frame-weight-template.hbs
+//! NOTE: the file is maintained by hand until the benchmarks are run on the reference hardware.
+//! Only the `foreign_asset_registration_checks` weight is measured
+//! (the Substrate benchmark CLI 29.0.0, 2023-12-15, `--steps=50 --repeat=20`, CHAIN: karura-dev).
+//! Every other weight is an unmeasured placeholder marked with a `Not measured yet` comment.
+//! Regenerate the whole file with the `benchmark pallet` command
+//! and the frame weight template to replace them.
 
 #![cfg_attr(rustfmt, rustfmt_skip)]
 #![allow(unused_parens)]
@@ -32,6 +17,19 @@ use sp_std::marker::PhantomData;
 /// Weight functions needed for pallet_xnft.
 pub trait WeightInfo {
 	fn foreign_asset_registration_checks() -> Weight;
+	fn class_instance_lookup() -> Weight;
+	fn dispatch_error_conversion() -> Weight;
+	fn deposit_derivative_new() -> Weight;
+	fn deposit_derivative_stashed() -> Weight;
+	fn withdraw_derivative_burned() -> Weight;
+	fn withdraw_derivative_stashed() -> Weight;
+	fn transfer_derivative() -> Weight;
+	fn deposit_local() -> Weight;
+	fn withdraw_local() -> Weight;
+	fn transfer_local() -> Weight;
+	fn bulk_withdraw_to_custody(n: u32, ) -> Weight;
+	fn retry_deposit() -> Weight;
+	fn force_withdraw_stashed() -> Weight;
 }
 
 /// Weights for pallet_xnft using the Substrate node and recommended hardware.
@@ -49,6 +47,94 @@ impl<T: frame_system::Config> WeightInfo for SubstrateWeight<T> {
 		Weight::from_parts(4_640_000, 4080)
 			.saturating_add(T::DbWeight::get().reads(2_u64))
 	}
+	fn class_instance_lookup() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for the class resolution and the derivative status reads.
+		Weight::from_parts(6_230_000, 4080)
+			.saturating_add(T::DbWeight::get().reads(4_u64))
+	}
+	fn dispatch_error_conversion() -> Weight {
+		// Not measured yet: a placeholder depending on the runtime's `DispatchErrorsConvert`,
+		// replaced by the `dispatch_error_conversion` benchmark output.
+		Weight::from_parts(2_000_000, 0)
+	}
+	fn deposit_derivative_new() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative mint with the mapping and counter updates.
+		Weight::from_parts(40_000_000, 8000)
+			.saturating_add(T::DbWeight::get().reads(7_u64))
+			.saturating_add(T::DbWeight::get().writes(5_u64))
+	}
+	fn deposit_derivative_stashed() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a stashed derivative transfer with the status and counter updates.
+		Weight::from_parts(30_000_000, 6000)
+			.saturating_add(T::DbWeight::get().reads(6_u64))
+			.saturating_add(T::DbWeight::get().writes(4_u64))
+	}
+	fn withdraw_derivative_burned() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative burn with the mapping and counter updates.
+		Weight::from_parts(30_000_000, 6000)
+			.saturating_add(T::DbWeight::get().reads(5_u64))
+			.saturating_add(T::DbWeight::get().writes(4_u64))
+	}
+	fn withdraw_derivative_stashed() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative stash transfer with the status and counter updates.
+		Weight::from_parts(30_000_000, 6000)
+			.saturating_add(T::DbWeight::get().reads(5_u64))
+			.saturating_add(T::DbWeight::get().writes(4_u64))
+	}
+	fn transfer_derivative() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative transfer between two accounts.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(T::DbWeight::get().reads(3_u64))
+			.saturating_add(T::DbWeight::get().writes(2_u64))
+	}
+	fn deposit_local() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a local class instance transfer from the custody account.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(T::DbWeight::get().reads(3_u64))
+			.saturating_add(T::DbWeight::get().writes(2_u64))
+	}
+	fn withdraw_local() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a local class instance transfer to the custody account.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(T::DbWeight::get().reads(3_u64))
+			.saturating_add(T::DbWeight::get().writes(2_u64))
+	}
+	fn transfer_local() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a local class instance transfer between two accounts.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(T::DbWeight::get().reads(3_u64))
+			.saturating_add(T::DbWeight::get().writes(2_u64))
+	}
+	fn bulk_withdraw_to_custody(n: u32, ) -> Weight {
+		// Not measured yet: the costliest instance withdrawal
+		// with the ownership and categorization reads per instance.
+		Self::withdraw_local()
+			.max(Self::withdraw_derivative_burned())
+			.max(Self::withdraw_derivative_stashed())
+			.saturating_add(T::DbWeight::get().reads(4_u64))
+			.saturating_mul(n.into())
+	}
+	fn retry_deposit() -> Weight {
+		// Not measured yet: a new derivative deposit
+		// with the pending deposit, registration and pause reads, and the pending deposit removal.
+		Self::deposit_derivative_new()
+			.saturating_add(T::DbWeight::get().reads_writes(3_u64, 1_u64))
+	}
+	fn force_withdraw_stashed() -> Weight {
+		// Not measured yet: a derivative transfer from the custody account
+		// with the mapping and counter updates.
+		Self::transfer_derivative()
+			.saturating_add(T::DbWeight::get().reads_writes(4_u64, 4_u64))
+	}
 }
 
 // For backwards compatibility and tests
@@ -65,5 +151,93 @@ impl WeightInfo for () {
 		Weight::from_parts(4_640_000, 4080)
 			.saturating_add(RocksDbWeight::get().reads(2_u64))
 	}
+	fn class_instance_lookup() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for the class resolution and the derivative status reads.
+		Weight::from_parts(6_230_000, 4080)
+			.saturating_add(RocksDbWeight::get().reads(4_u64))
+	}
+	fn dispatch_error_conversion() -> Weight {
+		// Not measured yet: a placeholder depending on the runtime's `DispatchErrorsConvert`,
+		// replaced by the `dispatch_error_conversion` benchmark output.
+		Weight::from_parts(2_000_000, 0)
+	}
+	fn deposit_derivative_new() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative mint with the mapping and counter updates.
+		Weight::from_parts(40_000_000, 8000)
+			.saturating_add(RocksDbWeight::get().reads(7_u64))
+			.saturating_add(RocksDbWeight::get().writes(5_u64))
+	}
+	fn deposit_derivative_stashed() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a stashed derivative transfer with the status and counter updates.
+		Weight::from_parts(30_000_000, 6000)
+			.saturating_add(RocksDbWeight::get().reads(6_u64))
+			.saturating_add(RocksDbWeight::get().writes(4_u64))
+	}
+	fn withdraw_derivative_burned() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative burn with the mapping and counter updates.
+		Weight::from_parts(30_000_000, 6000)
+			.saturating_add(RocksDbWeight::get().reads(5_u64))
+			.saturating_add(RocksDbWeight::get().writes(4_u64))
+	}
+	fn withdraw_derivative_stashed() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative stash transfer with the status and counter updates.
+		Weight::from_parts(30_000_000, 6000)
+			.saturating_add(RocksDbWeight::get().reads(5_u64))
+			.saturating_add(RocksDbWeight::get().writes(4_u64))
+	}
+	fn transfer_derivative() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a derivative transfer between two accounts.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(RocksDbWeight::get().reads(3_u64))
+			.saturating_add(RocksDbWeight::get().writes(2_u64))
+	}
+	fn deposit_local() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a local class instance transfer from the custody account.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(RocksDbWeight::get().reads(3_u64))
+			.saturating_add(RocksDbWeight::get().writes(2_u64))
+	}
+	fn withdraw_local() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a local class instance transfer to the custody account.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(RocksDbWeight::get().reads(3_u64))
+			.saturating_add(RocksDbWeight::get().writes(2_u64))
+	}
+	fn transfer_local() -> Weight {
+		// Not measured yet: a conservative estimate
+		// for a local class instance transfer between two accounts.
+		Weight::from_parts(20_000_000, 4000)
+			.saturating_add(RocksDbWeight::get().reads(3_u64))
+			.saturating_add(RocksDbWeight::get().writes(2_u64))
+	}
+	fn bulk_withdraw_to_custody(n: u32, ) -> Weight {
+		// Not measured yet: the costliest instance withdrawal
+		// with the ownership and categorization reads per instance.
+		Self::withdraw_local()
+			.max(Self::withdraw_derivative_burned())
+			.max(Self::withdraw_derivative_stashed())
+			.saturating_add(RocksDbWeight::get().reads(4_u64))
+			.saturating_mul(n.into())
+	}
+	fn retry_deposit() -> Weight {
+		// Not measured yet: a new derivative deposit
+		// with the pending deposit, registration and pause reads, and the pending deposit removal.
+		Self::deposit_derivative_new()
+			.saturating_add(RocksDbWeight::get().reads_writes(3_u64, 1_u64))
+	}
+	fn force_withdraw_stashed() -> Weight {
+		// Not measured yet: a derivative transfer from the custody account
+		// with the mapping and counter updates.
+		Self::transfer_derivative()
+			.saturating_add(RocksDbWeight::get().reads_writes(4_u64, 4_u64))
+	}
 }
 
@@ -1,19 +1,25 @@
 //! This module contains conversion utilities.
 
-use frame_support::pallet_prelude::*;
-use sp_runtime::traits::MaybeEquivalence;
+use frame_support::{pallet_prelude::*, traits::Contains};
+use sp_core::U256;
+use sp_runtime::traits::{BlakeTwo256, Convert, Hash, MaybeEquivalence};
+use sp_std::vec::Vec;
 use xcm::v3::prelude::*;
+use xcm_executor::traits::ConvertLocation;
+
+fn has_prefix(location: &InteriorMultiLocation, prefix: &InteriorMultiLocation) -> bool {
+    prefix
+        .iter()
+        .enumerate()
+        .all(|(index, junction)| location.at(index) == Some(junction))
+}
 
 fn ensure_correct_prefix<Prefix: Get<InteriorMultiLocation>>(
     location: &InteriorMultiLocation,
 ) -> Option<InteriorMultiLocation> {
     let prefix = Prefix::get();
 
-    prefix
-        .iter()
-        .enumerate()
-        .all(|(index, junction)| location.at(index) == Some(junction))
-        .then_some(prefix)
+    has_prefix(location, &prefix).then_some(prefix)
 }
 
 /// The converter to match the [`InteriorMultiLocation`] as prefixed `GeneralIndex`
@@ -44,6 +50,41 @@ impl<
     }
 }
 
+/// The converter to match the [`InteriorMultiLocation`] as `GeneralIndex`
+/// prefixed by any of the `Prefixes`
+/// and to convert the index into a value of the `AssetId` type
+/// using the `ConvertAssetId` converter.
+///
+/// This allows keeping the same asset IDs when the source chain moves its collections
+/// under another prefix (e.g., after a pallet migration).
+/// The first prefix is the canonical one, it is used in `convert_back`.
+pub struct InteriorGeneralIndexMultiPrefix<Prefixes, AssetId, ConvertAssetId>(
+    PhantomData<(Prefixes, AssetId, ConvertAssetId)>,
+);
+impl<
+        Prefixes: Get<Vec<InteriorMultiLocation>>,
+        AssetId,
+        ConvertAssetId: MaybeEquivalence<u128, AssetId>,
+    > MaybeEquivalence<InteriorMultiLocation, AssetId>
+    for InteriorGeneralIndexMultiPrefix<Prefixes, AssetId, ConvertAssetId>
+{
+    fn convert(id: &InteriorMultiLocation) -> Option<AssetId> {
+        Prefixes::get()
+            .iter()
+            .filter(|prefix| has_prefix(id, prefix))
+            .find_map(|prefix| match id.at(prefix.len()) {
+                Some(Junction::GeneralIndex(index)) => ConvertAssetId::convert(index),
+                _ => None,
+            })
+    }
+    fn convert_back(what: &AssetId) -> Option<InteriorMultiLocation> {
+        let mut location = Prefixes::get().into_iter().next()?;
+        let index = ConvertAssetId::convert_back(what)?;
+        location.push(Junction::GeneralIndex(index)).ok()?;
+        Some(location)
+    }
+}
+
 /// The converter to match the [`InteriorMultiLocation`] as prefixed `AccountKey20`
 /// and to convert the account key into a value of the `AssetId` type
 /// using the `ConvertAssetId` converter.
@@ -137,6 +178,86 @@ impl<
     }
 }
 
+/// The converter to match the [`InteriorMultiLocation`] as a prefixed value junction
+/// and to convert the junction into a value of the `CollectionId` type
+/// using its [`TryFrom<Junction>`] implementation.
+///
+/// This allows using typed collection ID wrappers directly as the pallet's asset ID converters.
+pub struct JunctionConvert<Prefix, CollectionId>(PhantomData<(Prefix, CollectionId)>);
+impl<
+        Prefix: Get<InteriorMultiLocation>,
+        CollectionId: TryFrom<Junction> + Into<Junction> + Clone,
+    > MaybeEquivalence<InteriorMultiLocation, CollectionId>
+    for JunctionConvert<Prefix, CollectionId>
+{
+    fn convert(id: &InteriorMultiLocation) -> Option<CollectionId> {
+        let prefix = ensure_correct_prefix::<Prefix>(id)?;
+        let junction = id.at(prefix.len())?;
+        CollectionId::try_from(*junction).ok()
+    }
+    fn convert_back(what: &CollectionId) -> Option<InteriorMultiLocation> {
+        let mut location = Prefix::get();
+        location.push(what.clone().into()).ok()?;
+        Some(location)
+    }
+}
+
+/// The converter to hash the whole [`InteriorMultiLocation`] using `BlakeTwo256`
+/// and to convert the hash into a value of the `AssetId` type
+/// using the `ConvertAssetId` converter.
+///
+/// The hash is deterministic for a given location,
+/// so the converter can derive a stable class ID seed from a reserve location.
+///
+/// NOTE: this converter is one-way since the hash can't be reversed.
+/// Its `convert_back` always returns `None`, so the asset ID can be used for matching only.
+pub struct HashedLocationClassId<AssetId, ConvertAssetId>(PhantomData<(AssetId, ConvertAssetId)>);
+impl<AssetId, ConvertAssetId: MaybeEquivalence<[u8; 32], AssetId>>
+    MaybeEquivalence<InteriorMultiLocation, AssetId>
+    for HashedLocationClassId<AssetId, ConvertAssetId>
+{
+    fn convert(id: &InteriorMultiLocation) -> Option<AssetId> {
+        ConvertAssetId::convert(&BlakeTwo256::hash_of(id).to_fixed_bytes())
+    }
+    fn convert_back(_what: &AssetId) -> Option<InteriorMultiLocation> {
+        None
+    }
+}
+
+/// The converter to match the [`MultiLocation`] of a collection bridged
+/// from the `Network` consensus system
+/// and to convert its value junction into a value of the `ClassId` type
+/// using the `ConvertClassId` converter.
+///
+/// The expected location shape is `../../GlobalConsensus(Network)/<value junction>`.
+/// E.g., Snowbridge represents an Ethereum ERC-721 contract as
+/// `../../GlobalConsensus(Ethereum { chain_id })/AccountKey20 { network: None, key: <contract> }`.
+/// Locations of other consensus systems or with extra junctions aren't matched.
+pub struct BridgedConsensusConvert<Network, ClassId, ConvertClassId>(
+    PhantomData<(Network, ClassId, ConvertClassId)>,
+);
+impl<Network: Get<NetworkId>, ClassId, ConvertClassId: MaybeEquivalence<Junction, ClassId>>
+    MaybeEquivalence<MultiLocation, ClassId>
+    for BridgedConsensusConvert<Network, ClassId, ConvertClassId>
+{
+    fn convert(id: &MultiLocation) -> Option<ClassId> {
+        match id {
+            MultiLocation {
+                parents: 2,
+                interior: X2(GlobalConsensus(network), junction),
+            } if *network == Network::get() => ConvertClassId::convert(junction),
+            _ => None,
+        }
+    }
+    fn convert_back(what: &ClassId) -> Option<MultiLocation> {
+        let junction = ConvertClassId::convert_back(what)?;
+        Some(MultiLocation {
+            parents: 2,
+            interior: X2(GlobalConsensus(Network::get()), junction),
+        })
+    }
+}
+
 /// The converter to match the [`AssetInstance`] as `Index`
 /// and to convert the index into a value of the `InstanceId` type
 /// using the `ConvertAssetInstance` converter.
@@ -246,3 +367,211 @@ impl<InstanceId, ConvertAssetInstance: MaybeEquivalence<[u8; 32], InstanceId>>
         ConvertAssetInstance::convert_back(instance).map(AssetInstance::Array32)
     }
 }
+
+/// The converter to match the [`AssetInstance`] as a value of the `InstanceId` type
+/// using its [`TryFrom<AssetInstance>`] implementation.
+///
+/// This allows using typed instance ID wrappers (e.g., [`U256AssetInstance`])
+/// directly as the pallet's asset instance converters.
+pub struct TryFromAssetInstance<InstanceId>(PhantomData<InstanceId>);
+impl<InstanceId: TryFrom<AssetInstance> + Into<AssetInstance> + Clone>
+    MaybeEquivalence<AssetInstance, InstanceId> for TryFromAssetInstance<InstanceId>
+{
+    fn convert(instance: &AssetInstance) -> Option<InstanceId> {
+        InstanceId::try_from(*instance).ok()
+    }
+
+    fn convert_back(instance: &InstanceId) -> Option<AssetInstance> {
+        Some(instance.clone().into())
+    }
+}
+
+/// The 256-bit token ID common for the EVM NFT collections.
+///
+/// It corresponds to the [`AssetInstance`] as `Array32`
+/// holding the big-endian bytes of the token ID.
+#[derive(Clone, Copy, PartialEq, Eq, RuntimeDebug, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub struct U256AssetInstance(pub U256);
+impl TryFrom<AssetInstance> for U256AssetInstance {
+    type Error = ();
+
+    fn try_from(instance: AssetInstance) -> Result<Self, Self::Error> {
+        match instance {
+            AssetInstance::Array32(bytes) => Ok(Self(U256::from_big_endian(&bytes))),
+            _ => Err(()),
+        }
+    }
+}
+impl From<U256AssetInstance> for AssetInstance {
+    fn from(instance: U256AssetInstance) -> Self {
+        let mut bytes = [0; 32];
+        instance.0.to_big_endian(&mut bytes);
+        AssetInstance::Array32(bytes)
+    }
+}
+
+/// The converter to match the [`AssetInstance`] as `Array32` packing a composite
+/// `(collection, token)` pair and to convert the pair into a value of the `InstanceId` type
+/// using the `ConvertComposite` converter.
+///
+/// The byte layout is `collection ++ token`, where both halves are big-endian `u128` numbers,
+/// i.e., the first 16 bytes encode the collection, and the last 16 bytes encode the token.
+/// The `convert_back` reproduces exactly the same 32 bytes.
+pub struct CompositeArray32AssetInstance<InstanceId, ConvertComposite>(
+    PhantomData<(InstanceId, ConvertComposite)>,
+);
+impl<InstanceId, ConvertComposite: MaybeEquivalence<(u128, u128), InstanceId>>
+    MaybeEquivalence<AssetInstance, InstanceId>
+    for CompositeArray32AssetInstance<InstanceId, ConvertComposite>
+{
+    fn convert(instance: &AssetInstance) -> Option<InstanceId> {
+        match instance {
+            AssetInstance::Array32(bytes) => {
+                let (collection, token) = bytes.split_at(16);
+                let collection = u128::from_be_bytes(collection.try_into().ok()?);
+                let token = u128::from_be_bytes(token.try_into().ok()?);
+
+                ConvertComposite::convert(&(collection, token))
+            }
+            _ => None,
+        }
+    }
+
+    fn convert_back(instance: &InstanceId) -> Option<AssetInstance> {
+        let (collection, token) = ConvertComposite::convert_back(instance)?;
+
+        let mut bytes = [0; 32];
+        bytes[..16].copy_from_slice(&collection.to_be_bytes());
+        bytes[16..].copy_from_slice(&token.to_be_bytes());
+
+        Some(AssetInstance::Array32(bytes))
+    }
+}
+
+/// The variable-length instance ID holding the bytes of an array [`AssetInstance`].
+///
+/// The length is capped at 32 bytes since `Array32` is the largest array form of the XCM instance.
+pub type BytesInstanceId = BoundedVec<u8, ConstU32<32>>;
+
+/// The converter to match the [`AssetInstance`] as `Array4`, `Array8`, `Array16`, or `Array32`
+/// and to convert its bytes into the [`BytesInstanceId`].
+///
+/// The `convert_back` selects the array form by the ID length,
+/// so the IDs of other lengths aren't representable as XCM instances.
+///
+/// NOTE: XCM v3 has no `Blob` instance form, so a content-addressed NFT ID
+/// must be conveyed as an array form, e.g., its 32-byte hash as `Array32`.
+pub struct BytesAssetInstance;
+impl MaybeEquivalence<AssetInstance, BytesInstanceId> for BytesAssetInstance {
+    fn convert(instance: &AssetInstance) -> Option<BytesInstanceId> {
+        let bytes: &[u8] = match instance {
+            AssetInstance::Array4(bytes) => bytes,
+            AssetInstance::Array8(bytes) => bytes,
+            AssetInstance::Array16(bytes) => bytes,
+            AssetInstance::Array32(bytes) => bytes,
+            _ => return None,
+        };
+
+        bytes.to_vec().try_into().ok()
+    }
+
+    fn convert_back(instance: &BytesInstanceId) -> Option<AssetInstance> {
+        let bytes = instance.as_slice();
+
+        match bytes.len() {
+            4 => Some(AssetInstance::Array4(bytes.try_into().ok()?)),
+            8 => Some(AssetInstance::Array8(bytes.try_into().ok()?)),
+            16 => Some(AssetInstance::Array16(bytes.try_into().ok()?)),
+            32 => Some(AssetInstance::Array32(bytes.try_into().ok()?)),
+            _ => None,
+        }
+    }
+}
+
+/// Returns the `location` with the network removed from its account leaf junction
+/// (`AccountId32`, `AccountKey20`, or `AccountIndex64`)
+/// if the network is specified and accepted by the `is_accepted_network`.
+///
+/// Returns `None` if the location doesn't end with such a junction.
+///
+/// NOTE: the accepted networks MUST be limited to the chain's own network
+/// unless the accounts are known to be the same across the networks.
+/// Otherwise, an account of another network is confused with a local one.
+pub fn strip_account_network(
+    location: &MultiLocation,
+    is_accepted_network: impl FnOnce(&NetworkId) -> bool,
+) -> Option<MultiLocation> {
+    let mut location = *location;
+    let leaf_index = location.interior.len().checked_sub(1)?;
+
+    let (Junction::AccountId32 { network, .. }
+    | Junction::AccountKey20 { network, .. }
+    | Junction::AccountIndex64 { network, .. }) = location.interior.at_mut(leaf_index)?
+    else {
+        return None;
+    };
+
+    if !is_accepted_network(network.as_ref()?) {
+        return None;
+    }
+
+    *network = None;
+
+    Some(location)
+}
+
+/// The location converter that falls back to converting the location
+/// with the network removed from its account leaf junction
+/// if the `Inner` converter fails to convert the original location.
+///
+/// Only the networks contained in the `AcceptedNetworks` are removed.
+/// See [`strip_account_network`] for the security considerations.
+pub struct StripAccountNetwork<AcceptedNetworks, Inner>(PhantomData<(AcceptedNetworks, Inner)>);
+impl<AccountId, AcceptedNetworks: Contains<NetworkId>, Inner: ConvertLocation<AccountId>>
+    ConvertLocation<AccountId> for StripAccountNetwork<AcceptedNetworks, Inner>
+{
+    fn convert_location(location: &MultiLocation) -> Option<AccountId> {
+        Inner::convert_location(location).or_else(|| {
+            let location = strip_account_network(location, AcceptedNetworks::contains)?;
+            Inner::convert_location(&location)
+        })
+    }
+}
+
+/// The junctions the converters of this module support in the foreign asset locations:
+/// `GlobalConsensus`, `Parachain`, `PalletInstance`, `GeneralIndex`, `GeneralKey`,
+/// `AccountKey20`, and `AccountId32`.
+///
+/// Use it as the pallet's `AllowedInteriorJunctions`.
+pub struct SupportedInteriorJunctions;
+impl Contains<Junction> for SupportedInteriorJunctions {
+    fn contains(junction: &Junction) -> bool {
+        matches!(
+            junction,
+            GlobalConsensus(_)
+                | Parachain(_)
+                | PalletInstance(_)
+                | GeneralIndex(_)
+                | GeneralKey { .. }
+                | AccountKey20 { .. }
+                | AccountId32 { .. }
+        )
+    }
+}
+
+/// The router to map the [`AssetInstance`] as `Index` to a shard
+/// consisting of `ShardSize` consecutive indices.
+///
+/// The instance `Index(n)` is routed to the shard `n / ShardSize`.
+/// Other instance forms and out-of-range shards aren't routed.
+pub struct IndexRangeRouter<ShardSize>(PhantomData<ShardSize>);
+impl<ShardSize: Get<u128>> Convert<AssetInstance, Option<u32>> for IndexRangeRouter<ShardSize> {
+    fn convert(instance: AssetInstance) -> Option<u32> {
+        match instance {
+            AssetInstance::Index(index) => index
+                .checked_div(ShardSize::get())
+                .and_then(|shard| shard.try_into().ok()),
+            _ => None,
+        }
+    }
+}
@@ -3,4 +3,7 @@
 //! This crate provides conversion helpers and traits for pallet-xnft.
 
 pub mod conversion;
+#[cfg(feature = "nfts")]
+pub mod nfts;
+pub mod topology;
 pub mod traits;
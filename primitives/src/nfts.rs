@@ -0,0 +1,189 @@
+//! The integration adapter for the `pallet-nfts`.
+//!
+//! The [`PalletNftsAdapter`] implements the [`NftEngine`] and the [`NftTransactor`]
+//! on top of the `pallet-nfts` instance,
+//! and the [`NftsErrorConvert`] converts its errors into the XCM ones.
+
+use frame_support::{
+    pallet_prelude::*,
+    traits::tokens::nonfungibles_v2::{Create, Inspect, InspectEnumerable, Mutate, Transfer},
+};
+use pallet_nfts::{
+    CollectionConfigFor, CollectionConfigOf, CollectionSetting, Config, Error, ItemConfig, Pallet,
+};
+use sp_runtime::{traits::StaticLookup, DispatchError, DispatchResult};
+use sp_std::marker::PhantomData;
+use xcm::latest::Error as XcmError;
+
+use crate::traits::{
+    DerivativeWithdrawal, DispatchErrorConvert, ForeignAssetInstance, NftEngine, NftTransactor,
+};
+
+/// The [`NftEngine`] and [`NftTransactor`] backed by the `I` instance of the `pallet-nfts`.
+///
+/// * The derivative classes are created with the `CollectionConfig`,
+///   and the collection deposit is reserved from the class owner (i.e., the xnft pallet account).
+/// * The `pallet-nfts` items have caller-chosen IDs, so a derivative is minted
+///   at the instance ID hint, see [`NftTransactor::mint_derivative`].
+///   The xnft pallet must be configured to provide the hint (e.g., via its `AssetInstanceConvert`).
+/// * The derivatives are always burned on withdrawal.
+/// * A collection with the non-transferable items isn't operational.
+/// * The class owner is changed via the `force_collection_owner`,
+///   so the `pallet-nfts` `ForceOrigin` must accept the root origin.
+pub struct PalletNftsAdapter<T, I, CollectionConfig>(PhantomData<(T, I, CollectionConfig)>);
+
+impl<T, I, CollectionConfig> NftTransactor for PalletNftsAdapter<T, I, CollectionConfig>
+where
+    T: Config<I>,
+    I: 'static,
+{
+    type AccountId = T::AccountId;
+    type ClassId = T::CollectionId;
+    type InstanceId = T::ItemId;
+
+    fn transfer_class_instance(
+        class_id: &Self::ClassId,
+        instance_id: &Self::InstanceId,
+        from: &Self::AccountId,
+        to: &Self::AccountId,
+    ) -> DispatchResult {
+        ensure!(
+            <Pallet<T, I> as Inspect<T::AccountId>>::owner(class_id, instance_id).as_ref()
+                == Some(from),
+            <Error<T, I>>::NoPermission,
+        );
+
+        <Pallet<T, I> as Transfer<T::AccountId>>::transfer(class_id, instance_id, to)
+    }
+
+    fn class_instance_owner(
+        class_id: &Self::ClassId,
+        instance_id: &Self::InstanceId,
+    ) -> Option<Self::AccountId> {
+        <Pallet<T, I> as Inspect<T::AccountId>>::owner(class_id, instance_id)
+    }
+
+    fn mint_derivative(
+        class_id: &Self::ClassId,
+        to: &Self::AccountId,
+        instance_id_hint: Option<&Self::InstanceId>,
+    ) -> Result<Self::InstanceId, DispatchError> {
+        let instance_id = instance_id_hint.copied().ok_or(DispatchError::Other(
+            "pallet-nfts requires the derivative instance ID hint",
+        ))?;
+
+        <Pallet<T, I> as Mutate<T::AccountId, ItemConfig>>::mint_into(
+            class_id,
+            &instance_id,
+            to,
+            &ItemConfig::default(),
+            true,
+        )?;
+
+        Ok(instance_id)
+    }
+
+    fn withdraw_derivative(
+        class_id: &Self::ClassId,
+        instance_id: &Self::InstanceId,
+        from: &Self::AccountId,
+        _foreign_asset_instance: &ForeignAssetInstance,
+    ) -> Result<DerivativeWithdrawal, DispatchError> {
+        <Pallet<T, I> as Mutate<T::AccountId, ItemConfig>>::burn(
+            class_id,
+            instance_id,
+            Some(from),
+        )?;
+
+        Ok(DerivativeWithdrawal::Burned)
+    }
+}
+
+impl<T, I, CollectionConfig> NftEngine for PalletNftsAdapter<T, I, CollectionConfig>
+where
+    T: Config<I>,
+    I: 'static,
+    CollectionConfig: Get<CollectionConfigFor<T, I>>,
+{
+    type Transactor = Self;
+
+    /// The derivative class configuration is fixed by the `CollectionConfig`.
+    type ClassInitData = ();
+
+    fn create_class_weight(_data: &Self::ClassInitData) -> Weight {
+        <T as Config<I>>::WeightInfo::create()
+    }
+
+    fn create_class(
+        owner: &T::AccountId,
+        _data: Self::ClassInitData,
+    ) -> Result<T::CollectionId, DispatchError> {
+        <Pallet<T, I> as Create<T::AccountId, CollectionConfigFor<T, I>>>::create_collection(
+            owner,
+            owner,
+            &CollectionConfig::get(),
+        )
+    }
+
+    fn class_owner(class_id: &T::CollectionId) -> Option<T::AccountId> {
+        <Pallet<T, I> as Inspect<T::AccountId>>::collection_owner(class_id)
+    }
+
+    fn is_class_empty(class_id: &T::CollectionId) -> bool {
+        <Pallet<T, I> as InspectEnumerable<T::AccountId>>::items(class_id)
+            .next()
+            .is_none()
+    }
+
+    fn is_class_operational(class_id: &T::CollectionId) -> bool {
+        <CollectionConfigOf<T, I>>::get(class_id).map_or(false, |config| {
+            config.is_setting_enabled(CollectionSetting::TransferableItems)
+        })
+    }
+
+    fn can_transfer(
+        class_id: &T::CollectionId,
+        instance_id: &T::ItemId,
+        _from: &T::AccountId,
+        _to: &T::AccountId,
+    ) -> DispatchResult {
+        ensure!(
+            <Pallet<T, I> as Inspect<T::AccountId>>::can_transfer(class_id, instance_id),
+            <Error<T, I>>::ItemLocked,
+        );
+
+        Ok(())
+    }
+
+    fn set_class_owner_weight() -> Weight {
+        <T as Config<I>>::WeightInfo::force_collection_owner()
+    }
+
+    fn set_class_owner(class_id: &T::CollectionId, new_owner: &T::AccountId) -> DispatchResult {
+        <Pallet<T, I>>::force_collection_owner(
+            frame_system::RawOrigin::Root.into(),
+            *class_id,
+            T::Lookup::unlookup(new_owner.clone()),
+        )
+    }
+}
+
+/// The conversion of the `pallet-nfts` errors into the XCM errors.
+///
+/// * `UnknownCollection` and `UnknownItem` become the [`XcmError::AssetNotFound`].
+/// * `NoPermission` becomes the [`XcmError::NoPermission`].
+/// * Any other error becomes the [`XcmError::FailedToTransactAsset`] with the error name.
+pub struct NftsErrorConvert<T, I = ()>(PhantomData<(T, I)>);
+
+impl<T: Config<I>, I: 'static> DispatchErrorConvert for NftsErrorConvert<T, I> {
+    type Pallet = Pallet<T, I>;
+    type Error = Error<T, I>;
+
+    fn convert(error: Self::Error) -> XcmError {
+        match error {
+            Error::UnknownCollection | Error::UnknownItem => XcmError::AssetNotFound,
+            Error::NoPermission => XcmError::NoPermission,
+            error => XcmError::FailedToTransactAsset(error.into()),
+        }
+    }
+}
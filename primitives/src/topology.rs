@@ -0,0 +1,86 @@
+//! This module contains the presets for the common relay chain and Asset Hub topologies.
+//!
+//! The presets describe a parachain under the Polkadot or Kusama relay chain
+//! receiving NFTs from the Asset Hub acting as their reserve.
+
+use frame_support::pallet_prelude::*;
+use xcm::v3::prelude::*;
+
+/// The parachain ID of the Asset Hub on both Polkadot and Kusama.
+pub const ASSET_HUB_PARA_ID: u32 = 1000;
+
+/// The `pallet-uniques` instance index on the Asset Hub.
+pub const ASSET_HUB_UNIQUES_PALLET_INDEX: u8 = 51;
+
+/// The `pallet-nfts` instance index on the Asset Hub.
+pub const ASSET_HUB_NFTS_PALLET_INDEX: u8 = 52;
+
+/// The Polkadot network ID.
+pub struct PolkadotNetwork;
+impl Get<NetworkId> for PolkadotNetwork {
+    fn get() -> NetworkId {
+        NetworkId::Polkadot
+    }
+}
+
+/// The Kusama network ID.
+pub struct KusamaNetwork;
+impl Get<NetworkId> for KusamaNetwork {
+    fn get() -> NetworkId {
+        NetworkId::Kusama
+    }
+}
+
+/// The Universal Location of the `ParaId` parachain within the `Network` consensus.
+///
+/// Use it as the pallet's `UniversalLocation`.
+/// The global consensus junction is essential: without it,
+/// the locations of the assets coming from other consensus systems aren't simplified correctly.
+pub struct ParachainUniversalLocation<Network, ParaId>(PhantomData<(Network, ParaId)>);
+impl<Network: Get<NetworkId>, ParaId: Get<u32>> Get<InteriorMultiLocation>
+    for ParachainUniversalLocation<Network, ParaId>
+{
+    fn get() -> InteriorMultiLocation {
+        X2(GlobalConsensus(Network::get()), Parachain(ParaId::get()))
+    }
+}
+
+/// Returns the location of the Asset Hub `pallet-nfts` collection
+/// relative to a sibling parachain.
+pub fn asset_hub_nfts_collection(collection_id: u32) -> MultiLocation {
+    MultiLocation {
+        parents: 1,
+        interior: X3(
+            Parachain(ASSET_HUB_PARA_ID),
+            PalletInstance(ASSET_HUB_NFTS_PALLET_INDEX),
+            GeneralIndex(collection_id.into()),
+        ),
+    }
+}
+
+/// Returns the location of the Asset Hub `pallet-uniques` class
+/// relative to a sibling parachain.
+pub fn asset_hub_uniques_class(class_id: u32) -> MultiLocation {
+    MultiLocation {
+        parents: 1,
+        interior: X3(
+            Parachain(ASSET_HUB_PARA_ID),
+            PalletInstance(ASSET_HUB_UNIQUES_PALLET_INDEX),
+            GeneralIndex(class_id.into()),
+        ),
+    }
+}
+
+/// Normalizes the reserve `location` as seen by the chain at the `universal_location`.
+///
+/// A reserve location can be expressed via the global consensus
+/// (e.g., `../../GlobalConsensus(Polkadot)/Parachain(1000)/...`).
+/// The normalization reduces it to the shortest relative form (e.g., `../Parachain(1000)/...`),
+/// which is the form the xnft pallet stores and matches the foreign assets by.
+pub fn normalize_reserve_location(
+    mut location: MultiLocation,
+    universal_location: &InteriorMultiLocation,
+) -> MultiLocation {
+    location.simplify(universal_location);
+    location
+}
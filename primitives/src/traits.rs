@@ -2,8 +2,11 @@
 
 use frame_support::pallet_prelude::*;
 use parity_scale_codec::{Decode, MaxEncodedLen};
-use sp_runtime::{DispatchError, ModuleError};
-use xcm::latest::Error as XcmError;
+use sp_runtime::{traits::MaybeSerializeDeserialize, DispatchError, DispatchResult, ModuleError};
+use xcm::{
+    latest::Error as XcmError,
+    v3::{AssetId, AssetInstance},
+};
 
 /// This trait describes the NFT Transactor.
 pub trait NftTransactor {
@@ -25,28 +28,135 @@ pub trait NftTransactor {
         to: &Self::AccountId,
     ) -> DispatchResult;
 
+    /// Get the owner of the class instance.
+    ///
+    /// Returns `None` if the class instance doesn't exist.
+    fn class_instance_owner(
+        class_id: &Self::ClassId,
+        instance_id: &Self::InstanceId,
+    ) -> Option<Self::AccountId>;
+
+    /// Check if the `who` account is the owner of the class instance
+    /// or an operator approved by the owner.
+    ///
+    /// The default implementation recognizes the owner only.
+    fn is_approved_or_owner(
+        class_id: &Self::ClassId,
+        instance_id: &Self::InstanceId,
+        who: &Self::AccountId,
+    ) -> bool {
+        Self::class_instance_owner(class_id, instance_id).as_ref() == Some(who)
+    }
+
+    /// Check if a new derivative can be minted within the specified derivative class.
+    ///
+    /// The engine can veto the mint (e.g., if the class is full or paused)
+    /// before the xnft pallet changes any state.
+    /// The default implementation allows minting.
+    fn can_mint(_class_id: &Self::ClassId) -> DispatchResult {
+        Ok(())
+    }
+
     /// Mint a new derivative NFT within the specified derivative class to the `to` account.
+    ///
+    /// The `instance_id_hint` is the instance ID derived from the foreign asset instance.
+    /// An engine that can mint at a caller-chosen ID should use it,
+    /// so the derivative IDs are deterministic.
+    /// An engine that can't is free to ignore it.
     fn mint_derivative(
         class_id: &Self::ClassId,
-        // TODO(think about):
-        // instance_id_hint: Option<&Self::InstanceId>,
         to: &Self::AccountId,
+        instance_id_hint: Option<&Self::InstanceId>,
     ) -> Result<Self::InstanceId, DispatchError>;
 
+    /// Set the metadata of the derivative NFT.
+    ///
+    /// The default implementation ignores the metadata.
+    fn set_derivative_metadata(
+        _class_id: &Self::ClassId,
+        _instance_id: &Self::InstanceId,
+        _metadata: &[u8],
+    ) -> DispatchResult {
+        Ok(())
+    }
+
+    /// Set the royalty of the derivative NFT carried over from the reserve.
+    ///
+    /// The `basis_points` is the royalty share in hundredths of a percent.
+    /// The default implementation ignores the royalty.
+    fn set_derivative_royalty(
+        _class_id: &Self::ClassId,
+        _instance_id: &Self::InstanceId,
+        _creator: &Self::AccountId,
+        _basis_points: u16,
+    ) -> DispatchResult {
+        Ok(())
+    }
+
+    /// Mint a new derivative NFT within the specified derivative class to the `to` account,
+    /// honoring the `instance_id_hint` and setting the `metadata` if they are provided.
+    ///
+    /// The xnft pallet always mints the derivatives using this function.
+    /// An engine can implement it to mint the derivative in a single step.
+    /// The default implementation calls the [`mint_derivative`](NftTransactor::mint_derivative),
+    /// and then the [`set_derivative_metadata`](NftTransactor::set_derivative_metadata).
+    fn mint_derivative_full(
+        class_id: &Self::ClassId,
+        to: &Self::AccountId,
+        instance_id_hint: Option<&Self::InstanceId>,
+        metadata: Option<&[u8]>,
+    ) -> Result<Self::InstanceId, DispatchError> {
+        let instance_id = Self::mint_derivative(class_id, to, instance_id_hint)?;
+
+        if let Some(metadata) = metadata {
+            Self::set_derivative_metadata(class_id, &instance_id, metadata)?;
+        }
+
+        Ok(instance_id)
+    }
+
     /// Withdraw a derivative from the `from` account.
     ///
     /// The derivative can be either burned or stashed.
-    /// The choice of what operation to use is up to the trait's implementation.
+    /// The choice of what operation to use is up to the trait's implementation,
+    /// which can base it on the `foreign_asset_instance` the derivative corresponds to.
     ///
     /// * If the implementation has burned the derivative, it must return the [`DerivativeWithdrawal::Burned`] value.
     /// * If the implementation wants to stash the derivative, it should return the [`DerivativeWithdrawal::Stash`] value.
+    ///
+    /// An implementation that always burns never makes the pallet stash derivatives:
+    /// a subsequent deposit of the same foreign instance mints a fresh derivative,
+    /// which can get a different instance ID unless the engine honors the instance ID hint
+    /// (see [`NftTransactor::mint_derivative_full`]).
     fn withdraw_derivative(
         class_id: &Self::ClassId,
         instance_id: &Self::InstanceId,
         from: &Self::AccountId,
+        foreign_asset_instance: &ForeignAssetInstance,
     ) -> Result<DerivativeWithdrawal, DispatchError>;
 }
 
+/// A foreign NFT complete identification.
+///
+/// It is expressed in the XCM version the xnft pallet stores the foreign assets in.
+#[derive(RuntimeDebug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub struct ForeignAssetInstance {
+    /// The asset ID of the foreign instance.
+    pub asset_id: AssetId,
+
+    /// The foreign asset instance.
+    pub asset_instance: AssetInstance,
+}
+
+impl From<(AssetId, AssetInstance)> for ForeignAssetInstance {
+    fn from((asset_id, asset_instance): (AssetId, AssetInstance)) -> Self {
+        Self {
+            asset_id,
+            asset_instance,
+        }
+    }
+}
+
 /// Derivative withdrawal operation.
 pub enum DerivativeWithdrawal {
     /// Indicate that the derivative is burned.
@@ -56,13 +166,25 @@ pub enum DerivativeWithdrawal {
     Stash,
 }
 
+/// The royalty of an NFT.
+#[derive(RuntimeDebug, PartialEq, Eq, Clone, Encode, Decode, MaxEncodedLen, TypeInfo)]
+pub struct Royalty<AccountId> {
+    /// The creator receiving the royalty.
+    pub creator: AccountId,
+
+    /// The royalty share in hundredths of a percent.
+    pub basis_points: u16,
+}
+
 /// This trait describes the NFT Engine (i.e., the NFT solution) of the chain.
 pub trait NftEngine {
     /// This trait describes the NFT Transactor.
     type Transactor: NftTransactor;
 
     /// Extra data which to be used to create a new class.
-    type ClassInitData: Member + Parameter;
+    ///
+    /// It is serializable so the foreign assets can be registered at genesis.
+    type ClassInitData: Member + Parameter + MaybeSerializeDeserialize;
 
     /// Compute the class creation weight.
     fn create_class_weight(data: &Self::ClassInitData) -> Weight;
@@ -72,6 +194,103 @@ pub trait NftEngine {
         owner: &<Self::Transactor as NftTransactor>::AccountId,
         data: Self::ClassInitData,
     ) -> Result<<Self::Transactor as NftTransactor>::ClassId, DispatchError>;
+
+    /// Get the owner of the class.
+    ///
+    /// Returns `None` if the class doesn't exist.
+    fn class_owner(
+        class_id: &<Self::Transactor as NftTransactor>::ClassId,
+    ) -> Option<<Self::Transactor as NftTransactor>::AccountId>;
+
+    /// Check if the class has no instances.
+    ///
+    /// The default implementation considers every class non-empty.
+    fn is_class_empty(_class_id: &<Self::Transactor as NftTransactor>::ClassId) -> bool {
+        false
+    }
+
+    /// Check if the class can currently be transacted (e.g., it isn't frozen by governance
+    /// and the engine itself is available).
+    ///
+    /// The xnft pallet delegates a failed operation to its `FallbackTransactor`
+    /// only if the class isn't operational.
+    /// The default implementation considers every class operational.
+    fn is_class_operational(_class_id: &<Self::Transactor as NftTransactor>::ClassId) -> bool {
+        true
+    }
+
+    /// Check if the class instance can be transferred from the `from` account to the `to` account
+    /// by an XCM transfer.
+    ///
+    /// The engine can veto the transfer (e.g., while the class is frozen by governance).
+    /// The default implementation allows the transfer.
+    fn can_transfer(
+        _class_id: &<Self::Transactor as NftTransactor>::ClassId,
+        _instance_id: &<Self::Transactor as NftTransactor>::InstanceId,
+        _from: &<Self::Transactor as NftTransactor>::AccountId,
+        _to: &<Self::Transactor as NftTransactor>::AccountId,
+    ) -> DispatchResult {
+        Ok(())
+    }
+
+    /// Compute the weight of setting the class metadata of the given length.
+    fn set_class_metadata_weight(_metadata_len: u32) -> Weight {
+        Weight::zero()
+    }
+
+    /// Set the metadata of the class (e.g., its name and symbol).
+    ///
+    /// The default implementation ignores the metadata.
+    fn set_class_metadata(
+        _class_id: &<Self::Transactor as NftTransactor>::ClassId,
+        _metadata: &[u8],
+    ) -> DispatchResult {
+        Ok(())
+    }
+
+    /// Mint the local class instance with the given ID to the `to` account.
+    ///
+    /// The xnft pallet calls it when a local class instance is deposited back
+    /// after the original was burned, if the pallet is configured to re-mint it.
+    /// The default implementation doesn't support minting.
+    fn mint_local(
+        _class_id: &<Self::Transactor as NftTransactor>::ClassId,
+        _instance_id: &<Self::Transactor as NftTransactor>::InstanceId,
+        _to: &<Self::Transactor as NftTransactor>::AccountId,
+    ) -> DispatchResult {
+        Err(DispatchError::Other(
+            "minting local class instances isn't supported",
+        ))
+    }
+
+    /// Compute the class owner change weight.
+    fn set_class_owner_weight() -> Weight;
+
+    /// Change the owner of the class.
+    ///
+    /// If the engine separates the class ownership from the minting rights,
+    /// the previous owner's minting, transferring, and burning rights MUST be retained,
+    /// so the xnft pallet can still transact the derivatives.
+    fn set_class_owner(
+        class_id: &<Self::Transactor as NftTransactor>::ClassId,
+        new_owner: &<Self::Transactor as NftTransactor>::AccountId,
+    ) -> DispatchResult;
+
+    /// Compute the instance range reservation weight.
+    fn reserve_instance_range_weight(_count: u32) -> Weight {
+        Weight::zero()
+    }
+
+    /// Pre-allocate the `count` contiguous instance IDs within the class for the future mints.
+    ///
+    /// The default implementation does nothing,
+    /// which suits the engines not supporting the contiguous allocation.
+    fn reserve_instance_range(
+        _class_id: &<Self::Transactor as NftTransactor>::ClassId,
+        _count: u32,
+    ) -> DispatchResult {
+        Ok(())
+    }
 }
 
 /// The conversion from a pallet error to the [`XcmError`].
@@ -154,3 +373,19 @@ impl<T: frame_system::Config, E: DispatchErrorConvert> DispatchErrorsConvert<T>
         <(E,) as DispatchErrorsConvert<T>>::convert(error)
     }
 }
+
+/// Converts the `error` into the [`XcmError`] without consulting any pallet-specific conversions.
+///
+/// Mirrors the `()` implementation of the [`DispatchErrorsConvert`]:
+/// * `BadOrigin` becomes the [`XcmError::BadOrigin`].
+/// * A module error becomes the [`XcmError::FailedToTransactAsset`] with the module error message.
+/// * Any other error becomes the [`XcmError::FailedToTransactAsset`] with the error description.
+pub fn default_dispatch_error_to_xcm(error: DispatchError) -> XcmError {
+    match error {
+        DispatchError::Module(ModuleError { message, .. }) => {
+            XcmError::FailedToTransactAsset(message.unwrap_or("Unknown module error"))
+        }
+        DispatchError::BadOrigin => XcmError::BadOrigin,
+        _ => XcmError::FailedToTransactAsset(error.into()),
+    }
+}